@@ -0,0 +1,416 @@
+use anyhow::{bail, Context, Result};
+use lettre::{message::Mailbox, Message, SmtpTransport, Transport};
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    instructions::Transfer,
+    metrics::metrics,
+    mints::{mint_decimals, parse_token_amount},
+    sinks::{transfer_json, TransferSink},
+    USDC_MINT_ADDRESS,
+};
+
+/// Webhooks and SMTP sessions taking longer than this fail
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where an alert is sent
+#[derive(Clone, Debug, PartialEq)]
+pub enum Channel {
+    /// Slack incoming webhook, posted `{"text": ...}`
+    Slack { url: String },
+    /// Discord webhook, posted `{"content": ...}`
+    Discord { url: String },
+    /// Plain SMTP relay without authentication, e.g. "localhost:25"
+    Email {
+        relay: String,
+        from: String,
+        to: Vec<String>,
+    },
+    /// Local program run with the alert as JSON on stdin
+    Command { program: String, args: Vec<String> },
+}
+
+impl Channel {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Channel::Slack { .. } => "slack",
+            Channel::Discord { .. } => "discord",
+            Channel::Email { .. } => "email",
+            Channel::Command { .. } => "command",
+        }
+    }
+
+    fn from_json(json: &Value) -> Result<Self> {
+        let kind = json["type"].as_str().context("expected channel type")?;
+        Ok(match kind {
+            "slack" => Channel::Slack {
+                url: string(json, "url")?,
+            },
+            "discord" => Channel::Discord {
+                url: string(json, "url")?,
+            },
+            "email" => Channel::Email {
+                relay: string(json, "relay")?,
+                from: string(json, "from")?,
+                to: strings(json, "to")?,
+            },
+            "command" => {
+                let mut command = strings(json, "command")?.into_iter();
+                Channel::Command {
+                    program: command.next().context("expected a program in command")?,
+                    args: command.collect(),
+                }
+            }
+            _ => bail!("unknown channel type {kind}"),
+        })
+    }
+}
+
+/// Alerts on transfers of at least `min_amount` of `mint`, optionally only those sent or received by some owners
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertRule {
+    pub name: String,
+    pub mint: String,
+    /// Raw amount in the smallest unit of the mint, e.g. 1000000000000 for 1M USDC
+    pub min_amount: u64,
+    /// Owners the transfer must be sent or received by, any owner when `None`
+    pub addresses: Option<HashSet<String>>,
+    /// No further alert for this long after the rule fires
+    pub cooldown: Duration,
+    pub channels: Vec<Channel>,
+}
+
+impl AlertRule {
+    pub fn matches(&self, transfer: &Transfer) -> bool {
+        transfer.mint == self.mint
+            && transfer.amount >= self.min_amount
+            && self.addresses.as_ref().is_none_or(|addresses| {
+                addresses.contains(&transfer.source_owner)
+                    || addresses.contains(&transfer.destination_owner)
+            })
+    }
+}
+
+/// A transfer that matched a rule
+#[derive(Clone, Debug)]
+pub struct Alert {
+    pub rule: String,
+    pub slot: u64,
+    pub transfer: Transfer,
+    pub channels: Vec<Channel>,
+}
+
+impl Alert {
+    /// e.g. "Whale alert whales: TX detected: Alice sent 1,500,000 USDC to Bob in slot 250684537 (signature ...)"
+    pub fn message(&self) -> String {
+        format!(
+            "Whale alert {}: {} in slot {} (signature {})",
+            self.rule, self.transfer, self.slot, self.transfer.signature
+        )
+    }
+
+    /// The transfer as written by `JsonlSink` with the rule and message added
+    pub fn to_json(&self) -> Value {
+        let mut json = transfer_json(self.slot, &self.transfer);
+        json["type"] = "alert".into();
+        json["rule"] = self.rule.clone().into();
+        json["message"] = self.message().into();
+        json
+    }
+}
+
+/// Alert rules and when each last fired
+pub struct AlertRules {
+    rules: Vec<AlertRule>,
+    last_fired: Vec<Option<Instant>>,
+}
+
+impl AlertRules {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        AlertRules {
+            last_fired: vec![None; rules.len()],
+            rules,
+        }
+    }
+
+    /// Read rules from a JSON file, see the README for the format
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let json: Value = serde_json::from_str(&json)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        Self::from_json(&json).with_context(|| format!("invalid alert rules in {}", path.display()))
+    }
+
+    /// Rules from `{"tags": {"tag": ["owner", ...]}, "rules": [...]}`, rule `tags` add the owners of each tag to its `addresses`
+    pub fn from_json(json: &Value) -> Result<Self> {
+        let mut tags = HashMap::new();
+        if let Some(tag_map) = json["tags"].as_object() {
+            for tag in tag_map.keys() {
+                tags.insert(tag.clone(), strings(&json["tags"], tag)?);
+            }
+        }
+        let rules = json["rules"]
+            .as_array()
+            .context("expected a rules array")?
+            .iter()
+            .map(|rule| {
+                let name = string(rule, "name")?;
+                parse_rule(rule, name.clone(), &tags).with_context(|| format!("in rule {name}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(rules))
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    /// Alerts for every rule `transfer` matches that is not cooling down, starting their cooldowns
+    pub fn check(&mut self, transfer: &Transfer, slot: u64, now: Instant) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for (rule, last_fired) in self.rules.iter().zip(&mut self.last_fired) {
+            if !rule.matches(transfer) {
+                continue;
+            }
+            if let Some(last_fired) = last_fired {
+                if now.duration_since(*last_fired) < rule.cooldown {
+                    debug!("rule {} cooling down, not alerting", rule.name);
+                    continue;
+                }
+            }
+            *last_fired = Some(now);
+            alerts.push(Alert {
+                rule: rule.name.clone(),
+                slot,
+                transfer: transfer.clone(),
+                channels: rule.channels.clone(),
+            });
+        }
+        alerts
+    }
+}
+
+fn parse_rule(
+    rule: &Value,
+    name: String,
+    tags: &HashMap<String, Vec<String>>,
+) -> Result<AlertRule> {
+    let mut addresses = match rule.get("addresses") {
+        Some(_) => Some(
+            strings(rule, "addresses")?
+                .into_iter()
+                .collect::<HashSet<_>>(),
+        ),
+        None => None,
+    };
+    if rule.get("tags").is_some() {
+        let addresses = addresses.get_or_insert_with(HashSet::new);
+        for tag in strings(rule, "tags")? {
+            let owners = tags
+                .get(&tag)
+                .with_context(|| format!("unknown tag {tag}"))?;
+            addresses.extend(owners.iter().cloned());
+        }
+    }
+    let mint = match rule.get("mint") {
+        Some(_) => string(rule, "mint")?,
+        None => USDC_MINT_ADDRESS.to_string(),
+    };
+    let decimals = match rule.get("decimals") {
+        Some(decimals) => decimals
+            .as_u64()
+            .and_then(|decimals| u8::try_from(decimals).ok())
+            .context("expected decimals to be a small integer")?,
+        None => {
+            mint_decimals(&mint).with_context(|| format!("expected decimals for mint {mint}"))?
+        }
+    };
+    // Scaled once here so transfers are compared exactly in raw units
+    let min_amount = match &rule["minAmount"] {
+        Value::Number(amount) => amount.to_string(),
+        Value::String(amount) => amount.clone(),
+        _ => bail!("expected minAmount"),
+    };
+    Ok(AlertRule {
+        name,
+        mint,
+        min_amount: parse_token_amount(&min_amount, decimals)?,
+        addresses,
+        cooldown: Duration::from_secs(rule["cooldownSecs"].as_u64().unwrap_or(0)),
+        channels: rule["channels"]
+            .as_array()
+            .context("expected a channels array")?
+            .iter()
+            .map(Channel::from_json)
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn string(json: &Value, key: &str) -> Result<String> {
+    json[key]
+        .as_str()
+        .map(ToString::to_string)
+        .with_context(|| format!("expected {key} to be a string"))
+}
+
+fn strings(json: &Value, key: &str) -> Result<Vec<String>> {
+    json[key]
+        .as_array()
+        .and_then(|values| {
+            values
+                .iter()
+                .map(|value| value.as_str().map(ToString::to_string))
+                .collect()
+        })
+        .with_context(|| format!("expected {key} to be an array of strings"))
+}
+
+/// HTTP client for webhooks, built once and reused for every delivery
+pub fn webhook_client() -> Result<Client> {
+    Ok(Client::builder().timeout(DELIVERY_TIMEOUT).build()?)
+}
+
+/// Send an alert to one channel, blocking until it is delivered
+pub fn deliver(client: &Client, channel: &Channel, alert: &Alert) -> Result<()> {
+    match channel {
+        Channel::Slack { url } => post_webhook(client, url, &json!({ "text": alert.message() })),
+        Channel::Discord { url } => {
+            post_webhook(client, url, &json!({ "content": alert.message() }))
+        }
+        Channel::Email { relay, from, to } => send_email(relay, from, to, alert),
+        Channel::Command { program, args } => run_command(program, args, alert),
+    }
+}
+
+fn post_webhook(client: &Client, url: &str, payload: &Value) -> Result<()> {
+    client
+        .post(url)
+        .json(payload)
+        .send()
+        .context("failed to post webhook")?
+        .error_for_status()?;
+    Ok(())
+}
+
+fn send_email(relay: &str, from: &str, to: &[String], alert: &Alert) -> Result<()> {
+    let (host, port) = match relay.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().context("invalid SMTP relay port")?),
+        None => (relay, 25),
+    };
+    let mut message = Message::builder()
+        .from(from.parse::<Mailbox>().context("invalid from address")?)
+        .subject(format!("Whale alert {}", alert.rule));
+    for to in to {
+        message = message.to(to
+            .parse::<Mailbox>()
+            .with_context(|| format!("invalid address {to}"))?);
+    }
+    let message = message.body(alert.message())?;
+    SmtpTransport::builder_dangerous(host)
+        .port(port)
+        .timeout(Some(DELIVERY_TIMEOUT))
+        .build()
+        .send(&message)
+        .with_context(|| format!("failed to send email through {relay}"))?;
+    Ok(())
+}
+
+fn run_command(program: &str, args: &[String], alert: &Alert) -> Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run {program}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "{}", alert.to_json())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{program} exited with {status}");
+    }
+    Ok(())
+}
+
+/// Checks every transfer against the alert rules and delivers alerts on a background thread, so slow channels do not hold up blocks
+///
+/// Failed deliveries are logged and counted in `alert_failures_total`. Dropping the sink waits for pending alerts to be delivered.
+pub struct AlertSink {
+    rules: AlertRules,
+    slot: u64,
+    sender: Option<Sender<Alert>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl AlertSink {
+    pub fn new(rules: AlertRules) -> Result<Self> {
+        let client = webhook_client()?;
+        let (sender, receiver) = mpsc::channel::<Alert>();
+        let worker = thread::spawn(move || {
+            for alert in receiver {
+                for channel in &alert.channels {
+                    if let Err(e) = deliver(&client, channel, &alert) {
+                        warn!(
+                            "failed to deliver alert {} to {}: {e:#}",
+                            alert.rule,
+                            channel.kind()
+                        );
+                        metrics()
+                            .alert_failures
+                            .with_label_values(&[channel.kind()])
+                            .inc();
+                    }
+                }
+            }
+        });
+        Ok(AlertSink {
+            rules,
+            slot: 0,
+            sender: Some(sender),
+            worker: Some(worker),
+        })
+    }
+}
+
+impl TransferSink for AlertSink {
+    fn on_block_start(&mut self, slot: u64, _block_time: Option<i64>) -> Result<()> {
+        self.slot = slot;
+        Ok(())
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        for alert in self.rules.check(transfer, self.slot, Instant::now()) {
+            info!("{}", alert.message());
+            metrics().alerts.with_label_values(&[&alert.rule]).inc();
+            if let Some(sender) = &self.sender {
+                sender
+                    .send(alert)
+                    .context("alert delivery thread stopped")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AlertSink {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                warn!("alert delivery thread panicked");
+            }
+        }
+    }
+}
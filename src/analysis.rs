@@ -0,0 +1,137 @@
+use anyhow::Result;
+
+use crate::{
+    config::Config,
+    flows::FlowSummary,
+    instructions::Transfer,
+    patterns::{Pattern, PatternDetector},
+    reconcile::Discrepancy,
+    screening::{Screener, ScreeningHit},
+    sinks::TransferSink,
+    swaps::Swap,
+};
+
+/// Screening and pattern detection over every transfer, as configured by `Config::denylist_path` and `Config::patterns`
+///
+/// Shared by the `Monitor` and `AnalysisSink`, so every way of processing blocks reports the same hits and patterns.
+pub struct Analysis {
+    screener: Option<Screener>,
+    patterns: Option<PatternDetector>,
+    slot: u64,
+}
+
+impl Analysis {
+    /// Loads the denylist if one is configured
+    pub fn new(config: &Config) -> Result<Self> {
+        let screener = config
+            .denylist_path
+            .as_ref()
+            .map(|path| Screener::new(path, config.screening_audit_path.as_deref()))
+            .transpose()?;
+        Ok(Analysis {
+            screener,
+            patterns: config.patterns.clone().map(PatternDetector::new),
+            slot: 0,
+        })
+    }
+
+    /// Reload the denylist if its file changed
+    pub fn start_block(&mut self, slot: u64) -> Result<()> {
+        self.slot = slot;
+        if let Some(screener) = &mut self.screener {
+            screener.reload_if_changed()?;
+        }
+        Ok(())
+    }
+
+    /// The screening hit of a transfer of the current block, and the patterns the transfers of the previous transaction complete
+    pub fn observe(&mut self, transfer: &Transfer) -> Result<(Option<ScreeningHit>, Vec<Pattern>)> {
+        let hit = match &mut self.screener {
+            Some(screener) => screener.screen(self.slot, transfer)?,
+            None => None,
+        };
+        let patterns = match &mut self.patterns {
+            Some(detector) => detector.observe(self.slot, transfer),
+            None => Vec::new(),
+        };
+        Ok((hit, patterns))
+    }
+
+    /// Leave the legs of a swap out of pattern detection, called right after the transfers of its transaction
+    pub fn observe_swap(&mut self, swap: &Swap) {
+        if let Some(detector) = &mut self.patterns {
+            detector.exclude_swap(swap);
+        }
+    }
+
+    /// The patterns completed by the last transaction of the block
+    pub fn end_block(&mut self) -> Vec<Pattern> {
+        match &mut self.patterns {
+            Some(detector) => detector.finish(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Sends the screening hit of each transfer to the inner sink right after the transfer, and patterns once the transaction completing them is over
+pub struct AnalysisSink<K: TransferSink> {
+    inner: K,
+    analysis: Analysis,
+}
+
+impl<K: TransferSink> AnalysisSink<K> {
+    pub fn new(inner: K, analysis: Analysis) -> Self {
+        AnalysisSink { inner, analysis }
+    }
+}
+
+impl<K: TransferSink> TransferSink for AnalysisSink<K> {
+    fn on_block_start(&mut self, slot: u64, block_time: Option<i64>) -> Result<()> {
+        self.analysis.start_block(slot)?;
+        self.inner.on_block_start(slot, block_time)
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        self.inner.on_transfer(transfer)?;
+        let (hit, patterns) = self.analysis.observe(transfer)?;
+        if let Some(hit) = hit {
+            self.inner.on_screening_hit(&hit)?;
+        }
+        for pattern in &patterns {
+            self.inner.on_pattern(pattern)?;
+        }
+        Ok(())
+    }
+
+    fn on_swap(&mut self, swap: &Swap) -> Result<()> {
+        self.analysis.observe_swap(swap);
+        self.inner.on_swap(swap)
+    }
+
+    fn on_discrepancy(&mut self, discrepancy: &Discrepancy) -> Result<()> {
+        self.inner.on_discrepancy(discrepancy)
+    }
+
+    fn on_flow_summary(&mut self, summary: &FlowSummary) -> Result<()> {
+        self.inner.on_flow_summary(summary)
+    }
+
+    fn on_pattern(&mut self, pattern: &Pattern) -> Result<()> {
+        self.inner.on_pattern(pattern)
+    }
+
+    fn on_screening_hit(&mut self, hit: &ScreeningHit) -> Result<()> {
+        self.inner.on_screening_hit(hit)
+    }
+
+    fn on_block_end(&mut self, slot: u64) -> Result<()> {
+        for pattern in &self.analysis.end_block() {
+            self.inner.on_pattern(pattern)?;
+        }
+        self.inner.on_block_end(slot)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
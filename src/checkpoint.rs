@@ -0,0 +1,27 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::{fs, io, path::Path};
+
+/// Last slot processed before the monitor stopped, `None` if there is no checkpoint yet
+pub fn read_checkpoint(path: &Path) -> Result<Option<u64>> {
+    let checkpoint = match fs::read_to_string(path) {
+        Ok(checkpoint) => checkpoint,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let checkpoint: Value = serde_json::from_str(&checkpoint)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    let slot = checkpoint["lastSlot"]
+        .as_u64()
+        .with_context(|| format!("expected lastSlot in {}", path.display()))?;
+    Ok(Some(slot))
+}
+
+/// Replace the checkpoint through a rename, so it is never left half written
+pub fn write_checkpoint(path: &Path, slot: u64) -> Result<()> {
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, json!({ "lastSlot": slot }).to_string())
+        .with_context(|| format!("failed to write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
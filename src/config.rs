@@ -0,0 +1,116 @@
+use solana_transaction_status::UiTransactionEncoding;
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    dedup::DedupConfig, flows::DEFAULT_CAPACITY, labels::Labels, patterns::PatternConfig,
+    retry::RetryPolicy, rpc_pool::RpcEndpoint, USDC_MINT_ADDRESS,
+};
+
+/// Options controlling how blocks are fetched and which events are written
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// JSON-RPC endpoint blocks are polled from
+    pub rpc_url: String,
+    /// Endpoints blocks are polled from with failover and weighted round-robin, replacing `rpc_url` when not empty
+    pub rpc_endpoints: Vec<RpcEndpoint>,
+    /// Requests taking longer than this fail over to the next endpoint
+    pub rpc_timeout: Duration,
+    /// Backoff for requests that failed on every endpoint
+    pub retry: RetryPolicy,
+    /// Fetch every block from two endpoints and log an error when their transfers differ
+    pub quorum: bool,
+    /// Slot to start polling from, the RPC's current slot when `None`
+    pub start_slot: Option<u64>,
+    /// Mints whose transfers are written, USDC by default
+    pub mints: Vec<String>,
+    /// Stop after writing this many blocks, poll forever when `None`
+    pub max_blocks: Option<usize>,
+    /// Persist the last processed slot to this file when stopping and resume after it when `start_slot` is `None`
+    pub checkpoint_path: Option<PathBuf>,
+    /// Save every fetched block to this directory so it can be replayed
+    pub record_dir: Option<PathBuf>,
+    /// Also append every event as a JSON line to this file
+    pub jsonl_path: Option<PathBuf>,
+    /// Also insert every transfer, swap and discrepancy into this SQLite database, see `sqlite::SqliteSink`
+    pub sqlite_path: Option<PathBuf>,
+    /// Alert rules file, see `alerts::AlertRules::from_json` for the format
+    pub alerts_path: Option<PathBuf>,
+    /// Write the net flows over the last 1m, 1h and 24h this often, see `flows::FlowSink`
+    pub flow_summary_interval: Option<Duration>,
+    /// Addresses tracked per flow window
+    pub flow_capacity: usize,
+    /// Emit each transfer, swap and discrepancy once per signature and instruction path, see `dedup::Deduplicator`
+    pub dedup: Option<DedupConfig>,
+    /// Write a pattern event for round trips, cycles and ping-pong between owners, see `patterns::PatternDetector`
+    pub patterns: Option<PatternConfig>,
+    /// Screen the owners and token accounts of every transfer against this CSV of addresses, see `screening::Denylist::from_csv`
+    pub denylist_path: Option<PathBuf>,
+    /// Append denylist hits and reloads to this file
+    pub screening_audit_path: Option<PathBuf>,
+    /// Compare decoded transfers with pre/post token balances and write a discrepancy for every mismatch
+    pub reconcile: bool,
+    /// Encoding of the transactions in requested blocks
+    pub encoding: UiTransactionEncoding,
+    /// Fetch address lookup tables on-chain for v0 transactions whose meta is missing `loadedAddresses`
+    pub resolve_lookup_tables: bool,
+    /// Names of owners and token accounts shown instead of their addresses, the bundled labels by default
+    pub labels: Labels,
+    /// Append the programs that invoked each transfer, e.g. "via Jupiter v6 > Raydium AMM v4"
+    pub show_programs: bool,
+    /// Write a swap event for every trader exchanging USDC for another mint, or another mint for USDC
+    pub detect_swaps: bool,
+    /// Only write the swap event, not the transfers it is made of
+    pub hide_swap_legs: bool,
+    /// Serve Prometheus metrics and health checks on this address, e.g. "127.0.0.1:9100", which also polls the RPC's slot to report lag
+    pub metrics_addr: Option<String>,
+    /// Poll the RPC's slot to report lag without serving metrics, e.g. for the dashboard
+    pub track_slot_lag: bool,
+    /// `/healthz` fails when no block has been processed for this long
+    pub max_block_age: Duration,
+    /// `/readyz` fails while the last fetched block is more than this many slots behind the RPC
+    pub max_slot_lag: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            rpc_url: "https://api.mainnet-beta.solana.com".to_string(),
+            rpc_endpoints: Vec::new(),
+            rpc_timeout: Duration::from_secs(30),
+            retry: RetryPolicy::default(),
+            quorum: false,
+            start_slot: None,
+            mints: vec![USDC_MINT_ADDRESS.to_string()],
+            max_blocks: None,
+            checkpoint_path: None,
+            record_dir: None,
+            jsonl_path: None,
+            sqlite_path: None,
+            alerts_path: None,
+            flow_summary_interval: None,
+            flow_capacity: DEFAULT_CAPACITY,
+            dedup: None,
+            patterns: None,
+            denylist_path: None,
+            screening_audit_path: None,
+            reconcile: false,
+            encoding: UiTransactionEncoding::JsonParsed,
+            resolve_lookup_tables: false,
+            labels: Labels::bundled(),
+            show_programs: false,
+            detect_swaps: false,
+            hide_swap_legs: false,
+            metrics_addr: None,
+            track_slot_lag: false,
+            max_block_age: Duration::from_secs(60),
+            // About a minute of slots
+            max_slot_lag: 150,
+        }
+    }
+}
+
+impl Config {
+    pub fn tracks_mint(&self, mint: &str) -> bool {
+        self.mints.iter().any(|tracked| tracked == mint)
+    }
+}
@@ -0,0 +1,433 @@
+use anyhow::{bail, Result};
+use ratatui::{
+    crossterm::event::{self, Event as TerminalEvent, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, List, Paragraph, Row, Sparkline, Table},
+    DefaultTerminal, Frame,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    instructions::Transfer,
+    metrics::metrics,
+    mints::{format_token_amount, mint_label},
+    monitor::{Event, Monitor},
+    shutdown::Shutdown,
+};
+
+/// Transfers kept in the feed, newest first
+const FEED_LENGTH: usize = 500;
+/// Period the rate-limit status looks back over, the same as the RPC rate limit period
+const RATE_LIMIT_PERIOD: Duration = Duration::from_secs(10);
+/// Minimum amounts cycled through with the `m` key, in whole tokens
+const MIN_AMOUNTS: [f64; 5] = [0.0, 1_000.0, 10_000.0, 100_000.0, 1_000_000.0];
+const TOP_LENGTH: usize = 10;
+
+/// Restricts the transfer feed, the aggregates always include every transfer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeedFilter {
+    /// Case-insensitive substring of the sender, receiver or mint
+    pub text: String,
+    /// In whole tokens
+    pub min_amount: f64,
+}
+
+impl FeedFilter {
+    pub fn matches(&self, transfer: &Transfer) -> bool {
+        if ui_amount(transfer) < self.min_amount {
+            return false;
+        }
+        let text = self.text.to_lowercase();
+        text.is_empty()
+            || [
+                transfer.source_owner.as_str(),
+                transfer.destination_owner.as_str(),
+                transfer.source_name(),
+                transfer.destination_name(),
+                mint_label(&transfer.mint),
+            ]
+            .iter()
+            .any(|field| field.to_lowercase().contains(&text))
+    }
+}
+
+/// RPC counters sampled from `metrics::metrics`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RpcStatus {
+    pub slot_lag: i64,
+    pub requests: u64,
+    pub errors: u64,
+    /// Total seconds waited to stay under the rate limit
+    pub rate_limit_wait: f64,
+}
+
+impl RpcStatus {
+    pub fn from_metrics() -> Self {
+        let metrics = metrics();
+        let (requests, errors) = metrics.rpc_totals();
+        RpcStatus {
+            slot_lag: metrics.slot_lag.get(),
+            requests,
+            errors,
+            rate_limit_wait: metrics.rate_limit_wait_seconds.get(),
+        }
+    }
+}
+
+/// State of the dashboard, updated from monitor events and independent of the terminal
+pub struct Dashboard {
+    /// Length of the top senders and receivers and volume windows
+    window: Duration,
+    feed: VecDeque<Transfer>,
+    /// Transfers within the window with the time they were received
+    recent: VecDeque<(SystemTime, Transfer)>,
+    pub filter: FeedFilter,
+    slot: Option<u64>,
+    blocks: u64,
+    status: RpcStatus,
+    /// Rate-limit wait totals over the last `RATE_LIMIT_PERIOD`
+    rate_limit_samples: VecDeque<(SystemTime, f64)>,
+    /// Why the monitor stopped, e.g. an error or the end of a replay
+    stopped: Option<String>,
+}
+
+impl Dashboard {
+    pub fn new(window: Duration) -> Self {
+        Dashboard {
+            window,
+            feed: VecDeque::new(),
+            recent: VecDeque::new(),
+            filter: FeedFilter::default(),
+            slot: None,
+            blocks: 0,
+            status: RpcStatus::default(),
+            rate_limit_samples: VecDeque::new(),
+            stopped: None,
+        }
+    }
+
+    pub fn on_event(&mut self, event: &Event, now: SystemTime) {
+        match event {
+            Event::Transfer(transfer) => {
+                self.feed.push_front(transfer.clone());
+                self.feed.truncate(FEED_LENGTH);
+                self.recent.push_back((now, transfer.clone()));
+            }
+            Event::BlockEnd { slot } => {
+                self.slot = Some(*slot);
+                self.blocks += 1;
+            }
+            Event::BlockStart { .. }
+            | Event::Swap(_)
+            | Event::Discrepancy(_)
+            | Event::Pattern(_)
+            | Event::ScreeningHit(_) => {}
+        }
+        self.evict(now);
+    }
+
+    pub fn on_status(&mut self, status: RpcStatus, now: SystemTime) {
+        self.status = status;
+        self.rate_limit_samples
+            .push_back((now, status.rate_limit_wait));
+        while let Some((time, _)) = self.rate_limit_samples.front() {
+            if elapsed(*time, now) <= RATE_LIMIT_PERIOD {
+                break;
+            }
+            self.rate_limit_samples.pop_front();
+        }
+        self.evict(now);
+    }
+
+    pub fn on_stopped(&mut self, reason: impl Into<String>) {
+        self.stopped = Some(reason.into());
+    }
+
+    /// Feed transfers matching the filter, newest first
+    pub fn feed(&self) -> impl Iterator<Item = &Transfer> {
+        self.feed
+            .iter()
+            .filter(|transfer| self.filter.matches(transfer))
+    }
+
+    /// Owners that sent the most within the window, in whole tokens, by label when they have one
+    pub fn top_senders(&self, count: usize) -> Vec<(String, f64)> {
+        self.top(count, Transfer::source_name)
+    }
+
+    /// Owners that received the most within the window, in whole tokens, by label when they have one
+    pub fn top_receivers(&self, count: usize) -> Vec<(String, f64)> {
+        self.top(count, Transfer::destination_name)
+    }
+
+    fn top(&self, count: usize, owner: impl Fn(&Transfer) -> &str) -> Vec<(String, f64)> {
+        let mut totals = HashMap::<&str, f64>::new();
+        for (_, transfer) in &self.recent {
+            *totals.entry(owner(transfer)).or_default() += ui_amount(transfer);
+        }
+        let mut totals = totals
+            .into_iter()
+            .map(|(owner, total)| (owner.to_string(), total))
+            .collect::<Vec<_>>();
+        totals.sort_by(|(a_owner, a), (b_owner, b)| b.total_cmp(a).then(a_owner.cmp(b_owner)));
+        totals.truncate(count);
+        totals
+    }
+
+    /// Volume of every minute in the window in whole tokens, oldest first, the last minute is the current one
+    pub fn volume_per_minute(&self, now: SystemTime) -> Vec<u64> {
+        let minutes = self.window.as_secs().div_ceil(60).max(1);
+        let current = minute(now);
+        let mut volume = vec![0.0; minutes as usize];
+        for (time, transfer) in &self.recent {
+            let age = current.saturating_sub(minute(*time));
+            if age < minutes {
+                volume[(minutes - 1 - age) as usize] += ui_amount(transfer);
+            }
+        }
+        volume.into_iter().map(|volume| volume as u64).collect()
+    }
+
+    /// Seconds waited to stay under the rate limit over the last 10 seconds
+    pub fn recent_rate_limit_wait(&self) -> f64 {
+        match (
+            self.rate_limit_samples.front(),
+            self.rate_limit_samples.back(),
+        ) {
+            (Some((_, first)), Some((_, last))) => last - first,
+            _ => 0.0,
+        }
+    }
+
+    fn evict(&mut self, now: SystemTime) {
+        while let Some((time, _)) = self.recent.front() {
+            if elapsed(*time, now) <= self.window {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+
+    /// Render the dashboard into the whole frame
+    pub fn draw(&self, frame: &mut Frame, now: SystemTime) {
+        let [status_area, volume_area, top_area, feed_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(6),
+            Constraint::Length(TOP_LENGTH as u16 + 3),
+            Constraint::Min(3),
+        ])
+        .areas(frame.area());
+
+        let slot = match self.slot {
+            Some(slot) => slot.to_string(),
+            None => "-".to_string(),
+        };
+        let rate_limit = match self.recent_rate_limit_wait() {
+            wait if wait > 0.0 => format!("throttled {wait:.1}s in the last 10s").yellow(),
+            _ => "ok".green(),
+        };
+        let mut status = Line::from(vec![
+            format!("Slot {slot}").bold(),
+            format!(
+                " | lag {} slots | {} blocks | RPC {} requests, {} errors | rate limit ",
+                self.status.slot_lag, self.blocks, self.status.requests, self.status.errors
+            )
+            .into(),
+            rate_limit,
+        ]);
+        if let Some(stopped) = &self.stopped {
+            status.push_span(format!(" | stopped: {stopped}").red());
+        }
+        frame.render_widget(
+            Paragraph::new(status).block(Block::bordered().title("Status")),
+            status_area,
+        );
+
+        let volume = self.volume_per_minute(now);
+        let current = volume.last().copied().unwrap_or_default();
+        frame.render_widget(
+            Sparkline::default()
+                .data(&volume)
+                .style(Style::new().cyan())
+                .block(Block::bordered().title(format!(
+                    "Volume per minute, last {} min, {} this minute",
+                    volume.len(),
+                    format_whole(current as f64)
+                ))),
+            volume_area,
+        );
+
+        let [senders_area, receivers_area] =
+            Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).areas(top_area);
+        let minutes = self.window.as_secs().div_ceil(60);
+        frame.render_widget(
+            top_table(
+                self.top_senders(TOP_LENGTH),
+                format!("Top senders, last {minutes} min"),
+            ),
+            senders_area,
+        );
+        frame.render_widget(
+            top_table(
+                self.top_receivers(TOP_LENGTH),
+                format!("Top receivers, last {minutes} min"),
+            ),
+            receivers_area,
+        );
+
+        let mut title = "Transfers".to_string();
+        if !self.filter.text.is_empty() {
+            title.push_str(&format!(" matching \"{}\"", self.filter.text));
+        }
+        if self.filter.min_amount > 0.0 {
+            title.push_str(&format!(
+                " of at least {}",
+                format_whole(self.filter.min_amount)
+            ));
+        }
+        title.push_str(" | / filter, m minimum amount, c clear, q quit");
+        let feed = self
+            .feed()
+            .take(feed_area.height as usize)
+            .map(ToString::to_string)
+            .collect::<List>();
+        frame.render_widget(feed.block(Block::bordered().title(title)), feed_area);
+    }
+}
+
+fn top_table(totals: Vec<(String, f64)>, title: String) -> Table<'static> {
+    let rows = totals
+        .into_iter()
+        .map(|(owner, total)| Row::new([owner, format_whole(total)]));
+    Table::new(rows, [Constraint::Fill(1), Constraint::Length(16)])
+        .block(Block::bordered().title(title))
+}
+
+fn ui_amount(transfer: &Transfer) -> f64 {
+    transfer.amount as f64 / 10f64.powi(transfer.decimals.into())
+}
+
+fn format_whole(amount: f64) -> String {
+    format_token_amount(amount.round() as u64, 0).unwrap_or_else(|_| amount.to_string())
+}
+
+fn minute(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 60
+}
+
+fn elapsed(earlier: SystemTime, now: SystemTime) -> Duration {
+    now.duration_since(earlier).unwrap_or_default()
+}
+
+/// Run `monitor` on a background thread and show its events in the terminal until `q`, Esc, Ctrl-C or a signal
+///
+/// Quitting requests `shutdown`, so the monitor stops after the current block and writes its checkpoint. `on_event` is called on the monitor thread, e.g. to also write JSON lines.
+pub fn run(
+    monitor: Monitor,
+    shutdown: Shutdown,
+    window: Duration,
+    mut on_event: impl FnMut(&Event) -> Result<()> + Send + 'static,
+) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let worker = thread::spawn(move || {
+        for event in monitor {
+            let event = event.and_then(|event| on_event(&event).map(|_| event));
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut terminal = ratatui::init();
+    let result = run_terminal(&mut terminal, &receiver, window, &shutdown);
+    ratatui::restore();
+    shutdown.request();
+    drop(receiver);
+    if worker.join().is_err() {
+        bail!("monitor thread panicked");
+    }
+    result
+}
+
+fn run_terminal(
+    terminal: &mut DefaultTerminal,
+    receiver: &Receiver<Result<Event>>,
+    window: Duration,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let mut dashboard = Dashboard::new(window);
+    let mut editing = false;
+    loop {
+        // SIGTERM stops the monitor and the dashboard with it
+        if shutdown.is_requested() {
+            return Ok(());
+        }
+        let now = SystemTime::now();
+        loop {
+            match receiver.try_recv() {
+                Ok(Ok(event)) => dashboard.on_event(&event, now),
+                Ok(Err(e)) => dashboard.on_stopped(format!("{e:#}")),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if dashboard.stopped.is_none() {
+                        dashboard.on_stopped("no more blocks");
+                    }
+                    break;
+                }
+            }
+        }
+        dashboard.on_status(RpcStatus::from_metrics(), now);
+        terminal.draw(|frame| dashboard.draw(frame, now))?;
+
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        let TerminalEvent::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        // Raw mode turns Ctrl-C into a key press instead of SIGINT
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Ok(());
+        }
+        if editing {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => editing = false,
+                KeyCode::Backspace => {
+                    dashboard.filter.text.pop();
+                }
+                KeyCode::Char(c) => dashboard.filter.text.push(c),
+                _ => {}
+            }
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('/') => {
+                editing = true;
+                dashboard.filter.text.clear();
+            }
+            KeyCode::Char('m') => {
+                let next = MIN_AMOUNTS
+                    .iter()
+                    .position(|amount| *amount > dashboard.filter.min_amount)
+                    .unwrap_or(0);
+                dashboard.filter.min_amount = MIN_AMOUNTS[next];
+            }
+            KeyCode::Char('c') => dashboard.filter = FeedFilter::default(),
+            _ => {}
+        }
+    }
+}
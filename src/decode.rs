@@ -0,0 +1,311 @@
+use anyhow::{bail, Context, Result};
+use solana_sdk::bs58;
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedTransaction, UiCompiledInstruction,
+    UiInnerInstructions, UiInstruction, UiLoadedAddresses, UiMessage, UiParsedInstruction,
+};
+
+use crate::{lookup_tables::address_table_lookups, programs::CallStack};
+
+pub const TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub const TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+
+// https://github.com/solana-labs/solana-program-library/blob/master/token/program/src/instruction.rs
+const TRANSFER_TAG: u8 = 3;
+const TRANSFER_CHECKED_TAG: u8 = 12;
+
+pub fn is_token_program(program_id: &str) -> bool {
+    program_id == TOKEN_PROGRAM_ID || program_id == TOKEN_2022_PROGRAM_ID
+}
+
+/// Whether the program name of an instruction parsed by the RPC is "spl-token" or "spl-token-2022"
+pub fn is_parsed_token_program(program: &str) -> bool {
+    program == "spl-token" || program == "spl-token-2022"
+}
+
+/// The subset of spl-token instructions the monitor cares about, decoded from raw instruction data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TokenInstruction {
+    /// Accounts: source, destination, authority
+    Transfer { amount: u64 },
+    /// Accounts: source, mint, destination, authority
+    TransferChecked { amount: u64, decimals: u8 },
+    /// Any other instruction, identified by its tag
+    Other(u8),
+}
+
+impl TokenInstruction {
+    pub fn unpack(data: &[u8]) -> Result<TokenInstruction> {
+        let (&tag, rest) = data.split_first().context("empty token instruction data")?;
+        Ok(match tag {
+            TRANSFER_TAG => TokenInstruction::Transfer {
+                amount: unpack_amount(rest)?,
+            },
+            TRANSFER_CHECKED_TAG => TokenInstruction::TransferChecked {
+                amount: unpack_amount(rest)?,
+                decimals: *rest
+                    .get(8)
+                    .context("decimals not found in instruction data")?,
+            },
+            _ => TokenInstruction::Other(tag),
+        })
+    }
+}
+
+fn unpack_amount(data: &[u8]) -> Result<u64> {
+    let amount = data
+        .get(..8)
+        .context("amount not found in instruction data")?;
+    Ok(u64::from_le_bytes(amount.try_into()?))
+}
+
+/// Source and destination token accounts and raw amount of a decoded transfer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenTransfer {
+    pub source: String,
+    pub destination: String,
+    pub amount: u64,
+}
+
+/// Decode base58 instruction data, as returned by the RPC for compiled and partially decoded instructions
+pub fn decode_instruction_data(data: &str) -> Result<Vec<u8>> {
+    Ok(bs58::decode(data).into_vec()?)
+}
+
+/// Decode a transfer from raw spl-token instruction data and the instruction's account addresses
+pub fn decode_token_transfer(data: &[u8], accounts: &[&str]) -> Result<Option<TokenTransfer>> {
+    let (amount, source, destination) = match TokenInstruction::unpack(data)? {
+        TokenInstruction::Transfer { amount } => (amount, accounts.first(), accounts.get(1)),
+        TokenInstruction::TransferChecked { amount, .. } => {
+            (amount, accounts.first(), accounts.get(2))
+        }
+        TokenInstruction::Other(_) => return Ok(None),
+    };
+    let source = source.context("source not found in instruction accounts")?;
+    let destination = destination.context("destination not found in instruction accounts")?;
+    Ok(Some(TokenTransfer {
+        source: source.to_string(),
+        destination: destination.to_string(),
+        amount,
+    }))
+}
+
+/// Signatures, account keys and top-level instructions of a transaction
+#[derive(Clone, Debug, Default)]
+pub struct DecodedTransaction {
+    pub signatures: Vec<String>,
+    /// For v0 transactions the addresses loaded from lookup tables are appended to the static account keys, in the order instructions index them
+    pub account_keys: Vec<String>,
+    /// Accounts that signed the transaction, the fee payer first
+    pub signers: Vec<String>,
+    /// Top-level instructions in the form inner instructions of the same encoding take
+    pub instructions: Vec<UiInstruction>,
+    /// Program id of every top-level instruction, in order
+    pub program_ids: Vec<String>,
+}
+
+/// A top-level or inner instruction, where it ran and which programs invoked it
+#[derive(Clone, Debug)]
+pub struct ExecutedInstruction<'a> {
+    pub instruction: &'a UiInstruction,
+    pub program_id: String,
+    /// Index of the top-level instruction
+    pub instruction_index: u8,
+    /// Position within the inner instructions of the top-level instruction, `None` for the top-level instruction itself
+    pub inner_instruction_index: Option<usize>,
+    /// Programs that invoked the instruction, starting with the top-level program, empty for top-level instructions
+    pub call_stack: Vec<String>,
+}
+
+impl DecodedTransaction {
+    /// Every instruction in execution order, each top-level instruction followed by its inner instructions
+    pub fn executed_instructions<'a>(
+        &'a self,
+        inner_instructions: &'a [UiInnerInstructions],
+    ) -> Result<Vec<ExecutedInstruction<'a>>> {
+        if inner_instructions
+            .iter()
+            .any(|inner| inner.index as usize >= self.instructions.len())
+        {
+            bail!("inner instructions index out of range of instructions");
+        }
+        let mut executed = Vec::new();
+        for (index, (instruction, program_id)) in
+            self.instructions.iter().zip(&self.program_ids).enumerate()
+        {
+            let instruction_index = u8::try_from(index).context("too many instructions")?;
+            executed.push(ExecutedInstruction {
+                instruction,
+                program_id: program_id.clone(),
+                instruction_index,
+                inner_instruction_index: None,
+                call_stack: Vec::new(),
+            });
+            let mut call_stack = CallStack::new(program_id.clone());
+            for inner in inner_instructions
+                .iter()
+                .filter(|inner| inner.index == instruction_index)
+            {
+                for (inner_index, instruction) in inner.instructions.iter().enumerate() {
+                    let (program_id, stack_height) =
+                        instruction_program(instruction, &self.account_keys)?;
+                    let callers = call_stack.invoke(&program_id, stack_height);
+                    executed.push(ExecutedInstruction {
+                        instruction,
+                        program_id,
+                        instruction_index,
+                        inner_instruction_index: Some(inner_index),
+                        call_stack: callers,
+                    });
+                }
+            }
+        }
+        Ok(executed)
+    }
+}
+
+/// Decode the parts of a transaction the monitor needs, for any encoding that includes the message
+pub fn decode_transaction(
+    transaction: &EncodedTransaction,
+    loaded_addresses: &OptionSerializer<UiLoadedAddresses>,
+) -> Result<DecodedTransaction> {
+    let (signatures, mut account_keys, num_signers, instructions) = match transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            // Parsed account keys already include the addresses loaded from lookup tables
+            UiMessage::Parsed(ui_parsed_message) => {
+                let account_keys = ui_parsed_message
+                    .account_keys
+                    .iter()
+                    .map(|account| account.pubkey.clone())
+                    .collect::<Vec<_>>();
+                let program_ids = ui_parsed_message
+                    .instructions
+                    .iter()
+                    .map(|instruction| {
+                        instruction_program(instruction, &account_keys)
+                            .map(|(program_id, _)| program_id)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let signers = ui_parsed_message
+                    .account_keys
+                    .iter()
+                    .filter(|account| account.signer)
+                    .map(|account| account.pubkey.clone())
+                    .collect();
+                return Ok(DecodedTransaction {
+                    signatures: ui_transaction.signatures.clone(),
+                    account_keys,
+                    signers,
+                    instructions: ui_parsed_message.instructions.clone(),
+                    program_ids,
+                });
+            }
+            UiMessage::Raw(ui_raw_message) => (
+                ui_transaction.signatures.clone(),
+                ui_raw_message.account_keys.clone(),
+                ui_raw_message.header.num_required_signatures,
+                ui_raw_message
+                    .instructions
+                    .iter()
+                    .cloned()
+                    .map(UiInstruction::Compiled)
+                    .collect::<Vec<_>>(),
+            ),
+        },
+        EncodedTransaction::LegacyBinary(_) | EncodedTransaction::Binary(_, _) => {
+            let versioned_transaction = transaction
+                .decode()
+                .context("failed to decode binary transaction")?;
+            let signatures = versioned_transaction
+                .signatures
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            let account_keys = versioned_transaction
+                .message
+                .static_account_keys()
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            let instructions = versioned_transaction
+                .message
+                .instructions()
+                .iter()
+                .map(|instruction| {
+                    UiInstruction::Compiled(UiCompiledInstruction {
+                        program_id_index: instruction.program_id_index,
+                        accounts: instruction.accounts.clone(),
+                        data: bs58::encode(&instruction.data).into_string(),
+                        stack_height: None,
+                    })
+                })
+                .collect();
+            let num_signers = versioned_transaction
+                .message
+                .header()
+                .num_required_signatures;
+            (signatures, account_keys, num_signers, instructions)
+        }
+        EncodedTransaction::Accounts(_) => bail!("expected full transaction details"),
+    };
+
+    match loaded_addresses {
+        OptionSerializer::Some(loaded_addresses) => {
+            account_keys.extend(loaded_addresses.writable.iter().cloned());
+            account_keys.extend(loaded_addresses.readonly.iter().cloned());
+        }
+        _ => {
+            if !address_table_lookups(transaction)?.is_empty() {
+                bail!("loadedAddresses missing for transaction using address lookup tables");
+            }
+        }
+    }
+
+    let program_ids = instructions
+        .iter()
+        .map(|instruction| {
+            instruction_program(instruction, &account_keys).map(|(program_id, _)| program_id)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Signers always come first in the static account keys
+    let signers = account_keys
+        .iter()
+        .take(num_signers as usize)
+        .cloned()
+        .collect();
+
+    Ok(DecodedTransaction {
+        signatures,
+        account_keys,
+        signers,
+        instructions,
+        program_ids,
+    })
+}
+
+/// Program id and stack height of an instruction in any of the forms the RPC returns
+pub fn instruction_program(
+    instruction: &UiInstruction,
+    account_keys: &[String],
+) -> Result<(String, Option<u32>)> {
+    Ok(match instruction {
+        UiInstruction::Compiled(compiled_instruction) => (
+            account_keys
+                .get(compiled_instruction.program_id_index as usize)
+                .cloned()
+                .context("program id index out of range of account keys")?,
+            compiled_instruction.stack_height,
+        ),
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed_instruction)) => (
+            parsed_instruction.program_id.clone(),
+            parsed_instruction.stack_height,
+        ),
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(
+            partially_decoded_instruction,
+        )) => (
+            partially_decoded_instruction.program_id.clone(),
+            partially_decoded_instruction.stack_height,
+        ),
+    })
+}
@@ -0,0 +1,351 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    flows::FlowSummary, instructions::Transfer, metrics::metrics, patterns::Pattern,
+    reconcile::Discrepancy, screening::ScreeningHit, sinks::TransferSink, swaps::Swap,
+};
+
+/// Keys remembered by default
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+/// Options of `Deduplicator`
+#[derive(Clone, Debug, PartialEq)]
+pub struct DedupConfig {
+    /// Most recently seen keys kept, older keys are forgotten and their events emitted again
+    pub capacity: usize,
+    /// Append keys to this file and load them on start, so restarts and backfills skip events already emitted
+    pub path: Option<PathBuf>,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        DedupConfig {
+            capacity: DEFAULT_CAPACITY,
+            path: None,
+        }
+    }
+}
+
+/// Identifies an event by the transaction and the instruction that produced it
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DedupKey {
+    pub signature: String,
+    /// e.g. "2.0" for the first inner instruction of the third instruction, "swap:2" for a swap made in the third instruction
+    pub path: String,
+}
+
+impl DedupKey {
+    pub fn transfer(transfer: &Transfer) -> Self {
+        DedupKey {
+            signature: transfer.signature.clone(),
+            path: transfer.instruction_path(),
+        }
+    }
+
+    /// A swap by the top-level instruction it was made in, which holds at most one swap
+    pub fn swap(swap: &Swap) -> Self {
+        DedupKey {
+            signature: swap.signature.clone(),
+            path: format!("swap:{}", swap.instruction_index),
+        }
+    }
+
+    /// A discrepancy by the owner whose balance differs
+    pub fn discrepancy(discrepancy: &Discrepancy) -> Self {
+        DedupKey {
+            signature: discrepancy.signature.clone(),
+            path: format!("discrepancy:{}", discrepancy.owner),
+        }
+    }
+
+    /// From a line of the store, `signature path`
+    pub fn parse(line: &str) -> Result<Self> {
+        let Some((signature, path)) = line.split_once(' ') else {
+            bail!("expected a signature and an instruction path");
+        };
+        Ok(DedupKey {
+            signature: signature.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for DedupKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.signature, self.path)
+    }
+}
+
+/// Least recently seen keys are evicted beyond `capacity`
+#[derive(Clone, Debug)]
+pub struct SeenKeys {
+    capacity: usize,
+    stamps: HashMap<DedupKey, u64>,
+    order: BTreeMap<u64, DedupKey>,
+    next_stamp: u64,
+}
+
+impl SeenKeys {
+    pub fn new(capacity: usize) -> Self {
+        SeenKeys {
+            capacity,
+            stamps: HashMap::new(),
+            order: BTreeMap::new(),
+            next_stamp: 0,
+        }
+    }
+
+    /// Mark the key as most recently seen, returning whether it was new
+    pub fn insert(&mut self, key: DedupKey) -> bool {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        if let Some(previous) = self.stamps.insert(key.clone(), stamp) {
+            self.order.remove(&previous);
+            self.order.insert(stamp, key);
+            return false;
+        }
+        self.order.insert(stamp, key);
+        while self.stamps.len() > self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.stamps.remove(&oldest);
+        }
+        true
+    }
+
+    /// Forget a key, as if it was never seen
+    pub fn remove(&mut self, key: &DedupKey) -> bool {
+        match self.stamps.remove(key) {
+            Some(stamp) => {
+                self.order.remove(&stamp);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, key: &DedupKey) -> bool {
+        self.stamps.contains_key(key)
+    }
+
+    /// Least recently seen first
+    pub fn iter(&self) -> impl Iterator<Item = &DedupKey> {
+        self.order.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.stamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stamps.is_empty()
+    }
+}
+
+/// Remembers the keys of emitted events, in memory and optionally in an append-only file
+///
+/// New keys are pending until `flush`, which should only be called once their events were written, so a crash before then emits them again rather than losing them.
+/// The file is rewritten with only the remembered keys when it grows past twice the capacity.
+pub struct Deduplicator {
+    seen: SeenKeys,
+    /// Keys checked since the last flush or discard
+    pending: Vec<DedupKey>,
+    store: Option<(PathBuf, BufWriter<File>)>,
+    /// Lines in the store, including keys that were evicted or seen again
+    stored: usize,
+}
+
+impl Deduplicator {
+    /// Load the keys of the store, if there is one
+    pub fn open(config: &DedupConfig) -> Result<Self> {
+        let mut dedup = Deduplicator {
+            seen: SeenKeys::new(config.capacity),
+            pending: Vec::new(),
+            store: None,
+            stored: 0,
+        };
+        let Some(path) = &config.path else {
+            return Ok(dedup);
+        };
+        match File::open(path) {
+            Ok(file) => {
+                for (number, line) in BufReader::new(file).lines().enumerate() {
+                    let line =
+                        line.with_context(|| format!("failed to read {}", path.display()))?;
+                    let key = DedupKey::parse(&line).with_context(|| {
+                        format!("invalid key on line {} of {}", number + 1, path.display())
+                    })?;
+                    dedup.seen.insert(key);
+                    dedup.stored += 1;
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+        dedup.store = Some((path.clone(), open_append(path)?));
+        if dedup.stored > dedup.seen.len() {
+            dedup.compact()?;
+        }
+        Ok(dedup)
+    }
+
+    pub fn seen(&self) -> &SeenKeys {
+        &self.seen
+    }
+
+    /// Whether the event was not emitted before, remembering its key until it is flushed or discarded
+    pub fn check(&mut self, key: DedupKey) -> bool {
+        if !self.seen.insert(key.clone()) {
+            metrics().duplicates_dropped.inc();
+            return false;
+        }
+        self.pending.push(key);
+        true
+    }
+
+    /// Write the pending keys to the store, once the events they belong to were written
+    pub fn flush(&mut self) -> Result<()> {
+        let Some((path, writer)) = &mut self.store else {
+            self.pending.clear();
+            return Ok(());
+        };
+        for key in &self.pending {
+            writeln!(writer, "{key}")
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        writer
+            .flush()
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        self.stored += self.pending.len();
+        self.pending.clear();
+        if self.stored > 2 * self.seen.capacity {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Forget the pending keys, when writing their events failed
+    pub fn discard(&mut self) {
+        for key in self.pending.drain(..) {
+            self.seen.remove(&key);
+        }
+    }
+
+    /// Replace the store with the remembered keys, written next to it then renamed
+    fn compact(&mut self) -> Result<()> {
+        let Some((path, writer)) = &mut self.store else {
+            return Ok(());
+        };
+        writer.flush()?;
+        let temporary_path = path.with_extension("compacting");
+        let mut compacted = BufWriter::new(
+            File::create(&temporary_path)
+                .with_context(|| format!("failed to create {}", temporary_path.display()))?,
+        );
+        for key in self.seen.iter() {
+            writeln!(compacted, "{key}")?;
+        }
+        compacted.flush()?;
+        fs::rename(&temporary_path, &*path)
+            .with_context(|| format!("failed to replace {}", path.display()))?;
+        *writer = open_append(path)?;
+        self.stored = self.seen.len();
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> Result<BufWriter<File>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+/// Forwards each transfer, swap and discrepancy to the inner sink only the first time its key is seen
+///
+/// Keys are written to the store after the inner sink ended and flushed a block, and forgotten if that fails or the block is abandoned.
+pub struct DedupSink<'a, K: TransferSink> {
+    inner: K,
+    dedup: &'a mut Deduplicator,
+}
+
+impl<'a, K: TransferSink> DedupSink<'a, K> {
+    pub fn new(inner: K, dedup: &'a mut Deduplicator) -> Self {
+        DedupSink { inner, dedup }
+    }
+
+    pub fn deduplicator(&self) -> &Deduplicator {
+        self.dedup
+    }
+
+    /// Persist the pending keys if `write` succeeds, otherwise forget them
+    fn commit(&mut self, write: impl FnOnce(&mut K) -> Result<()>) -> Result<()> {
+        if let Err(e) = write(&mut self.inner) {
+            self.dedup.discard();
+            return Err(e);
+        }
+        self.dedup.flush()
+    }
+}
+
+impl<K: TransferSink> TransferSink for DedupSink<'_, K> {
+    fn on_block_start(&mut self, slot: u64, block_time: Option<i64>) -> Result<()> {
+        // Keys of a block that failed before its end
+        self.dedup.discard();
+        self.inner.on_block_start(slot, block_time)
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        if self.dedup.check(DedupKey::transfer(transfer)) {
+            self.inner.on_transfer(transfer)?;
+        }
+        Ok(())
+    }
+
+    fn on_swap(&mut self, swap: &Swap) -> Result<()> {
+        if self.dedup.check(DedupKey::swap(swap)) {
+            self.inner.on_swap(swap)?;
+        }
+        Ok(())
+    }
+
+    fn on_discrepancy(&mut self, discrepancy: &Discrepancy) -> Result<()> {
+        if self.dedup.check(DedupKey::discrepancy(discrepancy)) {
+            self.inner.on_discrepancy(discrepancy)?;
+        }
+        Ok(())
+    }
+
+    fn on_flow_summary(&mut self, summary: &FlowSummary) -> Result<()> {
+        self.inner.on_flow_summary(summary)
+    }
+
+    fn on_pattern(&mut self, pattern: &Pattern) -> Result<()> {
+        self.inner.on_pattern(pattern)
+    }
+
+    fn on_screening_hit(&mut self, hit: &ScreeningHit) -> Result<()> {
+        self.inner.on_screening_hit(hit)
+    }
+
+    fn on_block_end(&mut self, slot: u64) -> Result<()> {
+        self.commit(|inner| {
+            inner.on_block_end(slot)?;
+            inner.flush()
+        })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.commit(K::flush)
+    }
+}
@@ -0,0 +1,504 @@
+use anyhow::Result;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    instructions::Transfer,
+    labels::Label,
+    mints::{format_token_amount, mint_label},
+    patterns::Pattern,
+    reconcile::Discrepancy,
+    screening::ScreeningHit,
+    sinks::TransferSink,
+    swaps::Swap,
+};
+
+/// Windows tracked by `FlowAggregator::default`
+pub const DEFAULT_WINDOWS: [Duration; 3] = [
+    Duration::from_secs(60),
+    Duration::from_secs(60 * 60),
+    Duration::from_secs(24 * 60 * 60),
+];
+
+/// Addresses tracked per window by `FlowAggregator::default`
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Windows are split into this many buckets, so a window covers between 59/60 and all of its duration
+const BUCKETS: u64 = 60;
+
+/// Owner and mint flows are tracked for
+pub type FlowKey = (String, String);
+
+/// Raw amounts an address received and sent in one mint
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Flow {
+    pub inflow: u64,
+    pub outflow: u64,
+    pub decimals: u8,
+}
+
+impl Flow {
+    /// Inflow minus outflow
+    pub fn net(&self) -> i128 {
+        i128::from(self.inflow) - i128::from(self.outflow)
+    }
+
+    pub fn volume(&self) -> u64 {
+        self.inflow.saturating_add(self.outflow)
+    }
+
+    fn add(&mut self, other: &Flow) {
+        self.inflow = self.inflow.saturating_add(other.inflow);
+        self.outflow = self.outflow.saturating_add(other.outflow);
+        self.decimals = other.decimals;
+    }
+
+    fn sub(&mut self, other: &Flow) {
+        self.inflow = self.inflow.saturating_sub(other.inflow);
+        self.outflow = self.outflow.saturating_sub(other.outflow);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inflow == 0 && self.outflow == 0
+    }
+}
+
+/// How `FlowWindow::top` ranks addresses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlowOrder {
+    Inflow,
+    Outflow,
+    /// Largest positive net flow first, addresses with a net outflow are left out
+    NetInflow,
+    /// Largest negative net flow first, addresses with a net inflow are left out
+    NetOutflow,
+}
+
+struct Bucket {
+    index: u64,
+    flows: HashMap<FlowKey, Flow>,
+}
+
+/// Flows per address over a sliding window of processing time, in buckets of 1/60 of the window
+///
+/// At most `capacity` addresses are tracked, when there are more the ones with the smallest volume in the window are evicted.
+pub struct FlowWindow {
+    duration: Duration,
+    bucket_secs: u64,
+    capacity: usize,
+    buckets: VecDeque<Bucket>,
+    totals: HashMap<FlowKey, Flow>,
+    evicted: u64,
+}
+
+impl FlowWindow {
+    pub fn new(duration: Duration, capacity: usize) -> Self {
+        FlowWindow {
+            duration,
+            bucket_secs: (duration.as_secs() / BUCKETS).max(1),
+            capacity: capacity.max(1),
+            buckets: VecDeque::new(),
+            totals: HashMap::new(),
+            evicted: 0,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Addresses with flows in the window
+    pub fn len(&self) -> usize {
+        self.totals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.totals.is_empty()
+    }
+
+    /// Addresses evicted to stay within the capacity so far
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    /// Flow of an owner in a mint, zero when it is not tracked
+    pub fn flow(&self, owner: &str, mint: &str) -> Flow {
+        self.totals
+            .get(&(owner.to_string(), mint.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The `count` highest ranked addresses
+    pub fn top(&self, count: usize, order: FlowOrder) -> Vec<(FlowKey, Flow)> {
+        let mut flows = self
+            .totals
+            .iter()
+            .filter_map(|(key, flow)| {
+                let rank = match order {
+                    FlowOrder::Inflow => i128::from(flow.inflow),
+                    FlowOrder::Outflow => i128::from(flow.outflow),
+                    FlowOrder::NetInflow => flow.net(),
+                    FlowOrder::NetOutflow => -flow.net(),
+                };
+                (rank > 0).then_some((rank, key, flow))
+            })
+            .collect::<Vec<_>>();
+        flows.sort_by(|(a_rank, a_key, _), (b_rank, b_key, _)| {
+            b_rank.cmp(a_rank).then(a_key.cmp(b_key))
+        });
+        flows
+            .into_iter()
+            .take(count)
+            .map(|(_, key, flow)| (key.clone(), *flow))
+            .collect()
+    }
+
+    pub fn record(&mut self, key: FlowKey, flow: Flow, now: SystemTime) {
+        self.advance(now);
+        let index = self.bucket_index(now);
+        match self.buckets.back_mut() {
+            // Late events are counted in the current bucket
+            Some(bucket) if bucket.index >= index => {}
+            _ => self.buckets.push_back(Bucket {
+                index,
+                flows: HashMap::new(),
+            }),
+        }
+        let bucket = self.buckets.back_mut().expect("bucket was just pushed");
+        bucket.flows.entry(key.clone()).or_default().add(&flow);
+        self.totals.entry(key).or_default().add(&flow);
+        if self.totals.len() > self.capacity {
+            self.evict();
+        }
+    }
+
+    /// Drop the buckets that left the window
+    pub fn advance(&mut self, now: SystemTime) {
+        let index = self.bucket_index(now);
+        while let Some(bucket) = self.buckets.front() {
+            if bucket.index + BUCKETS > index {
+                break;
+            }
+            let bucket = self.buckets.pop_front().expect("front bucket exists");
+            for (key, flow) in bucket.flows {
+                if let Some(total) = self.totals.get_mut(&key) {
+                    total.sub(&flow);
+                    if total.is_empty() {
+                        self.totals.remove(&key);
+                    }
+                }
+            }
+        }
+    }
+
+    fn bucket_index(&self, now: SystemTime) -> u64 {
+        let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        secs / self.bucket_secs
+    }
+
+    /// Evict the smallest addresses down to 90% of the capacity, so eviction does not run on every new address
+    fn evict(&mut self) {
+        let keep = self.capacity - self.capacity / 10;
+        let mut volumes = self
+            .totals
+            .iter()
+            .map(|(key, flow)| (flow.volume(), key.clone()))
+            .collect::<Vec<_>>();
+        volumes.sort();
+        for (_, key) in volumes.into_iter().take(self.totals.len() - keep) {
+            self.totals.remove(&key);
+            for bucket in &mut self.buckets {
+                bucket.flows.remove(&key);
+            }
+            self.evicted += 1;
+        }
+    }
+}
+
+/// Inflow, outflow and net flow per address over several sliding windows, 1 minute, 1 hour and 24 hours by default
+///
+/// ```
+/// use solana_transfer_monitor::{flows::{FlowAggregator, FlowOrder}, instructions::Transfer};
+/// use std::time::{Duration, SystemTime};
+///
+/// let mut flows = FlowAggregator::default();
+/// let transfer = Transfer {
+///     source_owner: "Alice".to_string(),
+///     destination_owner: "Bob".to_string(),
+///     amount: 25_000_000,
+///     ..Default::default()
+/// };
+/// flows.record(&transfer, SystemTime::now());
+/// let hour = flows.window(Duration::from_secs(3600)).unwrap();
+/// assert_eq!(-25_000_000, hour.flow("Alice", &transfer.mint).net());
+/// assert_eq!("Bob", hour.top(1, FlowOrder::NetInflow)[0].0 .0);
+/// ```
+pub struct FlowAggregator {
+    windows: Vec<FlowWindow>,
+    /// Labels of the owners seen, bounded by the label registry
+    labels: HashMap<String, Label>,
+}
+
+impl Default for FlowAggregator {
+    fn default() -> Self {
+        FlowAggregator::new(&DEFAULT_WINDOWS, DEFAULT_CAPACITY)
+    }
+}
+
+impl FlowAggregator {
+    /// Track `windows`, each with at most `capacity` addresses
+    pub fn new(windows: &[Duration], capacity: usize) -> Self {
+        FlowAggregator {
+            windows: windows
+                .iter()
+                .map(|duration| FlowWindow::new(*duration, capacity))
+                .collect(),
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Count a transfer as outflow of its sender and inflow of its receiver at `now`
+    pub fn record(&mut self, transfer: &Transfer, now: SystemTime) {
+        let outflow = Flow {
+            outflow: transfer.amount,
+            decimals: transfer.decimals,
+            ..Default::default()
+        };
+        let inflow = Flow {
+            inflow: transfer.amount,
+            decimals: transfer.decimals,
+            ..Default::default()
+        };
+        for window in &mut self.windows {
+            let source = (transfer.source_owner.clone(), transfer.mint.clone());
+            window.record(source, outflow, now);
+            let destination = (transfer.destination_owner.clone(), transfer.mint.clone());
+            window.record(destination, inflow, now);
+        }
+        for (owner, label) in [
+            (&transfer.source_owner, &transfer.source_label),
+            (&transfer.destination_owner, &transfer.destination_label),
+        ] {
+            if let Some(label) = label {
+                self.labels.insert(owner.clone(), label.clone());
+            }
+        }
+    }
+
+    /// Drop the flows that left each window
+    pub fn advance(&mut self, now: SystemTime) {
+        for window in &mut self.windows {
+            window.advance(now);
+        }
+    }
+
+    pub fn windows(&self) -> &[FlowWindow] {
+        &self.windows
+    }
+
+    pub fn window(&self, duration: Duration) -> Option<&FlowWindow> {
+        self.windows
+            .iter()
+            .find(|window| window.duration == duration)
+    }
+
+    /// The `count` largest net inflows and outflows of every window
+    pub fn summary(&self, count: usize) -> FlowSummary {
+        let entries = |window: &FlowWindow, order| {
+            window
+                .top(count, order)
+                .into_iter()
+                .map(|((owner, mint), flow)| FlowEntry {
+                    label: self.labels.get(&owner).cloned(),
+                    owner,
+                    mint,
+                    flow,
+                })
+                .collect()
+        };
+        FlowSummary {
+            windows: self
+                .windows
+                .iter()
+                .map(|window| WindowSummary {
+                    window: window.duration,
+                    addresses: window.len(),
+                    evicted: window.evicted(),
+                    net_inflow: entries(window, FlowOrder::NetInflow),
+                    net_outflow: entries(window, FlowOrder::NetOutflow),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlowEntry {
+    pub owner: String,
+    pub label: Option<Label>,
+    pub mint: String,
+    pub flow: Flow,
+}
+
+impl FlowEntry {
+    /// Label of the owner if known, otherwise its address
+    pub fn name(&self) -> &str {
+        self.label.as_ref().map_or(&self.owner, |label| &label.name)
+    }
+}
+
+impl fmt::Display for FlowEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let amount = |amount: u64| {
+            format_token_amount(amount, self.flow.decimals).unwrap_or_else(|_| amount.to_string())
+        };
+        let net = self.flow.net();
+        let sign = if net < 0 { '-' } else { '+' };
+        let net = u64::try_from(net.unsigned_abs()).unwrap_or(u64::MAX);
+        write!(
+            f,
+            "{sign}{} {} {} (in {}, out {})",
+            amount(net),
+            mint_label(&self.mint),
+            self.name(),
+            amount(self.flow.inflow),
+            amount(self.flow.outflow)
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowSummary {
+    pub window: Duration,
+    /// Addresses with flows in the window
+    pub addresses: usize,
+    /// Addresses evicted to stay within the capacity so far
+    pub evicted: u64,
+    pub net_inflow: Vec<FlowEntry>,
+    pub net_outflow: Vec<FlowEntry>,
+}
+
+/// Written periodically by `FlowSink`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlowSummary {
+    pub windows: Vec<WindowSummary>,
+}
+
+/// e.g. "1m", "1h" or "24h"
+pub fn window_label(window: Duration) -> String {
+    let secs = window.as_secs();
+    if secs >= 3600 && secs.is_multiple_of(3600) {
+        format!("{}h", secs / 3600)
+    } else if secs >= 60 && secs.is_multiple_of(60) {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{secs}s")
+    }
+}
+
+impl fmt::Display for FlowSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for window in &self.windows {
+            writeln!(
+                f,
+                "Net flow, last {} ({} addresses):",
+                window_label(window.window),
+                window.addresses
+            )?;
+            for entry in window.net_inflow.iter().chain(&window.net_outflow) {
+                writeln!(f, "  {entry}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Records every transfer in a `FlowAggregator` and sends a summary to the wrapped sink at the end of the first block after each `interval`
+///
+/// Time is the block time, so replayed blocks fall into the windows they were produced in, or the wall clock for blocks without one.
+pub struct FlowSink<K: TransferSink> {
+    inner: K,
+    aggregator: FlowAggregator,
+    interval: Duration,
+    top: usize,
+    /// Time of the current block
+    now: SystemTime,
+    /// Time of the last summary, or of the first block until then
+    last_summary: Option<SystemTime>,
+}
+
+impl<K: TransferSink> FlowSink<K> {
+    /// Summaries list the `top` largest net inflows and outflows of each window
+    pub fn new(inner: K, aggregator: FlowAggregator, interval: Duration, top: usize) -> Self {
+        FlowSink {
+            inner,
+            aggregator,
+            interval,
+            top,
+            now: SystemTime::now(),
+            last_summary: None,
+        }
+    }
+
+    pub fn aggregator(&self) -> &FlowAggregator {
+        &self.aggregator
+    }
+}
+
+impl<K: TransferSink> TransferSink for FlowSink<K> {
+    fn on_block_start(&mut self, slot: u64, block_time: Option<i64>) -> Result<()> {
+        self.now = block_time
+            .and_then(|secs| u64::try_from(secs).ok())
+            .map_or_else(SystemTime::now, |secs| {
+                UNIX_EPOCH + Duration::from_secs(secs)
+            });
+        self.last_summary.get_or_insert(self.now);
+        self.inner.on_block_start(slot, block_time)
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        self.aggregator.record(transfer, self.now);
+        self.inner.on_transfer(transfer)
+    }
+
+    fn on_swap(&mut self, swap: &Swap) -> Result<()> {
+        self.inner.on_swap(swap)
+    }
+
+    fn on_discrepancy(&mut self, discrepancy: &Discrepancy) -> Result<()> {
+        self.inner.on_discrepancy(discrepancy)
+    }
+
+    fn on_flow_summary(&mut self, summary: &FlowSummary) -> Result<()> {
+        self.inner.on_flow_summary(summary)
+    }
+
+    fn on_pattern(&mut self, pattern: &Pattern) -> Result<()> {
+        self.inner.on_pattern(pattern)
+    }
+
+    fn on_screening_hit(&mut self, hit: &ScreeningHit) -> Result<()> {
+        self.inner.on_screening_hit(hit)
+    }
+
+    fn on_block_end(&mut self, slot: u64) -> Result<()> {
+        self.inner.on_block_end(slot)?;
+        let now = self.now;
+        let last_summary = *self.last_summary.get_or_insert(now);
+        if now.duration_since(last_summary).unwrap_or_default() >= self.interval {
+            self.last_summary = Some(now);
+            self.aggregator.advance(now);
+            self.inner
+                .on_flow_summary(&self.aggregator.summary(self.top))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,363 @@
+use anyhow::{bail, Result};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    io::Write,
+    path::Path,
+};
+
+use crate::{instructions::Transfer, labels::Label, mints::mint_label, sinks::TransferSink};
+
+/// All transfers from one owner to another in one mint
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edge {
+    pub source: String,
+    pub destination: String,
+    pub mint: String,
+    pub decimals: u8,
+    /// Sum of the raw amounts
+    pub amount: u64,
+    pub count: u64,
+    pub first_slot: u64,
+    pub last_slot: u64,
+}
+
+impl Edge {
+    /// Amount in whole tokens, used as the edge weight
+    pub fn weight(&self) -> f64 {
+        self.amount as f64 / 10f64.powi(self.decimals.into())
+    }
+}
+
+/// Which transfers `TransferGraph::trace` follows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Where the seed's tokens went: transfers sent by the seed, then by their receivers
+    Forward,
+    /// Where the seed's tokens came from: transfers received by the seed, then by their senders
+    Backward,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphFormat {
+    GraphMl,
+    Gexf,
+    Dot,
+}
+
+impl GraphFormat {
+    /// From a `.graphml`, `.gexf`, `.dot` or `.gv` extension
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        Ok(match extension.to_lowercase().as_str() {
+            "graphml" => GraphFormat::GraphMl,
+            "gexf" => GraphFormat::Gexf,
+            "dot" | "gv" => GraphFormat::Dot,
+            _ => bail!(
+                "unknown graph format of {}, expected .graphml, .gexf, .dot or .gv",
+                path.display()
+            ),
+        })
+    }
+}
+
+/// Directed multigraph of owners with one edge per sender, receiver and mint
+///
+/// As a sink it adds every transfer it receives, so processing a range of blocks captures their transfers.
+#[derive(Clone, Debug, Default)]
+pub struct TransferGraph {
+    nodes: BTreeMap<String, Option<Label>>,
+    edges: BTreeMap<(String, String, String), Edge>,
+    slot: u64,
+}
+
+impl TransferGraph {
+    pub fn new() -> Self {
+        TransferGraph::default()
+    }
+
+    pub fn add(&mut self, slot: u64, transfer: &Transfer) {
+        for (owner, label) in [
+            (&transfer.source_owner, &transfer.source_label),
+            (&transfer.destination_owner, &transfer.destination_label),
+        ] {
+            let node = self.nodes.entry(owner.clone()).or_default();
+            if label.is_some() {
+                node.clone_from(label);
+            }
+        }
+        let key = (
+            transfer.source_owner.clone(),
+            transfer.destination_owner.clone(),
+            transfer.mint.clone(),
+        );
+        let edge = self.edges.entry(key).or_insert_with(|| Edge {
+            source: transfer.source_owner.clone(),
+            destination: transfer.destination_owner.clone(),
+            mint: transfer.mint.clone(),
+            decimals: transfer.decimals,
+            amount: 0,
+            count: 0,
+            first_slot: slot,
+            last_slot: slot,
+        });
+        edge.amount = edge.amount.saturating_add(transfer.amount);
+        edge.count += 1;
+        edge.first_slot = edge.first_slot.min(slot);
+        edge.last_slot = edge.last_slot.max(slot);
+    }
+
+    /// Owners sorted by address, with their labels
+    pub fn nodes(&self) -> impl Iterator<Item = (&str, Option<&Label>)> {
+        self.nodes
+            .iter()
+            .map(|(address, label)| (address.as_str(), label.as_ref()))
+    }
+
+    /// Edges sorted by sender, receiver and mint
+    pub fn edges(&self) -> impl Iterator<Item = &Edge> {
+        self.edges.values()
+    }
+
+    pub fn edge(&self, source: &str, destination: &str, mint: &str) -> Option<&Edge> {
+        self.edges.get(&(
+            source.to_string(),
+            destination.to_string(),
+            mint.to_string(),
+        ))
+    }
+
+    /// The part of the graph within `hops` transfers of `seed`, following `direction`
+    pub fn trace(&self, seed: &str, hops: usize, direction: Direction) -> TransferGraph {
+        let mut traced = TransferGraph::default();
+        let mut visited = HashSet::from([seed.to_string()]);
+        let mut frontier = BTreeSet::from([seed.to_string()]);
+        for _ in 0..hops {
+            let mut next = BTreeSet::new();
+            for edge in self.edges.values() {
+                let (from, to) = match direction {
+                    Direction::Forward => (&edge.source, &edge.destination),
+                    Direction::Backward => (&edge.destination, &edge.source),
+                };
+                if !frontier.contains(from) {
+                    continue;
+                }
+                traced.insert_edge(edge, &self.nodes);
+                if visited.insert(to.clone()) {
+                    next.insert(to.clone());
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        traced
+    }
+
+    fn insert_edge(&mut self, edge: &Edge, nodes: &BTreeMap<String, Option<Label>>) {
+        for owner in [&edge.source, &edge.destination] {
+            let label = nodes.get(owner).cloned().flatten();
+            self.nodes.insert(owner.clone(), label);
+        }
+        let key = (
+            edge.source.clone(),
+            edge.destination.clone(),
+            edge.mint.clone(),
+        );
+        self.edges.insert(key, edge.clone());
+    }
+
+    pub fn write(&self, format: GraphFormat, writer: &mut impl Write) -> Result<()> {
+        match format {
+            GraphFormat::GraphMl => self.write_graphml(writer),
+            GraphFormat::Gexf => self.write_gexf(writer),
+            GraphFormat::Dot => self.write_dot(writer),
+        }
+    }
+
+    pub fn write_graphml(&self, writer: &mut impl Write) -> Result<()> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        for (id, for_, type_) in [
+            ("label", "node", "string"),
+            ("category", "node", "string"),
+            ("mint", "edge", "string"),
+            ("amount", "edge", "long"),
+            ("weight", "edge", "double"),
+            ("count", "edge", "long"),
+            ("firstSlot", "edge", "long"),
+            ("lastSlot", "edge", "long"),
+        ] {
+            writeln!(
+                writer,
+                r#"  <key id="{id}" for="{for_}" attr.name="{id}" attr.type="{type_}"/>"#
+            )?;
+        }
+        writeln!(writer, r#"  <graph id="transfers" edgedefault="directed">"#)?;
+        for (address, label) in self.nodes() {
+            match label {
+                Some(label) => writeln!(
+                    writer,
+                    r#"    <node id="{}"><data key="label">{}</data><data key="category">{}</data></node>"#,
+                    xml_escape(address),
+                    xml_escape(&label.name),
+                    label.category.key()
+                )?,
+                None => writeln!(writer, r#"    <node id="{}"/>"#, xml_escape(address))?,
+            }
+        }
+        for (index, edge) in self.edges().enumerate() {
+            writeln!(
+                writer,
+                r#"    <edge id="e{index}" source="{}" target="{}"><data key="mint">{}</data><data key="amount">{}</data><data key="weight">{}</data><data key="count">{}</data><data key="firstSlot">{}</data><data key="lastSlot">{}</data></edge>"#,
+                xml_escape(&edge.source),
+                xml_escape(&edge.destination),
+                xml_escape(&edge.mint),
+                edge.amount,
+                edge.weight(),
+                edge.count,
+                edge.first_slot,
+                edge.last_slot
+            )?;
+        }
+        writeln!(writer, "  </graph>")?;
+        writeln!(writer, "</graphml>")?;
+        Ok(())
+    }
+
+    pub fn write_gexf(&self, writer: &mut impl Write) -> Result<()> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <graph defaultedgetype="directed" mode="static">"#
+        )?;
+        writeln!(writer, r#"    <attributes class="node">"#)?;
+        writeln!(
+            writer,
+            r#"      <attribute id="category" title="category" type="string"/>"#
+        )?;
+        writeln!(writer, "    </attributes>")?;
+        writeln!(writer, r#"    <attributes class="edge">"#)?;
+        for (id, type_) in [
+            ("mint", "string"),
+            ("amount", "long"),
+            ("count", "long"),
+            ("firstSlot", "long"),
+            ("lastSlot", "long"),
+        ] {
+            writeln!(
+                writer,
+                r#"      <attribute id="{id}" title="{id}" type="{type_}"/>"#
+            )?;
+        }
+        writeln!(writer, "    </attributes>")?;
+        writeln!(writer, "    <nodes>")?;
+        for (address, label) in self.nodes() {
+            match label {
+                Some(label) => writeln!(
+                    writer,
+                    r#"      <node id="{}" label="{}"><attvalues><attvalue for="category" value="{}"/></attvalues></node>"#,
+                    xml_escape(address),
+                    xml_escape(&label.name),
+                    label.category.key()
+                )?,
+                None => writeln!(
+                    writer,
+                    r#"      <node id="{0}" label="{0}"/>"#,
+                    xml_escape(address)
+                )?,
+            }
+        }
+        writeln!(writer, "    </nodes>")?;
+        writeln!(writer, "    <edges>")?;
+        for (index, edge) in self.edges().enumerate() {
+            writeln!(
+                writer,
+                r#"      <edge id="{index}" source="{}" target="{}" weight="{}" label="{}"><attvalues><attvalue for="mint" value="{}"/><attvalue for="amount" value="{}"/><attvalue for="count" value="{}"/><attvalue for="firstSlot" value="{}"/><attvalue for="lastSlot" value="{}"/></attvalues></edge>"#,
+                xml_escape(&edge.source),
+                xml_escape(&edge.destination),
+                edge.weight(),
+                xml_escape(mint_label(&edge.mint)),
+                xml_escape(&edge.mint),
+                edge.amount,
+                edge.count,
+                edge.first_slot,
+                edge.last_slot
+            )?;
+        }
+        writeln!(writer, "    </edges>")?;
+        writeln!(writer, "  </graph>")?;
+        writeln!(writer, "</gexf>")?;
+        Ok(())
+    }
+
+    pub fn write_dot(&self, writer: &mut impl Write) -> Result<()> {
+        writeln!(writer, "digraph transfers {{")?;
+        for (address, label) in self.nodes() {
+            match label {
+                Some(label) => writeln!(
+                    writer,
+                    "  {} [label={}, category={}];",
+                    dot_quote(address),
+                    dot_quote(&label.name),
+                    dot_quote(label.category.key())
+                )?,
+                None => writeln!(writer, "  {};", dot_quote(address))?,
+            }
+        }
+        for edge in self.edges() {
+            let label = format!(
+                "{} {} x{}",
+                edge.weight(),
+                mint_label(&edge.mint),
+                edge.count
+            );
+            writeln!(
+                writer,
+                "  {} -> {} [label={}, mint={}, amount={}, count={}];",
+                dot_quote(&edge.source),
+                dot_quote(&edge.destination),
+                dot_quote(&label),
+                dot_quote(&edge.mint),
+                edge.amount,
+                edge.count
+            )?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+}
+
+impl TransferSink for TransferGraph {
+    fn on_block_start(&mut self, slot: u64, _block_time: Option<i64>) -> Result<()> {
+        self.slot = slot;
+        Ok(())
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        self.add(self.slot, transfer);
+        Ok(())
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn dot_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
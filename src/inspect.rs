@@ -0,0 +1,271 @@
+use anyhow::{bail, Result};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedTransactionWithStatusMeta,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::{
+    config::Config,
+    decode::{decode_transaction, ExecutedInstruction, TokenTransfer},
+    instructions::{resolve_transfer, token_instruction, Transfer},
+    mints::mint_label,
+    programs::program_label,
+    token_accounts,
+};
+
+/// Why an instruction did not produce a written transfer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    NotTokenProgram,
+    /// A token program instruction other than a transfer, e.g. "closeAccount"
+    NotTransfer(String),
+    /// The account has no token balance before the transaction, so its owner and mint are unknown, e.g. it was created by the transaction
+    UnknownAccount(String),
+    /// A transfer of a mint that is not tracked, decoded only to recognise swaps
+    OtherMint(String),
+    /// The source and destination token accounts hold different mints
+    MintMismatch {
+        source: String,
+        destination: String,
+    },
+    /// Decoding failed, which fails the whole block when monitoring
+    Error(String),
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::NotTokenProgram => write!(f, "not a token program instruction"),
+            SkipReason::NotTransfer(kind) => write!(f, "{kind} is not a transfer"),
+            SkipReason::UnknownAccount(account) => {
+                write!(f, "{account} has no token balance before the transaction")
+            }
+            SkipReason::OtherMint(mint) => write!(f, "{} is not tracked", mint_label(mint)),
+            SkipReason::MintMismatch {
+                source,
+                destination,
+            } => write!(
+                f,
+                "source mint {} does not match destination mint {}",
+                mint_label(source),
+                mint_label(destination)
+            ),
+            SkipReason::Error(error) => write!(f, "error: {error}"),
+        }
+    }
+}
+
+/// What the monitor did with one instruction
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    Transfer(Box<Transfer>),
+    Skipped(SkipReason),
+}
+
+/// An instruction and how it was decoded
+#[derive(Clone, Debug)]
+pub struct InstructionInspection {
+    /// Index of the top-level instruction
+    pub instruction_index: u8,
+    /// Position within the inner instructions of the top-level instruction, `None` for the top-level instruction itself
+    pub inner_instruction_index: Option<usize>,
+    pub program_id: String,
+    /// Token program instruction type, e.g. "transferChecked"
+    pub kind: Option<String>,
+    /// Source and destination token accounts of a transfer instruction, whether or not they resolved
+    pub token_transfer: Option<TokenTransfer>,
+    pub outcome: Outcome,
+}
+
+/// Every instruction of a transaction with the reason it did or did not produce a transfer
+#[derive(Clone, Debug)]
+pub struct Inspection {
+    pub signature: String,
+    /// Error of a failed transaction, whose instructions are not decoded
+    pub error: Option<String>,
+    /// Owner, mint and decimals of the token accounts transfers are resolved against, by address
+    pub accounts: BTreeMap<String, (String, String, u8)>,
+    /// Top-level instructions, each followed by its inner instructions
+    pub instructions: Vec<InstructionInspection>,
+}
+
+/// Decode a transaction the way the monitor does, recording every instruction instead of only the transfers
+pub fn inspect_transaction(
+    transaction: &EncodedTransactionWithStatusMeta,
+    config: &Config,
+) -> Result<Inspection> {
+    let Some(meta) = &transaction.meta else {
+        bail!("transaction has no status meta");
+    };
+    let decoded = decode_transaction(&transaction.transaction, &meta.loaded_addresses)?;
+    let signature = decoded.signatures.first().cloned().unwrap_or_default();
+    if let Some(err) = &meta.err {
+        return Ok(Inspection {
+            signature,
+            error: Some(err.to_string()),
+            accounts: BTreeMap::new(),
+            instructions: Vec::new(),
+        });
+    }
+    let accounts_map = token_accounts(&meta.pre_token_balances, &decoded.account_keys, config)?;
+    let OptionSerializer::Some(inner_instructions) = &meta.inner_instructions else {
+        bail!("expected OptionSerializer::Some");
+    };
+
+    let mut instructions = Vec::new();
+    for executed in decoded.executed_instructions(inner_instructions)? {
+        let (kind, token_transfer, outcome) =
+            match token_instruction(executed.instruction, &decoded.account_keys) {
+                Ok(Some((kind, Some(token_transfer)))) => {
+                    let outcome = resolve(&executed, &token_transfer, &accounts_map, config);
+                    (Some(kind), Some(token_transfer), outcome)
+                }
+                Ok(Some((kind, None))) => (
+                    Some(kind.clone()),
+                    None,
+                    Outcome::Skipped(SkipReason::NotTransfer(kind)),
+                ),
+                Ok(None) => (None, None, Outcome::Skipped(SkipReason::NotTokenProgram)),
+                Err(e) => (
+                    None,
+                    None,
+                    Outcome::Skipped(SkipReason::Error(format!("{e:#}"))),
+                ),
+            };
+        let outcome = match outcome {
+            Outcome::Transfer(mut transfer) => {
+                transfer.signature = signature.clone();
+                config.labels.label_transfer(&mut transfer);
+                Outcome::Transfer(transfer)
+            }
+            outcome => outcome,
+        };
+        instructions.push(InstructionInspection {
+            instruction_index: executed.instruction_index,
+            inner_instruction_index: executed.inner_instruction_index,
+            program_id: executed.program_id,
+            kind,
+            token_transfer,
+            outcome,
+        });
+    }
+
+    Ok(Inspection {
+        signature,
+        error: None,
+        accounts: accounts_map.into_iter().collect(),
+        instructions,
+    })
+}
+
+/// The transfer `process_transaction` makes of a token transfer instruction, or why it makes none
+fn resolve(
+    executed: &ExecutedInstruction,
+    token_transfer: &TokenTransfer,
+    accounts_map: &HashMap<String, (String, String, u8)>,
+    config: &Config,
+) -> Outcome {
+    let source = accounts_map.get(&token_transfer.source);
+    let destination = accounts_map.get(&token_transfer.destination);
+    if let (Some((_, source_mint, _)), Some((_, destination_mint, _))) = (source, destination) {
+        if source_mint != destination_mint {
+            return Outcome::Skipped(SkipReason::MintMismatch {
+                source: source_mint.clone(),
+                destination: destination_mint.clone(),
+            });
+        }
+    }
+    match resolve_transfer(token_transfer.clone(), accounts_map) {
+        Ok(Some(transfer)) if !config.tracks_mint(&transfer.mint) => {
+            Outcome::Skipped(SkipReason::OtherMint(transfer.mint))
+        }
+        Ok(Some(transfer)) => Outcome::Transfer(Box::new(Transfer {
+            instruction_index: executed.instruction_index,
+            inner_instruction_index: executed.inner_instruction_index,
+            call_stack: executed.call_stack.clone(),
+            ..transfer
+        })),
+        Ok(None) => {
+            let account = if source.is_some() {
+                &token_transfer.destination
+            } else {
+                &token_transfer.source
+            };
+            Outcome::Skipped(SkipReason::UnknownAccount(account.clone()))
+        }
+        Err(e) => Outcome::Skipped(SkipReason::Error(format!("{e:#}"))),
+    }
+}
+
+impl Inspection {
+    pub fn transfers(&self) -> impl Iterator<Item = &Transfer> {
+        self.instructions
+            .iter()
+            .filter_map(|instruction| match &instruction.outcome {
+                Outcome::Transfer(transfer) => Some(transfer.as_ref()),
+                Outcome::Skipped(_) => None,
+            })
+    }
+
+    fn describe_account(&self, account: &str) -> String {
+        match self.accounts.get(account) {
+            Some((owner, mint, _)) => {
+                format!("{account} (owner {owner}, {})", mint_label(mint))
+            }
+            None => format!("{account} (unknown)"),
+        }
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Transaction {}", self.signature)?;
+        if let Some(error) = &self.error {
+            return writeln!(f, "Failed with {error}, no transfers are decoded");
+        }
+        writeln!(f, "Token accounts:")?;
+        for (account, (owner, mint, decimals)) in &self.accounts {
+            writeln!(
+                f,
+                "  {account}: owner {owner}, mint {}, {decimals} decimals",
+                mint_label(mint)
+            )?;
+        }
+        writeln!(f, "Instructions:")?;
+        for instruction in &self.instructions {
+            let (indent, position) = match instruction.inner_instruction_index {
+                None => ("  ", format!("#{}", instruction.instruction_index)),
+                Some(inner) => (
+                    "    ",
+                    format!("#{}.{inner}", instruction.instruction_index),
+                ),
+            };
+            write!(
+                f,
+                "{indent}{position} {}",
+                program_label(&instruction.program_id)
+            )?;
+            if let Some(kind) = &instruction.kind {
+                write!(f, " {kind}")?;
+            }
+            writeln!(f)?;
+            if let Some(token_transfer) = &instruction.token_transfer {
+                writeln!(
+                    f,
+                    "{indent}  {} -> {}, raw amount {}",
+                    self.describe_account(&token_transfer.source),
+                    self.describe_account(&token_transfer.destination),
+                    token_transfer.amount
+                )?;
+            }
+            match &instruction.outcome {
+                Outcome::Transfer(transfer) => writeln!(f, "{indent}  matched: {transfer}")?,
+                Outcome::Skipped(reason) => writeln!(f, "{indent}  skipped: {reason}")?,
+            }
+        }
+        Ok(())
+    }
+}
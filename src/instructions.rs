@@ -1,81 +1,248 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result};
 use serde_json::Value;
+use solana_transaction_status::{UiInstruction, UiParsedInstruction};
 use std::{collections::HashMap, fmt, str::from_utf8};
+use tracing::warn;
 
-use crate::USDC_MINT_ADDRESS;
+use crate::{
+    decode::{
+        decode_instruction_data, decode_token_transfer, is_parsed_token_program, is_token_program,
+        ExecutedInstruction, TokenInstruction, TokenTransfer,
+    },
+    labels::Label,
+    metrics::metrics,
+    mints::{format_token_amount, mint_label},
+    programs::program_label,
+    USDC_MINT_ADDRESS,
+};
+
+/// Transfer made by a top-level or inner instruction in any of the forms the RPC returns, `None` for other instructions and transfers of accounts without a token balance
+pub fn handle_instruction(
+    executed: &ExecutedInstruction,
+    account_keys: &[String],
+    accounts_map: &HashMap<String, (String, String, u8)>,
+) -> Result<Option<Transfer>> {
+    let Some((_, Some(token_transfer))) = token_instruction(executed.instruction, account_keys)?
+    else {
+        return Ok(None);
+    };
+    Ok(
+        resolve_transfer(token_transfer, accounts_map)?.map(|transfer| Transfer {
+            instruction_index: executed.instruction_index,
+            inner_instruction_index: executed.inner_instruction_index,
+            call_stack: executed.call_stack.clone(),
+            ..transfer
+        }),
+    )
+}
+
+/// Type of a token program instruction, e.g. "transferChecked", with its transfer if it is one, `None` for instructions of other programs
+pub fn token_instruction(
+    instruction: &UiInstruction,
+    account_keys: &[String],
+) -> Result<Option<(String, Option<TokenTransfer>)>> {
+    let (program_id, data, accounts) = match instruction {
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed_instruction)) => {
+            if !is_parsed_token_program(&parsed_instruction.program) {
+                return Ok(None);
+            }
+            let kind = parsed_instruction.parsed["type"]
+                .as_str()
+                .context("type not found in instruction JSON")?;
+            let token_transfer = parsed_token_transfer(&parsed_instruction.parsed)?;
+            return Ok(Some((kind.to_string(), token_transfer)));
+        }
+        UiInstruction::Compiled(compiled_instruction) => (
+            account_keys
+                .get(compiled_instruction.program_id_index as usize)
+                .context("program id index out of range of account keys")?,
+            &compiled_instruction.data,
+            compiled_instruction
+                .accounts
+                .iter()
+                .map(|index| {
+                    account_keys
+                        .get(*index as usize)
+                        .map(String::as_str)
+                        .context("account index out of range of account keys")
+                })
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(instruction)) => (
+            &instruction.program_id,
+            &instruction.data,
+            instruction
+                .accounts
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+        ),
+    };
+    if !is_token_program(program_id) {
+        return Ok(None);
+    }
+    let data = decode_instruction_data(data)?;
+    let kind = match TokenInstruction::unpack(&data)? {
+        TokenInstruction::Transfer { .. } => "transfer".to_string(),
+        TokenInstruction::TransferChecked { .. } => "transferChecked".to_string(),
+        TokenInstruction::Other(tag) => format!("instruction {tag}"),
+    };
+    Ok(Some((kind, decode_token_transfer(&data, &accounts)?)))
+}
 
 pub fn handle_parsed_instruction(
-    mut parsed_instruction: Value,
-    accounts_map: &mut HashMap<String, (String, String)>,
+    parsed_instruction: &Value,
+    accounts_map: &HashMap<String, (String, String, u8)>,
 ) -> Result<Option<Transfer>> {
-    let type_ = parsed_instruction["type"].take();
-    let type_ = type_
+    match parsed_token_transfer(parsed_instruction)? {
+        Some(token_transfer) => resolve_transfer(token_transfer, accounts_map),
+        None => Ok(None),
+    }
+}
+
+/// Transfer of a `transfer` or `transferChecked` spl-token or spl-token-2022 instruction parsed by the RPC, `None` for other instruction types
+pub fn parsed_token_transfer(parsed_instruction: &Value) -> Result<Option<TokenTransfer>> {
+    let type_ = parsed_instruction["type"]
         .as_str()
         .context("type not found in instruction JSON")?;
 
     if type_ == "transfer" || type_ == "transferChecked" {
-        let mut info = parsed_instruction["info"].take();
+        let info = &parsed_instruction["info"];
 
-        let source = info["source"].take();
         let err_message = "source not found in instruction JSON";
-        let source = source.as_str().context(err_message)?;
+        let source = info["source"].as_str().context(err_message)?;
 
-        let destination = info["destination"].take();
         let err_message = "destination not found in instruction JSON";
-        let destination = destination.as_str().context(err_message)?;
+        let destination = info["destination"].as_str().context(err_message)?;
 
-        // we only want to handle USDC transfers, but we don't know the mint key until we lookup the source and destination in accounts_mapping
-        // Instruction might not be for the correct mint, so might not exist in accounts_map
-        let Some((source_owner, source_mint)) = accounts_map.get(source) else {
-            return Ok(None);
+        let (raw_amount, message) = if type_ == "transfer" {
+            (&info["amount"], "amount not found in instruction JSON")
+        } else {
+            (
+                &info["tokenAmount"]["amount"],
+                "amount not found in tokenAmount JSON",
+            )
         };
-        let Some((destination_owner, destination_mint)) = accounts_map.get(destination) else {
-            return Ok(None);
-        };
-
-        if source_mint != destination_mint {
-            bail!("source and destination mint do not match");
-        }
+        let raw_amount = raw_amount.as_str().context(message)?;
 
-        if source_mint == USDC_MINT_ADDRESS {
-            let (raw_amount, message) = if type_ == "transfer" {
-                let raw_amount = info["amount"].take();
-                let message = "amount not found in instruction JSON";
-                (raw_amount, message)
-            } else {
-                let mut token_amount = info["tokenAmount"].take();
-                let raw_amount = token_amount["amount"].take();
-                let message = "amount not found in tokenAmount JSON";
-                (raw_amount, message)
-            };
-            let raw_amount = raw_amount.as_str().context(message)?;
-
-            let formatted_amount = format_amount(raw_amount)?;
-            return Ok(Some(Transfer {
-                source_owner: source_owner.clone(),
-                destination_owner: destination_owner.clone(),
-                formatted_amount,
-            }));
-        }
+        return Ok(Some(TokenTransfer {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            amount: raw_amount.parse()?,
+        }));
     }
     Ok(None)
 }
 
+/// Look up the owners and mint of a transfer's token accounts, `None` when either has no token balance in the transaction or their mints differ
+pub(crate) fn resolve_transfer(
+    token_transfer: TokenTransfer,
+    accounts_map: &HashMap<String, (String, String, u8)>,
+) -> Result<Option<Transfer>> {
+    // Instruction might be for an account without a token balance, so might not exist in accounts_map
+    let Some((source_owner, source_mint, decimals)) = accounts_map.get(&token_transfer.source)
+    else {
+        return Ok(None);
+    };
+    let Some((destination_owner, destination_mint, _)) =
+        accounts_map.get(&token_transfer.destination)
+    else {
+        return Ok(None);
+    };
+
+    if source_mint != destination_mint {
+        warn!(
+            "skipping transfer from {} to {}, source mint {source_mint} and destination mint {destination_mint} do not match",
+            token_transfer.source, token_transfer.destination
+        );
+        metrics().mint_mismatches.inc();
+        return Ok(None);
+    }
+
+    Ok(Some(Transfer {
+        source_owner: source_owner.clone(),
+        destination_owner: destination_owner.clone(),
+        source_account: token_transfer.source,
+        destination_account: token_transfer.destination,
+        formatted_amount: format_token_amount(token_transfer.amount, *decimals)?,
+        amount: token_transfer.amount,
+        mint: source_mint.clone(),
+        decimals: *decimals,
+        ..Default::default()
+    }))
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct Transfer {
+    /// First signature of the transaction the transfer is part of
+    pub signature: String,
     pub source_owner: String,
     pub destination_owner: String,
+    /// Token account the amount was moved from
+    pub source_account: String,
+    /// Token account the amount was moved to
+    pub destination_account: String,
+    /// Set from `Config::labels` while decoding
+    pub source_label: Option<Label>,
+    pub destination_label: Option<Label>,
     pub formatted_amount: String,
+    /// Raw amount in the smallest unit of the mint
+    pub amount: u64,
+    pub mint: String,
+    pub decimals: u8,
+    /// Index of the top-level instruction that executed the transfer
+    pub instruction_index: u8,
+    /// Position of the transfer within the inner instructions of the top-level instruction, `None` when it is the top-level instruction
+    pub inner_instruction_index: Option<usize>,
+    /// Programs that invoked the token program, starting with the top-level program, empty when the transfer is a top-level instruction
+    pub call_stack: Vec<String>,
+}
+
+impl Transfer {
+    pub fn is_usdc(&self) -> bool {
+        self.mint == USDC_MINT_ADDRESS
+    }
+
+    /// Label of the sender if known, otherwise its owner
+    pub fn source_name(&self) -> &str {
+        self.source_label
+            .as_ref()
+            .map_or(&self.source_owner, |label| &label.name)
+    }
+
+    /// Label of the receiver if known, otherwise its owner
+    pub fn destination_name(&self) -> &str {
+        self.destination_label
+            .as_ref()
+            .map_or(&self.destination_owner, |label| &label.name)
+    }
+
+    /// Position of the instruction within the transaction, e.g. "2.0" for the first inner instruction of the third instruction
+    pub fn instruction_path(&self) -> String {
+        match self.inner_instruction_index {
+            Some(inner_index) => format!("{}.{inner_index}", self.instruction_index),
+            None => self.instruction_index.to_string(),
+        }
+    }
+
+    /// Names of the invoking programs, e.g. "Jupiter v6 > Raydium AMM v4"
+    pub fn call_path(&self) -> String {
+        self.call_stack
+            .iter()
+            .map(|program_id| program_label(program_id))
+            .collect::<Vec<_>>()
+            .join(" > ")
+    }
 }
 impl fmt::Display for Transfer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Transfer {
-            source_owner,
-            destination_owner,
-            formatted_amount,
-        } = self;
         write!(
             f,
-            "TX detected: {source_owner} sent {formatted_amount} USDC to {destination_owner}"
+            "TX detected: {} sent {} {} to {}",
+            self.source_name(),
+            self.formatted_amount,
+            mint_label(&self.mint),
+            self.destination_name()
         )
     }
 }
@@ -86,7 +253,7 @@ impl fmt::Display for Transfer {
 // 5 -> 0.0xx xxx
 // 4 -> 0.00x xxx
 // etc
-fn format_amount(raw_amount: &str) -> Result<String> {
+pub(crate) fn format_amount(raw_amount: &str) -> Result<String> {
     Ok(match raw_amount.len() {
         7 => format!("{}.{}", &raw_amount[..1], &raw_amount[1..5]),
         6 => format!("0.{raw_amount}"),
@@ -96,8 +263,7 @@ fn format_amount(raw_amount: &str) -> Result<String> {
         2 => format!("0.000{raw_amount}"),
         1 => raw_amount.to_string(),
         _ => {
-            let pre_decy = raw_amount[..raw_amount.len() - 6]
-                .as_bytes()
+            let pre_decy = raw_amount.as_bytes()[..raw_amount.len() - 6]
                 .rchunks(3)
                 .rev()
                 .map(from_utf8)
@@ -0,0 +1,168 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::{collections::HashMap, fmt, fs, path::Path};
+
+use crate::instructions::Transfer;
+
+/// Labels shipped with the monitor, `address,name,category` per line
+const BUNDLED_LABELS: &str = include_str!("../data/labels.csv");
+
+/// What kind of entity controls a labeled address
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Category {
+    Cex,
+    DexPool,
+    Bridge,
+    Treasury,
+    Other,
+}
+
+impl Category {
+    /// Parse "CEX", "DEX pool", "dex-pool", "bridge", "treasury" or "other", ignoring case and separators
+    pub fn parse(category: &str) -> Result<Self> {
+        let normalized = category
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .collect::<String>()
+            .to_lowercase();
+        Ok(match normalized.as_str() {
+            "cex" => Category::Cex,
+            "dexpool" => Category::DexPool,
+            "bridge" => Category::Bridge,
+            "treasury" => Category::Treasury,
+            "" | "other" => Category::Other,
+            _ => bail!("unknown category {category}"),
+        })
+    }
+
+    /// Written to JSON, e.g. "dex-pool"
+    pub fn key(&self) -> &'static str {
+        match self {
+            Category::Cex => "cex",
+            Category::DexPool => "dex-pool",
+            Category::Bridge => "bridge",
+            Category::Treasury => "treasury",
+            Category::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Category::Cex => "CEX",
+            Category::DexPool => "DEX pool",
+            Category::Bridge => "bridge",
+            Category::Treasury => "treasury",
+            Category::Other => "other",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub name: String,
+    pub category: Category,
+}
+
+/// Names of owners and token accounts
+#[derive(Clone, Debug, Default)]
+pub struct Labels {
+    labels: HashMap<String, Label>,
+}
+
+impl Labels {
+    /// The labels in `data/labels.csv`
+    pub fn bundled() -> Self {
+        let mut labels = Labels::default();
+        labels
+            .extend_csv(BUNDLED_LABELS)
+            .expect("bundled labels are valid");
+        labels
+    }
+
+    /// Add the labels of a `.json` or CSV file, replacing labels of the same addresses
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let result = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            serde_json::from_str(&contents)
+                .map_err(Into::into)
+                .and_then(|json| self.extend_json(&json))
+        } else {
+            self.extend_csv(&contents)
+        };
+        result.with_context(|| format!("invalid labels in {}", path.display()))
+    }
+
+    /// Add `address,name,category` lines, skipping the header, blank lines and `#` comments. Names may contain commas.
+    pub fn extend_csv(&mut self, csv: &str) -> Result<()> {
+        for (number, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line == "address,name,category" {
+                continue;
+            }
+            let parse = || -> Result<(String, Label)> {
+                let (address, rest) = line.split_once(',').context("expected 3 columns")?;
+                let (name, category) = rest.rsplit_once(',').context("expected 3 columns")?;
+                Ok((address.trim().to_string(), label(name.trim(), category)?))
+            };
+            let (address, label) = parse().with_context(|| format!("on line {}", number + 1))?;
+            self.insert(address, label);
+        }
+        Ok(())
+    }
+
+    /// Add labels from `[{"address": ..., "name": ..., "category": ...}]`
+    pub fn extend_json(&mut self, json: &Value) -> Result<()> {
+        let entries = json.as_array().context("expected an array of labels")?;
+        for (index, entry) in entries.iter().enumerate() {
+            let parse = || -> Result<(String, Label)> {
+                let address = entry["address"].as_str().context("expected address")?;
+                let name = entry["name"].as_str().context("expected name")?;
+                let category = entry["category"].as_str().unwrap_or_default();
+                Ok((address.to_string(), label(name, category)?))
+            };
+            let (address, label) = parse().with_context(|| format!("in label {index}"))?;
+            self.insert(address, label);
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, address: String, label: Label) {
+        self.labels.insert(address, label);
+    }
+
+    pub fn get(&self, address: &str) -> Option<&Label> {
+        self.labels.get(address)
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Label both sides of a transfer by their owner, or by their token account when the owner has no label
+    pub fn label_transfer(&self, transfer: &mut Transfer) {
+        let label = |owner: &str, account: &str| self.get(owner).or(self.get(account)).cloned();
+        transfer.source_label = label(&transfer.source_owner, &transfer.source_account);
+        transfer.destination_label =
+            label(&transfer.destination_owner, &transfer.destination_account);
+    }
+}
+
+fn label(name: &str, category: &str) -> Result<Label> {
+    if name.is_empty() {
+        bail!("expected a name");
+    }
+    Ok(Label {
+        name: name.to_string(),
+        category: Category::parse(category)?,
+    })
+}
@@ -1,212 +1,398 @@
-use anyhow::{bail, Result};
-use instructions::handle_parsed_instruction;
-use solana_client::{rpc_client::RpcClient, rpc_config::RpcBlockConfig};
-use solana_sdk::commitment_config::CommitmentConfig;
+use analysis::{Analysis, AnalysisSink};
+use anyhow::{bail, Context, Result};
+use config::Config;
+use decode::{decode_transaction, DecodedTransaction};
+use dedup::{DedupSink, Deduplicator};
+use instructions::{handle_instruction, Transfer};
+use metrics::{metrics, Metrics};
+use monitor::Monitor;
+use record::Recorder;
+use rpc_pool::RpcPool;
+use serde_json::json;
+use shutdown::Shutdown;
+use sinks::{TextSink, TransferSink};
+use solana_client::{
+    rpc_config::{RpcBlockConfig, RpcTransactionConfig},
+    rpc_request::RpcRequest,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
 use solana_transaction_status::{
-    option_serializer::OptionSerializer, EncodedTransaction, EncodedTransactionWithStatusMeta,
-    TransactionDetails, UiConfirmedBlock, UiInstruction, UiMessage, UiParsedInstruction,
-    UiTransactionEncoding,
+    option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta,
+    EncodedTransactionWithStatusMeta, TransactionDetails, UiConfirmedBlock, UiTransactionEncoding,
+    UiTransactionTokenBalance,
 };
+use sources::BlockSource;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io::{self, Write},
-    thread::sleep,
-    time::{Duration, Instant},
+    str::FromStr,
 };
-use tracing::{debug, info, trace};
+use tracing::{debug, info};
 use tracing_subscriber::EnvFilter;
 
+pub mod alerts;
+pub mod analysis;
+pub mod checkpoint;
+pub mod config;
+pub mod dashboard;
+pub mod decode;
+pub mod dedup;
+pub mod flows;
+pub mod graph;
+pub mod inspect;
 pub mod instructions;
+pub mod labels;
+pub mod lookup_tables;
+pub mod metrics;
+pub mod mints;
+pub mod monitor;
+pub mod patterns;
+pub mod programs;
+pub mod reconcile;
+pub mod record;
+pub mod retry;
+pub mod rpc_pool;
+pub mod screening;
+pub mod shutdown;
+pub mod sinks;
+pub mod sources;
+pub mod sqlite;
+pub mod swaps;
 pub mod utils;
 
-const USDC_MINT_ADDRESS: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
-
-// https://solana.com/docs/core/clusters
-const RATE_LIMIT_PERIOD: u64 = 10;
-// const MAX_REQUESTS_PER_PERIOD: usize = 100;
-const MAX_REQUESTS_PER_PERIOD: usize = 40;
+pub const USDC_MINT_ADDRESS: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
 
+/// Poll mainnet and write USDC transfers to stdout until SIGINT or SIGTERM, logging at the level in `RUST_LOG`
+///
+/// See `monitor::Monitor` to configure the monitor or consume its events instead.
 pub fn run() -> Result<()> {
     if let Ok(level) = std::env::var("RUST_LOG") {
-        tracing_subscriber::fmt()
+        // Already set up by an application that installed its own subscriber
+        let _ = tracing_subscriber::fmt()
             .with_env_filter(EnvFilter::new(format!("solana_transfer_monitor={level}")))
-            .init();
+            .try_init();
     }
+    let config = Config::default();
+    let mut sink = TextSink::new(io::stdout().lock(), config.show_programs);
+    let monitor = Monitor::builder()
+        .config(config)
+        .shutdown(Shutdown::on_signals()?)
+        .build()?;
+    for event in monitor {
+        event?.send_to(&mut sink)?;
+    }
+    sink.flush()
+}
 
-    let rpc_url = "https://api.mainnet-beta.solana.com".to_string();
-    // let rpc_url = "https://api.devnet.solana.com".to_string();
-    let client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::finalized());
-
-    // Possible solution to rate limiting but doesn't appear to work for get_block
-    // let client = RpcClient::new_with_timeout_and_commitment(
-    //     rpc_url,
-    //     Duration::from_secs(2),
-    //     CommitmentConfig::finalized(),
-    // );
-
-    let rpc_block_config = make_block_config();
-    let mut starting_slot = client.get_slot()?;
-
-    let stdout = io::stdout();
-    let mut handle = stdout.lock();
-
-    let mut request_instants: Vec<Instant> = Vec::new();
-
-    loop {
-        let iteration_start = Instant::now();
-
-        check_request_instants(&mut request_instants);
-
-        let slots = client.get_blocks(starting_slot, None)?;
-        debug!("client.get_blocks slots.len(): {}", slots.len());
-        request_instants.push(Instant::now());
-
-        if let Some(last_slot) = slots.last() {
-            trace!("increment starting slot");
-            starting_slot = last_slot + 1;
-        } else {
-            trace!("no slots returned, waiting 500ms");
-            sleep(Duration::from_millis(500));
-            continue;
+/// Write the transfers of blocks from a source until it is exhausted or `config.max_blocks` blocks have been written
+pub(crate) fn write_source_transfers<S: BlockSource, W: Write>(
+    source: &mut S,
+    config: &Config,
+    writer: &mut W,
+) -> Result<()> {
+    let mut sink = AnalysisSink::new(
+        TextSink::new(writer, config.show_programs),
+        Analysis::new(config)?,
+    );
+    match &config.dedup {
+        Some(dedup) => {
+            let mut dedup = Deduplicator::open(dedup)?;
+            process_source(source, config, &mut DedupSink::new(sink, &mut dedup))
         }
+        None => process_source(source, config, &mut sink),
+    }
+}
 
-        for slot in slots.clone() {
-            check_request_instants(&mut request_instants);
-
-            let get_block_start = Instant::now();
-            trace!("request block {slot}");
-            let block = client.get_block_with_config(slot, rpc_block_config)?;
-            request_instants.push(Instant::now());
-            trace!(
-                "get_block_with_config took: {:?}",
-                get_block_start.elapsed()
-            );
-            write_block_transfers(block, slot, &mut handle)?;
+/// Send the transfers of blocks from a source to a sink until it is exhausted or `config.max_blocks` blocks have been processed, flushing the sink after every block
+pub fn process_source<S: BlockSource, K: TransferSink + ?Sized>(
+    source: &mut S,
+    config: &Config,
+    sink: &mut K,
+) -> Result<()> {
+    let recorder = config.record_dir.as_ref().map(Recorder::new).transpose()?;
+    let mut blocks_written = 0;
+    while config.max_blocks != Some(blocks_written) {
+        let Some((slot, block)) = source.next_block()? else {
+            break;
+        };
+        // Recorded after the source resolved lookup tables, so replaying never needs the RPC
+        if let Some(recorder) = &recorder {
+            recorder.record(slot, &block)?;
         }
-
-        trace!("loop iteration elapsed in {:?}", iteration_start.elapsed());
+        process_block(block, slot, config, sink)?;
+        sink.flush()?;
+        blocks_written += 1;
     }
+    Ok(())
 }
 
 pub fn make_block_config() -> RpcBlockConfig {
-    let mut rpc_block_config = RpcBlockConfig::default();
-    rpc_block_config.encoding = Some(UiTransactionEncoding::JsonParsed);
-    rpc_block_config.transaction_details = Some(TransactionDetails::Full);
-    rpc_block_config.max_supported_transaction_version = Some(0);
-    rpc_block_config
+    make_block_config_with_encoding(UiTransactionEncoding::JsonParsed)
 }
 
-fn check_request_instants(request_instants: &mut Vec<Instant>) {
-    trace!("checking request instants");
-    // Remove requests older than the rate limit period (10secs)
-    for i in 0..request_instants.len() {
-        if request_instants[i].elapsed() < Duration::from_secs(RATE_LIMIT_PERIOD) {
-            request_instants.drain(..i);
-            break;
-        }
+/// Block config requesting full transaction details in the given encoding
+///
+/// Binary encodings such as `UiTransactionEncoding::Base64` are cheaper for the RPC to serve, transfers are then decoded from raw instruction data
+pub(crate) fn make_block_config_with_encoding(encoding: UiTransactionEncoding) -> RpcBlockConfig {
+    RpcBlockConfig {
+        encoding: Some(encoding),
+        transaction_details: Some(TransactionDetails::Full),
+        max_supported_transaction_version: Some(0),
+        ..Default::default()
     }
-    if request_instants.len() > MAX_REQUESTS_PER_PERIOD {
-        // Exceeded rate limit so wait a while before the next iteration to avoid rate limiting
-        trace!("exceeded rate limit, waiting 500ms");
-        sleep(Duration::from_millis(500));
-        check_request_instants(request_instants);
+}
+
+/// Owner, mint and decimals of every token account with a balance before the transaction, by address
+///
+/// Accounts without an owner are only tolerated for untracked mints, whose transfers are only needed to recognise swaps
+pub(crate) fn token_accounts(
+    pre_token_balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+    account_keys: &[String],
+    config: &Config,
+) -> Result<HashMap<String, (String, String, u8)>> {
+    let OptionSerializer::Some(token_balances) = pre_token_balances else {
+        bail!("expected OptionSerializer::Some");
+    };
+    let mut accounts_map = HashMap::new();
+    // Transfers of every mint are decoded so swaps out of or into USDC can be recognised
+    for token_balance in token_balances {
+        let Some(pub_key) = account_keys.get(token_balance.account_index as usize) else {
+            bail!("token balance account index out of range of account keys");
+        };
+        let owner = match &token_balance.owner {
+            OptionSerializer::Some(owner) => owner.clone(),
+            _ if !config.tracks_mint(&token_balance.mint) => continue,
+            _ => bail!("expected OptionSerializer::Some"),
+        };
+        accounts_map.insert(
+            pub_key.clone(),
+            (
+                owner,
+                token_balance.mint.clone(),
+                token_balance.ui_token_amount.decimals,
+            ),
+        );
     }
+    Ok(accounts_map)
 }
 
-fn write_transaction_transfers<W: Write>(
+fn process_transaction<K: TransferSink + ?Sized>(
     transaction: EncodedTransactionWithStatusMeta,
-    writer: &mut W,
+    config: &Config,
+    sink: &mut K,
+    metrics: Option<&Metrics>,
 ) -> Result<()> {
-    let signature = match &transaction.transaction {
-        EncodedTransaction::Json(ui_transaction) => ui_transaction.signatures.clone(),
-        _ => bail!("expected EncodedTransaction::Json"),
-    };
+    if let Some(meta) = transaction.meta {
+        if meta.err.is_none() {
+            let decoded = decode_transaction(&transaction.transaction, &meta.loaded_addresses)?;
+            let DecodedTransaction {
+                signatures: signature,
+                account_keys,
+                signers,
+                ..
+            } = &decoded;
 
-    let mut accounts_map = HashMap::new();
+            let accounts_map = token_accounts(&meta.pre_token_balances, account_keys, config)?;
+            let OptionSerializer::Some(inner_instructions) = &meta.inner_instructions else {
+                bail!("expected OptionSerializer::Some");
+            };
 
-    let parsed_accounts = match transaction.transaction {
-        EncodedTransaction::Json(ui_transaction) => match ui_transaction.message {
-            UiMessage::Parsed(ui_parsed_message) => ui_parsed_message.account_keys,
-            _ => bail!("expected UiMessage::Parsed"),
-        },
-        _ => bail!("expected EncodedTransaction::Json"),
-    };
+            let mut transfers = Vec::new();
+            for executed in decoded.executed_instructions(inner_instructions)? {
+                if let Some(transfer) = handle_instruction(&executed, account_keys, &accounts_map)?
+                {
+                    transfers.push(transfer);
+                }
+            }
+            if !transfers.is_empty() {
+                debug!("tx signature: {signature:?}");
+            }
+            for transfer in &mut transfers {
+                transfer.signature = signature.first().cloned().unwrap_or_default();
+                config.labels.label_transfer(transfer);
+                if let Some(metrics) = metrics {
+                    metrics.record_transfer(transfer);
+                }
+            }
 
-    if let Some(meta) = transaction.meta {
-        if meta.err.is_none() {
-            match meta.pre_token_balances {
-                OptionSerializer::Some(token_balances) => {
-                    for token_balance in token_balances {
-                        if token_balance.mint == USDC_MINT_ADDRESS {
-                            let pub_key = parsed_accounts[token_balance.account_index as usize]
-                                .pubkey
-                                .clone();
-                            let owner = match token_balance.owner {
-                                OptionSerializer::Some(owner) => owner,
-                                _ => bail!("expected OptionSerializer::Some"),
-                            };
-                            accounts_map.insert(pub_key, (owner, token_balance.mint));
-                        }
-                    }
+            let swaps = if config.detect_swaps {
+                swaps::detect_swaps(&transfers, signers)
+                    .into_iter()
+                    .filter(|swap| {
+                        config.tracks_mint(&swap.in_mint) || config.tracks_mint(&swap.out_mint)
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let hidden_legs = if config.hide_swap_legs {
+                swaps
+                    .iter()
+                    .flat_map(|swap| swap.legs.iter().copied())
+                    .collect()
+            } else {
+                HashSet::new()
+            };
+
+            for (index, transfer) in transfers.iter().enumerate() {
+                if !config.tracks_mint(&transfer.mint) || hidden_legs.contains(&index) {
+                    continue;
                 }
-                _ => bail!("expected OptionSerializer::Some"),
+                sink.on_transfer(transfer)?;
+            }
+            for swap in &swaps {
+                sink.on_swap(swap)?;
             }
 
-            let mut first_transfer = true;
-
-            match meta.inner_instructions {
-                OptionSerializer::Some(intructions_vec) => {
-                    for instructions in intructions_vec {
-                        for instruction in instructions.instructions {
-                            match instruction {
-                                UiInstruction::Compiled(_) => {
-                                    bail!("expected UiInstruction::Parsed")
-                                }
-                                UiInstruction::Parsed(ui_instruction_parsed) => {
-                                    match ui_instruction_parsed {
-                                        UiParsedInstruction::Parsed(parsed_instruction) => {
-                                            if parsed_instruction.program == "spl-token" {
-                                                let transfer = handle_parsed_instruction(
-                                                    parsed_instruction.parsed,
-                                                    &mut accounts_map,
-                                                )?;
-                                                if let Some(transfer) = transfer {
-                                                    if first_transfer {
-                                                        debug!("tx signature: {signature:?}");
-                                                        first_transfer = false;
-                                                    }
-                                                    writeln!(writer, "{transfer}")?;
-                                                }
-                                            }
-                                        }
-                                        UiParsedInstruction::PartiallyDecoded(_) => {}
-                                    }
-                                }
-                            }
-                        }
-                    }
+            if config.reconcile {
+                let balance_deltas =
+                    reconcile::balance_deltas(&meta.pre_token_balances, &meta.post_token_balances)?;
+                let usdc_transfers = transfers
+                    .into_iter()
+                    .filter(Transfer::is_usdc)
+                    .collect::<Vec<_>>();
+                let transfer_deltas = reconcile::transfer_deltas(&usdc_transfers);
+                let signature = signature.first().map(String::as_str).unwrap_or_default();
+                for discrepancy in
+                    reconcile::reconcile_transaction(signature, &balance_deltas, &transfer_deltas)
+                {
+                    sink.on_discrepancy(&discrepancy)?;
                 }
-                _ => bail!("expected OptionSerializer::Some"),
             }
         }
     }
     Ok(())
 }
 
+/// Fetch a transaction by signature from the configured RPC endpoints, `None` when the RPC does not know it
+pub fn fetch_transaction(
+    config: &Config,
+    signature: &str,
+) -> Result<Option<EncodedConfirmedTransactionWithStatusMeta>> {
+    let signature = Signature::from_str(signature)
+        .with_context(|| format!("invalid transaction signature {signature}"))?;
+    let transaction_config = RpcTransactionConfig {
+        encoding: Some(config.encoding),
+        commitment: Some(CommitmentConfig::finalized()),
+        max_supported_transaction_version: Some(0),
+    };
+    // `get_transaction_with_config` fails to deserialize the null result for an unknown signature
+    RpcPool::new(config)?.call("getTransaction", |client| {
+        Ok(client.send(
+            RpcRequest::GetTransaction,
+            json!([signature.to_string(), transaction_config]),
+        )?)
+    })
+}
+
+/// Write the transfers of one transaction, as `write_block_transfers` does for each transaction of a block
+pub fn write_transaction_transfers<W: Write>(
+    transaction: EncodedTransactionWithStatusMeta,
+    config: &Config,
+    writer: &mut W,
+) -> Result<()> {
+    let mut sink = TextSink::new(writer, config.show_programs);
+    process_transaction(transaction, config, &mut sink, None)?;
+    sink.flush()
+}
+
 pub fn write_block_transfers<W: Write>(
     block: UiConfirmedBlock,
     slot: u64,
     writer: &mut W,
 ) -> Result<()> {
-    writeln!(writer, "Latest block: {slot}")?;
+    write_block_transfers_with_config(block, slot, &Config::default(), writer)
+}
+
+/// Write the transfers of a block, skipping events whose key is in the `config.dedup` store, with screening hits and patterns
+///
+/// The store, denylist and pattern window are opened for this block only, `process_source` or a `monitor::Monitor` keep them across blocks.
+pub(crate) fn write_block_transfers_with_config<W: Write>(
+    block: UiConfirmedBlock,
+    slot: u64,
+    config: &Config,
+    writer: &mut W,
+) -> Result<()> {
+    match &config.dedup {
+        Some(dedup) => write_block_transfers_with_dedup(
+            block,
+            slot,
+            config,
+            &mut Deduplicator::open(dedup)?,
+            writer,
+        ),
+        None => process_block(
+            block,
+            slot,
+            config,
+            &mut AnalysisSink::new(
+                TextSink::new(writer, config.show_programs),
+                Analysis::new(config)?,
+            ),
+        ),
+    }
+}
+
+/// Write the transfers of a block, skipping events `dedup` has seen, its keys are persisted once the block was written
+///
+/// Patterns are only detected within the block, wrap one `analysis::AnalysisSink` in a `DedupSink` to detect them across blocks.
+pub(crate) fn write_block_transfers_with_dedup<W: Write>(
+    block: UiConfirmedBlock,
+    slot: u64,
+    config: &Config,
+    dedup: &mut Deduplicator,
+    writer: &mut W,
+) -> Result<()> {
+    let sink = AnalysisSink::new(
+        TextSink::new(writer, config.show_programs),
+        Analysis::new(config)?,
+    );
+    process_block(block, slot, config, &mut DedupSink::new(sink, dedup))
+}
+
+/// Decode the transfers of a block and send them to a sink, bracketed by `on_block_start` and `on_block_end`
+///
+/// Screening hits and patterns are added by wrapping the sink in an `analysis::AnalysisSink`.
+pub fn process_block<K: TransferSink + ?Sized>(
+    block: UiConfirmedBlock,
+    slot: u64,
+    config: &Config,
+    sink: &mut K,
+) -> Result<()> {
+    let timer = metrics().block_processing_seconds.start_timer();
+    decode_block(block, slot, config, sink, Some(metrics()))?;
+    timer.observe_duration();
+    metrics().record_block();
+    Ok(())
+}
+
+/// The transfers `process_block` would emit, without recording metrics
+pub(crate) fn block_transfers(
+    block: UiConfirmedBlock,
+    slot: u64,
+    config: &Config,
+) -> Result<Vec<Transfer>> {
+    let mut transfers = Vec::new();
+    decode_block(block, slot, config, &mut transfers, None)?;
+    Ok(transfers)
+}
+
+fn decode_block<K: TransferSink + ?Sized>(
+    block: UiConfirmedBlock,
+    slot: u64,
+    config: &Config,
+    sink: &mut K,
+    metrics: Option<&Metrics>,
+) -> Result<()> {
+    sink.on_block_start(slot, block.block_time)?;
 
     if let Some(transactions) = block.transactions {
         for transaction in transactions {
-            write_transaction_transfers(transaction, writer)?;
+            process_transaction(transaction, config, sink, metrics)?;
         }
     } else {
         info!("no transactions found for block in slot {slot}");
     }
 
-    Ok(())
+    sink.on_block_end(slot)
 }
@@ -0,0 +1,166 @@
+use anyhow::{bail, Context, Result};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{address_lookup_table::state::AddressLookupTable, pubkey::Pubkey};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedTransaction, EncodedTransactionWithStatusMeta,
+    UiAddressTableLookup, UiConfirmedBlock, UiLoadedAddresses, UiMessage,
+};
+use std::collections::HashMap;
+use tracing::trace;
+
+use crate::{metrics::metrics, rpc_pool::RpcPool};
+
+/// Fetch the addresses stored in an address lookup table account
+pub trait LookupTableFetcher {
+    fn fetch_lookup_table(&mut self, table: &str) -> Result<Vec<String>>;
+}
+
+fn get_lookup_table(client: &RpcClient, table: &str) -> Result<Vec<String>> {
+    let table = table.parse::<Pubkey>()?;
+    let account = client.get_account(&table)?;
+    let lookup_table = AddressLookupTable::deserialize(&account.data)?;
+    Ok(lookup_table
+        .addresses
+        .iter()
+        .map(ToString::to_string)
+        .collect())
+}
+
+impl LookupTableFetcher for RpcClient {
+    fn fetch_lookup_table(&mut self, table: &str) -> Result<Vec<String>> {
+        metrics().record_rpc("getAccountInfo", get_lookup_table(self, table))
+    }
+}
+
+/// Fails over and retries like every other request of the pool
+impl LookupTableFetcher for RpcPool {
+    fn fetch_lookup_table(&mut self, table: &str) -> Result<Vec<String>> {
+        self.call("getAccountInfo", |client| get_lookup_table(client, table))
+    }
+}
+
+/// Cache of on-chain address lookup tables, used to resolve the loaded addresses of v0 transactions when the RPC did not include `loadedAddresses` in the transaction meta
+///
+/// Tables are fetched in their current state, not as they were at the slot of the block. A table can be deactivated, closed and recreated at the same address with other addresses, so blocks older than the last change of a table resolve to the wrong accounts, or fail when the table no longer exists. Prefer an RPC that returns `loadedAddresses` when decoding old blocks.
+#[derive(Default)]
+pub struct LookupTableCache {
+    tables: HashMap<String, Vec<String>>,
+    fetches: usize,
+}
+
+impl LookupTableCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of lookup tables fetched since the cache was created
+    pub fn fetches(&self) -> usize {
+        self.fetches
+    }
+
+    fn lookup(
+        &mut self,
+        fetcher: &mut impl LookupTableFetcher,
+        table: &str,
+        index: u8,
+    ) -> Result<String> {
+        // A table may have been extended since it was cached, so it is refetched when too short for the index. Other changes are not detected
+        let cached = self
+            .tables
+            .get(table)
+            .is_some_and(|addresses| (index as usize) < addresses.len());
+        if !cached {
+            trace!("fetch lookup table {table}");
+            let addresses = fetcher.fetch_lookup_table(table)?;
+            self.fetches += 1;
+            self.tables.insert(table.to_string(), addresses);
+        }
+        match self.tables[table].get(index as usize) {
+            Some(address) => Ok(address.clone()),
+            None => bail!("index {index} out of range of lookup table {table}"),
+        }
+    }
+
+    /// Resolve table lookups in the order the runtime loads them, all writable addresses followed by all readonly addresses
+    pub fn resolve(
+        &mut self,
+        fetcher: &mut impl LookupTableFetcher,
+        lookups: &[UiAddressTableLookup],
+    ) -> Result<UiLoadedAddresses> {
+        let mut loaded_addresses = UiLoadedAddresses::default();
+        for lookup in lookups {
+            for index in &lookup.writable_indexes {
+                let address = self.lookup(fetcher, &lookup.account_key, *index)?;
+                loaded_addresses.writable.push(address);
+            }
+        }
+        for lookup in lookups {
+            for index in &lookup.readonly_indexes {
+                let address = self.lookup(fetcher, &lookup.account_key, *index)?;
+                loaded_addresses.readonly.push(address);
+            }
+        }
+        Ok(loaded_addresses)
+    }
+
+    /// Fill in `loadedAddresses` for every transaction in the block that uses lookup tables but is missing them
+    pub fn fill_loaded_addresses(
+        &mut self,
+        fetcher: &mut impl LookupTableFetcher,
+        block: &mut UiConfirmedBlock,
+    ) -> Result<()> {
+        if let Some(transactions) = &mut block.transactions {
+            for transaction in transactions {
+                self.fill_transaction_loaded_addresses(fetcher, transaction)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_transaction_loaded_addresses(
+        &mut self,
+        fetcher: &mut impl LookupTableFetcher,
+        transaction: &mut EncodedTransactionWithStatusMeta,
+    ) -> Result<()> {
+        let Some(meta) = &mut transaction.meta else {
+            return Ok(());
+        };
+        if let OptionSerializer::Some(_) = meta.loaded_addresses {
+            return Ok(());
+        }
+        // Parsed account keys already include the addresses loaded from lookup tables
+        if let EncodedTransaction::Json(ui_transaction) = &transaction.transaction {
+            if let UiMessage::Parsed(_) = ui_transaction.message {
+                return Ok(());
+            }
+        }
+        let lookups = address_table_lookups(&transaction.transaction)?;
+        if !lookups.is_empty() {
+            meta.loaded_addresses = OptionSerializer::Some(self.resolve(fetcher, &lookups)?);
+        }
+        Ok(())
+    }
+}
+
+/// Address table lookups of a transaction, empty for legacy transactions
+pub fn address_table_lookups(
+    transaction: &EncodedTransaction,
+) -> Result<Vec<UiAddressTableLookup>> {
+    Ok(match transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Parsed(ui_parsed_message) => ui_parsed_message.address_table_lookups.clone(),
+            UiMessage::Raw(ui_raw_message) => ui_raw_message.address_table_lookups.clone(),
+        }
+        .unwrap_or_default(),
+        EncodedTransaction::LegacyBinary(_) | EncodedTransaction::Binary(_, _) => transaction
+            .decode()
+            .context("failed to decode binary transaction")?
+            .message
+            .address_table_lookups()
+            .unwrap_or_default()
+            .iter()
+            .map(UiAddressTableLookup::from)
+            .collect(),
+        EncodedTransaction::Accounts(_) => bail!("expected full transaction details"),
+    })
+}
@@ -1,8 +1,596 @@
-use solana_transfer_monitor::run;
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use solana_transaction_status::UiTransactionEncoding;
+use solana_transfer_monitor::{
+    alerts::{AlertRules, AlertSink},
+    config::Config,
+    dashboard,
+    dedup::{self, DedupConfig},
+    fetch_transaction,
+    flows::{FlowAggregator, FlowSink, DEFAULT_CAPACITY, DEFAULT_WINDOWS},
+    graph::{Direction, GraphFormat, TransferGraph},
+    inspect::inspect_transaction,
+    metrics,
+    monitor::{Event, Monitor},
+    patterns::PatternConfig,
+    process_block,
+    record::ReplayBlockSource,
+    retry::{classify, ErrorClass},
+    rpc_pool::RpcEndpoint,
+    shutdown::Shutdown,
+    sinks::{FanOut, JsonlSink, TextSink, TransferSink},
+    sources::{stdin_source, FileBlockSource, RpcBlockSource},
+    sqlite::SqliteSink,
+    utils::verify_block,
+};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+use tracing_subscriber::EnvFilter;
 
-fn main() {
-    match run() {
-        Ok(_) => {}
-        Err(e) => eprintln!("{e}"),
+/// Net inflows and outflows listed per window in flow summaries
+const FLOW_SUMMARY_TOP: usize = 5;
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  success, including stopping on SIGINT or SIGTERM
+  1  error, e.g. an RPC request failing on every endpoint after all retries
+  2  invalid arguments
+  3  verify found a mismatch
+  4  block or transaction not found";
+
+const VERIFY_FAILED: u8 = 3;
+const NOT_FOUND: u8 = 4;
+
+/// Watch Solana for USDC transfers
+#[derive(Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true, after_help = EXIT_CODES)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Options of `watch`, which runs when no subcommand is given
+    #[command(flatten)]
+    watch: WatchArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Follow the chain tip, writing transfers as blocks are finalized (the default)
+    Watch(WatchArgs),
+    /// Replay blocks saved with --record
+    Replay {
+        dir: PathBuf,
+        /// Multiple of the original block times to replay at, 0 for no delay
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+        #[command(flatten)]
+        watch: WatchArgs,
+    },
+    /// Show a live transfer feed, top senders and receivers, volume and RPC status in the terminal
+    Dashboard {
+        /// Minutes of transfers the top senders, top receivers and volume cover
+        #[arg(long, value_name = "MINUTES", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+        window: u64,
+        #[command(flatten)]
+        watch: WatchArgs,
+    },
+    /// Write the transfers of one block
+    Block {
+        slot: u64,
+        #[command(flatten)]
+        decode: DecodeArgs,
+    },
+    /// Explain how every instruction of a transaction was decoded and which transfers it produced
+    Tx {
+        signature: String,
+        #[command(flatten)]
+        decode: DecodeArgs,
+    },
+    /// Export the transfers of a slot range as a graph of owners, with amount and count per edge
+    Graph {
+        first_slot: u64,
+        last_slot: u64,
+        /// File to write, .graphml, .gexf or .dot/.gv
+        #[arg(long, short, value_name = "FILE")]
+        output: PathBuf,
+        /// Only export transfers within --hops of this owner
+        #[arg(long, value_name = "ADDRESS")]
+        trace: Option<String>,
+        /// Transfers to follow from the traced owner
+        #[arg(long, default_value_t = 2, requires = "trace")]
+        hops: usize,
+        /// Follow where the traced owner's tokens went, or backward to where they came from
+        #[arg(long, value_enum, default_value_t = TraceDirection::Forward, requires = "trace")]
+        direction: TraceDirection,
+        #[command(flatten)]
+        decode: DecodeArgs,
+    },
+    /// Cross-check the USDC transfers decoded from a block against a text search of the block
+    Verify {
+        slot: u64,
+        #[command(flatten)]
+        rpc: RpcArgs,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TraceDirection {
+    Forward,
+    Backward,
+}
+
+impl From<TraceDirection> for Direction {
+    fn from(direction: TraceDirection) -> Self {
+        match direction {
+            TraceDirection::Forward => Direction::Forward,
+            TraceDirection::Backward => Direction::Backward,
+        }
+    }
+}
+
+#[derive(Args)]
+struct RpcArgs {
+    /// JSON-RPC endpoint, repeat for failover and weighted round-robin of getBlock
+    #[arg(long = "rpc-url", value_name = "URL[#WEIGHT]", value_parser = RpcEndpoint::parse)]
+    rpc_endpoints: Vec<RpcEndpoint>,
+    /// Seconds before a request fails over to the next endpoint
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    rpc_timeout: u64,
+    /// Attempts for requests failing on every endpoint, 1 disables retrying
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    max_attempts: u32,
+    /// Fetch address lookup tables on-chain for v0 transactions whose meta is missing loadedAddresses
+    #[arg(long)]
+    resolve_lookup_tables: bool,
+    /// Encoding of the transactions in requested blocks
+    #[arg(long, value_enum, default_value_t = Encoding::JsonParsed)]
+    encoding: Encoding,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Encoding {
+    #[value(name = "jsonParsed")]
+    JsonParsed,
+    Json,
+    Base64,
+    Base58,
+}
+
+impl From<Encoding> for UiTransactionEncoding {
+    fn from(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::JsonParsed => UiTransactionEncoding::JsonParsed,
+            Encoding::Json => UiTransactionEncoding::Json,
+            Encoding::Base64 => UiTransactionEncoding::Base64,
+            Encoding::Base58 => UiTransactionEncoding::Base58,
+        }
+    }
+}
+
+impl RpcArgs {
+    fn config(&self) -> Config {
+        let mut config = Config {
+            rpc_endpoints: self.rpc_endpoints.clone(),
+            rpc_timeout: Duration::from_secs(self.rpc_timeout),
+            resolve_lookup_tables: self.resolve_lookup_tables,
+            encoding: self.encoding.into(),
+            ..Default::default()
+        };
+        config.retry.max_attempts = self.max_attempts;
+        config
+    }
+}
+
+#[derive(Args)]
+struct DecodeArgs {
+    #[command(flatten)]
+    rpc: RpcArgs,
+    /// Compare decoded transfers with pre/post token balances and report any discrepancy
+    #[arg(long)]
+    reconcile: bool,
+    /// Append the programs that invoked each transfer
+    #[arg(long)]
+    show_programs: bool,
+    /// Write a swap event for every trader exchanging USDC for another mint
+    #[arg(long)]
+    detect_swaps: bool,
+    /// Only write swap events, not the transfers they are made of, implies --detect-swaps
+    #[arg(long)]
+    hide_swap_legs: bool,
+    /// CSV or JSON file naming owners and token accounts, overriding the bundled labels, can be repeated
+    #[arg(long, value_name = "FILE")]
+    labels: Vec<PathBuf>,
+}
+
+impl DecodeArgs {
+    fn config(&self) -> Result<Config> {
+        let mut config = Config {
+            reconcile: self.reconcile,
+            show_programs: self.show_programs,
+            detect_swaps: self.detect_swaps || self.hide_swap_legs,
+            hide_swap_legs: self.hide_swap_legs,
+            ..self.rpc.config()
+        };
+        for path in &self.labels {
+            config.labels.load(path)?;
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Args)]
+struct WatchArgs {
+    #[command(flatten)]
+    decode: DecodeArgs,
+    /// Read blocks from a file, a directory of .json/.jsonl files or - for stdin instead of the RPC
+    #[arg(long, value_name = "PATH")]
+    input: Option<PathBuf>,
+    /// Also append every event as a JSON line to this file
+    #[arg(long, value_name = "FILE")]
+    jsonl: Option<PathBuf>,
+    /// Also insert every transfer, swap and discrepancy into this SQLite database
+    #[arg(long, value_name = "FILE")]
+    sqlite: Option<PathBuf>,
+    /// Send whale alerts according to the rules in this JSON file
+    #[arg(long, value_name = "FILE")]
+    alerts: Option<PathBuf>,
+    /// Write the largest net inflows and outflows over the last 1m, 1h and 24h every this many seconds
+    #[arg(long, value_name = "SECS")]
+    flow_summary: Option<u64>,
+    /// Addresses tracked per flow window, the smallest are evicted beyond this
+    #[arg(long, value_name = "ADDRESSES", default_value_t = DEFAULT_CAPACITY)]
+    flow_capacity: usize,
+    /// Report round trips, cycles and repeated equal transfers between owners
+    #[arg(long)]
+    detect_patterns: bool,
+    /// Slots of earlier transfers compared with each new one
+    #[arg(long, value_name = "SLOTS", default_value_t = PatternConfig::default().window_slots)]
+    pattern_window: u64,
+    /// Longest cycle reported, in owners
+    #[arg(long, value_name = "OWNERS", default_value_t = PatternConfig::default().max_cycle_length)]
+    max_cycle_length: usize,
+    /// Equal transfers back and forth between two owners before they are reported as ping-pong
+    #[arg(long, value_name = "TRANSFERS", default_value_t = PatternConfig::default().ping_pong_repeats)]
+    ping_pong_repeats: usize,
+    /// Write each transfer, swap and discrepancy once, even when blocks are fetched again or overlap
+    #[arg(long)]
+    dedup: bool,
+    /// Most recent events remembered for --dedup
+    #[arg(long, value_name = "EVENTS", default_value_t = dedup::DEFAULT_CAPACITY)]
+    dedup_capacity: usize,
+    /// Remember emitted events in this file across restarts, implies --dedup
+    #[arg(long, value_name = "FILE")]
+    dedup_store: Option<PathBuf>,
+    /// Report transfers from or to the addresses in this CSV, reloaded when the file changes
+    #[arg(long, value_name = "FILE")]
+    denylist: Option<PathBuf>,
+    /// Append denylist hits and reloads to this file
+    #[arg(long, value_name = "FILE", requires = "denylist")]
+    audit_log: Option<PathBuf>,
+    /// Save every fetched block to this directory so it can be replayed
+    #[arg(long, value_name = "DIR")]
+    record: Option<PathBuf>,
+    /// Save the last processed slot to this file on stop and resume after it on start
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<PathBuf>,
+    /// Serve Prometheus metrics, /healthz and /readyz on this address
+    #[arg(long, value_name = "ADDR")]
+    metrics_addr: Option<String>,
+    /// Seconds without a processed block before /healthz fails
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    max_block_age: u64,
+    /// Slots behind the RPC before /readyz fails
+    #[arg(long, value_name = "SLOTS", default_value_t = 150)]
+    max_slot_lag: u64,
+    /// Fetch every block from two endpoints and log an error when their transfers differ
+    #[arg(long)]
+    quorum: bool,
+}
+
+impl WatchArgs {
+    fn config(&self) -> Result<Config> {
+        Ok(Config {
+            jsonl_path: self.jsonl.clone(),
+            sqlite_path: self.sqlite.clone(),
+            alerts_path: self.alerts.clone(),
+            flow_summary_interval: self.flow_summary.map(Duration::from_secs),
+            flow_capacity: self.flow_capacity,
+            patterns: self.detect_patterns.then(|| PatternConfig {
+                window_slots: self.pattern_window,
+                max_cycle_length: self.max_cycle_length,
+                ping_pong_repeats: self.ping_pong_repeats,
+                ..PatternConfig::default()
+            }),
+            dedup: (self.dedup || self.dedup_store.is_some()).then(|| DedupConfig {
+                capacity: self.dedup_capacity,
+                path: self.dedup_store.clone(),
+            }),
+            denylist_path: self.denylist.clone(),
+            screening_audit_path: self.audit_log.clone(),
+            record_dir: self.record.clone(),
+            checkpoint_path: self.checkpoint.clone(),
+            metrics_addr: self.metrics_addr.clone(),
+            max_block_age: Duration::from_secs(self.max_block_age),
+            max_slot_lag: self.max_slot_lag,
+            quorum: self.quorum,
+            ..self.decode.config()?
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Ok(level) = std::env::var("RUST_LOG") {
+        let subscriber = tracing_subscriber::fmt()
+            .with_env_filter(EnvFilter::new(format!("solana_transfer_monitor={level}")));
+        // The dashboard owns stdout, so its logs can be redirected separately
+        if let Some(Command::Dashboard { .. }) = cli.command {
+            subscriber.with_writer(io::stderr).init();
+        } else {
+            subscriber.init();
+        }
+    }
+    let result = match cli.command {
+        None => watch(&cli.watch, None),
+        Some(Command::Watch(args)) => watch(&args, None),
+        Some(Command::Replay {
+            dir,
+            speed,
+            watch: args,
+        }) => watch(&args, Some((dir, speed))),
+        Some(Command::Dashboard {
+            window,
+            watch: args,
+        }) => dashboard(&args, Duration::from_secs(window * 60)),
+        Some(Command::Block { slot, decode }) => {
+            decode.config().and_then(|config| block(slot, &config))
+        }
+        Some(Command::Tx { signature, decode }) => {
+            decode.config().and_then(|config| tx(&signature, &config))
+        }
+        Some(Command::Graph {
+            first_slot,
+            last_slot,
+            output,
+            trace,
+            hops,
+            direction,
+            decode,
+        }) => decode.config().and_then(|config| {
+            let trace = trace.map(|seed| (seed, hops, direction.into()));
+            graph(first_slot..=last_slot, &output, trace, &config)
+        }),
+        Some(Command::Verify { slot, rpc }) => verify(slot, &rpc.config()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn watch(args: &WatchArgs, replay: Option<(PathBuf, f64)>) -> Result<ExitCode> {
+    let config = args.config()?;
+    let mut sink = FanOut::new().with_sink(
+        "stdout",
+        TextSink::new(io::stdout().lock(), config.show_programs),
+    );
+    if let Some(jsonl) = jsonl_sink(&config)? {
+        sink = sink.with_sink("jsonl", jsonl);
+    }
+    if let Some(path) = &config.sqlite_path {
+        sink = sink.with_sink("sqlite", SqliteSink::open(path)?);
+    }
+    if let Some(path) = &config.alerts_path {
+        sink = sink.with_sink("alerts", AlertSink::new(AlertRules::load(path)?)?);
+    }
+    let mut sink: Box<dyn TransferSink> = match config.flow_summary_interval {
+        Some(interval) => Box::new(FlowSink::new(
+            sink,
+            FlowAggregator::new(&DEFAULT_WINDOWS, config.flow_capacity),
+            interval,
+            FLOW_SUMMARY_TOP,
+        )),
+        None => Box::new(sink),
+    };
+    for event in build_monitor(args, config, replay, Shutdown::on_signals()?)? {
+        let event = event?;
+        event.send_to(&mut sink)?;
+        if let Event::BlockEnd { .. } = event {
+            sink.flush()?;
+        }
+    }
+    sink.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn dashboard(args: &WatchArgs, window: Duration) -> Result<ExitCode> {
+    let config = Config {
+        track_slot_lag: true,
+        ..args.config()?
+    };
+    let mut jsonl = jsonl_sink(&config)?;
+    let mut sqlite = config
+        .sqlite_path
+        .as_ref()
+        .map(SqliteSink::open)
+        .transpose()?;
+    let mut alerts = match &config.alerts_path {
+        Some(path) => Some(AlertSink::new(AlertRules::load(path)?)?),
+        None => None,
+    };
+    let shutdown = Shutdown::on_signals()?;
+    let monitor = build_monitor(args, config, None, shutdown.clone())?;
+    dashboard::run(monitor, shutdown, window, move |event| {
+        if let Some(jsonl) = &mut jsonl {
+            event.send_to(jsonl)?;
+            if let Event::BlockEnd { .. } = event {
+                jsonl.flush()?;
+            }
+        }
+        if let Some(sqlite) = &mut sqlite {
+            event.send_to(sqlite)?;
+        }
+        if let Some(alerts) = &mut alerts {
+            event.send_to(alerts)?;
+        }
+        Ok(())
+    })?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn jsonl_sink(config: &Config) -> Result<Option<JsonlSink<BufWriter<File>>>> {
+    let Some(path) = &config.jsonl_path else {
+        return Ok(None);
+    };
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    Ok(Some(JsonlSink::new(BufWriter::new(file))))
+}
+
+/// Serves metrics if configured and reads blocks from the replay directory, `--input` or the RPC
+fn build_monitor(
+    args: &WatchArgs,
+    config: Config,
+    replay: Option<(PathBuf, f64)>,
+    shutdown: Shutdown,
+) -> Result<Monitor> {
+    if let Some(addr) = &config.metrics_addr {
+        metrics::serve(addr, config.max_block_age, config.max_slot_lag)?;
+    }
+    let builder = Monitor::builder().config(config).shutdown(shutdown);
+    let builder = match (replay, &args.input) {
+        (Some((dir, speed)), _) => builder.source(ReplayBlockSource::new(&dir, speed)?),
+        (None, Some(input)) if input.as_os_str() == "-" => builder.source(stdin_source()),
+        (None, Some(input)) => builder.source(FileBlockSource::new(input)?),
+        (None, None) => builder,
+    };
+    builder.build()
+}
+
+fn fetch_block(
+    slot: u64,
+    config: &Config,
+) -> Result<Option<solana_transaction_status::UiConfirmedBlock>> {
+    let mut source = RpcBlockSource::new(&Config {
+        start_slot: Some(slot),
+        ..config.clone()
+    })?;
+    match source.fetch_block(slot) {
+        Ok(block) => Ok(Some(block)),
+        Err(e) if classify(&e) == ErrorClass::SkippedSlot => {
+            eprintln!("No block in slot {slot}: {e:#}");
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn block(slot: u64, config: &Config) -> Result<ExitCode> {
+    let Some(block) = fetch_block(slot, config)? else {
+        return Ok(ExitCode::from(NOT_FOUND));
+    };
+    // Not a position to resume from, unlike the blocks of `watch`
+    let monitor = Monitor::builder()
+        .config(Config {
+            checkpoint_path: None,
+            ..config.clone()
+        })
+        .source(VecDeque::from([(slot, block)]))
+        .build()?;
+    let mut sink = TextSink::new(io::stdout().lock(), config.show_programs);
+    for event in monitor {
+        event?.send_to(&mut sink)?;
+    }
+    sink.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn tx(signature: &str, config: &Config) -> Result<ExitCode> {
+    let Some(transaction) = fetch_transaction(config, signature)? else {
+        eprintln!("Transaction {signature} not found");
+        return Ok(ExitCode::from(NOT_FOUND));
+    };
+    print!("{}", inspect_transaction(&transaction.transaction, config)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn graph(
+    slots: RangeInclusive<u64>,
+    output: &Path,
+    trace: Option<(String, usize, Direction)>,
+    config: &Config,
+) -> Result<ExitCode> {
+    if slots.is_empty() {
+        bail!("the last slot is before the first");
+    }
+    let format = GraphFormat::from_path(output)?;
+    let mut source = RpcBlockSource::new(&Config {
+        start_slot: Some(*slots.start()),
+        ..config.clone()
+    })?;
+    let mut graph = TransferGraph::new();
+    for slot in slots {
+        match source.fetch_block(slot) {
+            Ok(block) => process_block(block, slot, config, &mut graph)?,
+            Err(e) if classify(&e) == ErrorClass::SkippedSlot => {
+                eprintln!("No block in slot {slot}: {e:#}");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if let Some((seed, hops, direction)) = trace {
+        graph = graph.trace(&seed, hops, direction);
+    }
+    let file =
+        File::create(output).with_context(|| format!("failed to create {}", output.display()))?;
+    let mut writer = BufWriter::new(file);
+    graph.write(format, &mut writer)?;
+    writer.flush()?;
+    eprintln!(
+        "Wrote {} owners and {} edges to {}",
+        graph.nodes().count(),
+        graph.edges().count(),
+        output.display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+fn verify(slot: u64, config: &Config) -> Result<ExitCode> {
+    let Some(block) = fetch_block(slot, config)? else {
+        return Ok(ExitCode::from(NOT_FOUND));
+    };
+    let verification = verify_block(block, slot, config)?;
+    println!(
+        "{} successful transactions in slot {slot} mention USDC, {} without decoded transfers",
+        verification.usdc_transactions.len(),
+        verification.without_transfers.len()
+    );
+    for signature in &verification.without_transfers {
+        println!("No transfers decoded from {signature}");
+    }
+    for signature in &verification.unexpected {
+        println!("Transfers decoded from {signature} which does not mention USDC");
+    }
+    for discrepancy in &verification.discrepancies {
+        println!("{discrepancy}");
+    }
+    if verification.passed() {
+        println!("Verification passed");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("Verification failed");
+        Ok(ExitCode::from(VERIFY_FAILED))
     }
 }
@@ -0,0 +1,298 @@
+use anyhow::{anyhow, Result};
+use prometheus::{
+    core::Collector, exponential_buckets, Counter, Encoder, Gauge, GaugeVec, Histogram,
+    HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::{
+    io,
+    net::SocketAddr,
+    sync::OnceLock,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tiny_http::{Header, Response, Server};
+use tracing::{info, warn};
+
+use crate::{instructions::Transfer, USDC_MINT_ADDRESS};
+
+/// Process wide metrics, recorded whether or not the `/metrics` endpoint is served
+pub struct Metrics {
+    registry: Registry,
+    pub blocks_processed: IntCounter,
+    /// Decoded transfers by mint address, including mints that are not written
+    pub transfers: IntCounterVec,
+    /// Sum of decoded USDC transfer amounts in whole USDC
+    pub usdc_volume: Counter,
+    pub rpc_requests: IntCounterVec,
+    pub rpc_errors: IntCounterVec,
+    /// Requests retried after every endpoint failed, by method
+    pub rpc_retries: IntCounterVec,
+    /// Moving average of request successes per endpoint host, from 0 to 1
+    pub rpc_endpoint_score: GaugeVec,
+    /// Blocks whose transfers differed between two endpoints in quorum mode
+    pub quorum_mismatches: IntCounter,
+    /// Alerts fired, by rule name
+    pub alerts: IntCounterVec,
+    /// Alerts that could not be delivered, by channel type
+    pub alert_failures: IntCounterVec,
+    /// Transfers skipped because their source and destination token accounts hold different mints
+    pub mint_mismatches: IntCounter,
+    /// Transfers, swaps and discrepancies dropped because their key was already emitted
+    pub duplicates_dropped: IntCounter,
+    /// Transfers involving a denylisted owner or token account
+    pub screening_hits: IntCounter,
+    /// Addresses in the denylist last loaded
+    pub denylist_entries: IntGauge,
+    /// Time spent sleeping in `check_request_instants` to stay under the RPC rate limit
+    pub rate_limit_wait_seconds: Counter,
+    /// Slots between the RPC's latest finalized slot and the last fetched block
+    pub slot_lag: IntGauge,
+    pub block_processing_seconds: Histogram,
+    /// Unix time the last block finished processing, 0 before the first block
+    pub last_block_timestamp: Gauge,
+    started: SystemTime,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| Metrics::new().expect("metric definitions are valid"))
+}
+
+impl Metrics {
+    fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some("transfer_monitor".to_string()), None)?;
+        let blocks_processed = IntCounter::new("blocks_processed_total", "Blocks processed")?;
+        let transfers = IntCounterVec::new(
+            Opts::new("transfers_total", "Token transfers decoded, by mint"),
+            &["mint"],
+        )?;
+        let usdc_volume = Counter::new("usdc_volume_total", "USDC transferred")?;
+        let rpc_requests = IntCounterVec::new(
+            Opts::new("rpc_requests_total", "JSON-RPC requests, by method"),
+            &["method"],
+        )?;
+        let rpc_errors = IntCounterVec::new(
+            Opts::new("rpc_errors_total", "Failed JSON-RPC requests, by method"),
+            &["method"],
+        )?;
+        let rpc_retries = IntCounterVec::new(
+            Opts::new("rpc_retries_total", "Retried JSON-RPC requests, by method"),
+            &["method"],
+        )?;
+        let rpc_endpoint_score = GaugeVec::new(
+            Opts::new(
+                "rpc_endpoint_score",
+                "Recent request success rate, by endpoint host",
+            ),
+            &["endpoint"],
+        )?;
+        let quorum_mismatches = IntCounter::new(
+            "quorum_mismatches_total",
+            "Blocks whose transfers differed between two endpoints",
+        )?;
+        let alerts = IntCounterVec::new(
+            Opts::new("alerts_total", "Alerts fired, by rule"),
+            &["rule"],
+        )?;
+        let alert_failures = IntCounterVec::new(
+            Opts::new(
+                "alert_failures_total",
+                "Alerts that could not be delivered, by channel",
+            ),
+            &["channel"],
+        )?;
+        let mint_mismatches = IntCounter::new(
+            "mint_mismatches_total",
+            "Transfers skipped because the source and destination mints differ",
+        )?;
+        let duplicates_dropped = IntCounter::new(
+            "duplicates_dropped_total",
+            "Events dropped because they were already emitted",
+        )?;
+        let screening_hits = IntCounter::new(
+            "screening_hits_total",
+            "Transfers involving a denylisted address",
+        )?;
+        let denylist_entries = IntGauge::new("denylist_entries", "Addresses in the denylist")?;
+        let rate_limit_wait_seconds = Counter::new(
+            "rate_limit_wait_seconds_total",
+            "Seconds waited to stay under the RPC rate limit",
+        )?;
+        let slot_lag = IntGauge::new(
+            "slot_lag",
+            "Latest finalized slot minus the slot of the last fetched block",
+        )?;
+        let block_processing_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "block_processing_seconds",
+                "Time to decode a block and emit its events",
+            )
+            .buckets(exponential_buckets(0.0005, 2.0, 14)?),
+        )?;
+        let last_block_timestamp = Gauge::new(
+            "last_block_timestamp_seconds",
+            "Unix time the last block finished processing",
+        )?;
+
+        registry.register(Box::new(blocks_processed.clone()))?;
+        registry.register(Box::new(transfers.clone()))?;
+        registry.register(Box::new(usdc_volume.clone()))?;
+        registry.register(Box::new(rpc_requests.clone()))?;
+        registry.register(Box::new(rpc_errors.clone()))?;
+        registry.register(Box::new(rpc_retries.clone()))?;
+        registry.register(Box::new(rpc_endpoint_score.clone()))?;
+        registry.register(Box::new(quorum_mismatches.clone()))?;
+        registry.register(Box::new(alerts.clone()))?;
+        registry.register(Box::new(alert_failures.clone()))?;
+        registry.register(Box::new(mint_mismatches.clone()))?;
+        registry.register(Box::new(duplicates_dropped.clone()))?;
+        registry.register(Box::new(screening_hits.clone()))?;
+        registry.register(Box::new(denylist_entries.clone()))?;
+        registry.register(Box::new(rate_limit_wait_seconds.clone()))?;
+        registry.register(Box::new(slot_lag.clone()))?;
+        registry.register(Box::new(block_processing_seconds.clone()))?;
+        registry.register(Box::new(last_block_timestamp.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            blocks_processed,
+            transfers,
+            usdc_volume,
+            rpc_requests,
+            rpc_errors,
+            rpc_retries,
+            rpc_endpoint_score,
+            quorum_mismatches,
+            alerts,
+            alert_failures,
+            mint_mismatches,
+            duplicates_dropped,
+            screening_hits,
+            denylist_entries,
+            rate_limit_wait_seconds,
+            slot_lag,
+            block_processing_seconds,
+            last_block_timestamp,
+            started: SystemTime::now(),
+        })
+    }
+
+    pub fn record_transfer(&self, transfer: &Transfer) {
+        self.transfers.with_label_values(&[&transfer.mint]).inc();
+        if transfer.mint == USDC_MINT_ADDRESS {
+            self.usdc_volume
+                .inc_by(transfer.amount as f64 / 10f64.powi(transfer.decimals.into()));
+        }
+    }
+
+    /// Count an RPC request and whether it failed, passing its result through
+    pub fn record_rpc<T, E>(&self, method: &str, result: Result<T, E>) -> Result<T, E> {
+        self.rpc_requests.with_label_values(&[method]).inc();
+        if result.is_err() {
+            self.rpc_errors.with_label_values(&[method]).inc();
+        }
+        result
+    }
+
+    /// Requests and failed requests summed over every method
+    pub fn rpc_totals(&self) -> (u64, u64) {
+        (
+            sum_counters(&self.rpc_requests),
+            sum_counters(&self.rpc_errors),
+        )
+    }
+
+    pub fn record_block(&self) {
+        self.blocks_processed.inc();
+        self.last_block_timestamp.set(unix_time(SystemTime::now()));
+    }
+
+    /// Fails when no block has been processed within `max_block_age`, counting from startup before the first block
+    pub fn check_health(&self, max_block_age: Duration) -> Result<(), String> {
+        let last_block = match self.last_block_timestamp.get() {
+            0.0 => unix_time(self.started),
+            timestamp => timestamp,
+        };
+        let age = unix_time(SystemTime::now()) - last_block;
+        if age > max_block_age.as_secs_f64() {
+            return Err(format!("no block processed for {age:.0}s"));
+        }
+        Ok(())
+    }
+
+    /// Fails until the first block has been processed and while the slot lag exceeds `max_slot_lag`, e.g. catching up from an old start slot
+    pub fn check_ready(&self, max_slot_lag: u64) -> Result<(), String> {
+        if self.blocks_processed.get() == 0 {
+            return Err("no block processed yet".to_string());
+        }
+        let slot_lag = self.slot_lag.get();
+        if slot_lag > max_slot_lag as i64 {
+            return Err(format!("{slot_lag} slots behind"));
+        }
+        Ok(())
+    }
+
+    /// Metrics in the Prometheus text exposition format
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+fn sum_counters(counters: &IntCounterVec) -> u64 {
+    counters
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .map(|metric| metric.get_counter().get_value() as u64)
+        .sum()
+}
+
+fn unix_time(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Serve `GET /metrics`, `/healthz` and `/readyz` on a background thread, returns the bound address so port 0 can be used
+///
+/// The health and readiness checks respond 503 with the reason when `Metrics::check_health` or `Metrics::check_ready` fail.
+pub fn serve(addr: &str, max_block_age: Duration, max_slot_lag: u64) -> Result<SocketAddr> {
+    let server = Server::http(addr).map_err(|e| anyhow!("failed to listen on {addr}: {e}"))?;
+    let addr = server
+        .server_addr()
+        .to_ip()
+        .ok_or_else(|| anyhow!("{addr} is not an IP address"))?;
+    info!("serving metrics on http://{addr}/metrics");
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = match request.url() {
+                "/metrics" => match metrics().encode() {
+                    Ok(body) => {
+                        let content_type =
+                            Header::from_bytes("Content-Type", TextEncoder::new().format_type())
+                                .expect("content type header is valid");
+                        Response::from_string(body).with_header(content_type)
+                    }
+                    Err(e) => Response::from_string(format!("{e:#}")).with_status_code(500),
+                },
+                "/healthz" => check_response(metrics().check_health(max_block_age)),
+                "/readyz" => check_response(metrics().check_ready(max_slot_lag)),
+                _ => Response::from_string("not found").with_status_code(404),
+            };
+            if let Err(e) = request.respond(response) {
+                warn!("failed to respond to HTTP request: {e}");
+            }
+        }
+    });
+    Ok(addr)
+}
+
+fn check_response(check: Result<(), String>) -> Response<io::Cursor<Vec<u8>>> {
+    match check {
+        Ok(()) => Response::from_string("ok"),
+        Err(reason) => Response::from_string(reason).with_status_code(503),
+    }
+}
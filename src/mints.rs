@@ -0,0 +1,97 @@
+use anyhow::{bail, Context, Result};
+use std::str::from_utf8;
+
+use crate::{instructions::format_amount, USDC_MINT_ADDRESS};
+
+/// Well-known mints, their symbols and decimals
+#[rustfmt::skip]
+pub const KNOWN_MINTS: [(&str, &str, u8); 5] = [
+    (USDC_MINT_ADDRESS, "USDC", 6),
+    ("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB", "USDT", 6),
+    ("So11111111111111111111111111111111111111112", "SOL", 9),
+    ("mSoLzYCxHdYgdzU16g5QSh3i5K3z3KZK7ytfqcJm7So", "mSOL", 9),
+    ("JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN", "JUP", 6),
+];
+
+pub fn mint_symbol(mint: &str) -> Option<&'static str> {
+    KNOWN_MINTS
+        .iter()
+        .find(|(address, _, _)| *address == mint)
+        .map(|(_, symbol, _)| *symbol)
+}
+
+pub fn mint_decimals(mint: &str) -> Option<u8> {
+    KNOWN_MINTS
+        .iter()
+        .find(|(address, _, _)| *address == mint)
+        .map(|(_, _, decimals)| *decimals)
+}
+
+/// Symbol of a mint if it is well known, otherwise its address
+pub fn mint_label(mint: &str) -> &str {
+    mint_symbol(mint).unwrap_or(mint)
+}
+
+/// Format a raw token amount with thousands separators, amounts of 6 decimal mints are formatted like USDC transfers
+pub fn format_token_amount(amount: u64, decimals: u8) -> Result<String> {
+    if decimals == 6 {
+        return format_amount(&amount.to_string());
+    }
+    let decimals = decimals as usize;
+    let raw_amount = format!("{amount:0>width$}", width = decimals + 1);
+    let (integer, fraction) = raw_amount.split_at(raw_amount.len() - decimals);
+    let integer = integer
+        .as_bytes()
+        .rchunks(3)
+        .rev()
+        .map(from_utf8)
+        .collect::<Result<Vec<&str>, _>>()?
+        .join(",");
+    let fraction = fraction.trim_end_matches('0');
+    Ok(if fraction.is_empty() {
+        integer
+    } else {
+        format!("{integer}.{fraction}")
+    })
+}
+
+/// Raw amount of a decimal token amount such as "1000000", "0.5" or "1e-6", without rounding
+pub fn parse_token_amount(amount: &str, decimals: u8) -> Result<u64> {
+    let (mantissa, exponent) = match amount.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (
+            mantissa,
+            exponent
+                .parse::<i32>()
+                .with_context(|| format!("invalid token amount {amount}"))?,
+        ),
+        None => (amount, 0),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{integer}{fraction}");
+    if integer.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        bail!("invalid token amount {amount}");
+    }
+    // Number of digits before the decimal point once the exponent is applied, e.g. -5 for 1e-6
+    let point = integer.len() as i64 + i64::from(exponent);
+    let shift = point + i64::from(decimals) - digits.len() as i64;
+    // u64 has at most 20 digits
+    if shift > 20 {
+        bail!("token amount {amount} is too large");
+    }
+    let raw_amount = if shift >= 0 {
+        format!("{digits}{}", "0".repeat(shift as usize))
+    } else {
+        let kept = digits.len() as i64 + shift;
+        let (kept, dropped) = digits.split_at(kept.max(0) as usize);
+        if dropped.bytes().any(|b| b != b'0') {
+            bail!("token amount {amount} has more than {decimals} decimals");
+        }
+        kept.to_string()
+    };
+    if raw_amount.is_empty() {
+        return Ok(0);
+    }
+    raw_amount
+        .parse()
+        .with_context(|| format!("token amount {amount} is too large"))
+}
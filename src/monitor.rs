@@ -0,0 +1,358 @@
+use anyhow::Result;
+use std::collections::VecDeque;
+use tracing::{info, warn};
+
+use crate::{
+    analysis::Analysis,
+    checkpoint::{read_checkpoint, write_checkpoint},
+    config::Config,
+    dedup::{DedupKey, Deduplicator},
+    instructions::Transfer,
+    patterns::Pattern,
+    process_block,
+    reconcile::Discrepancy,
+    record::Recorder,
+    screening::ScreeningHit,
+    shutdown::Shutdown,
+    sinks::TransferSink,
+    sources::{BlockSource, RpcBlockSource},
+    swaps::Swap,
+};
+
+/// Everything the monitor decodes from a block, in the order it was found
+#[derive(Clone, Debug)]
+pub enum Event {
+    BlockStart {
+        slot: u64,
+        /// Seconds since the Unix epoch, if the RPC knows it
+        block_time: Option<i64>,
+    },
+    Transfer(Transfer),
+    Swap(Swap),
+    Discrepancy(Discrepancy),
+    Pattern(Pattern),
+    ScreeningHit(ScreeningHit),
+    BlockEnd {
+        slot: u64,
+    },
+}
+
+impl Event {
+    /// Call the sink callback matching the event
+    pub fn send_to<K: TransferSink + ?Sized>(&self, sink: &mut K) -> Result<()> {
+        match self {
+            Event::BlockStart { slot, block_time } => sink.on_block_start(*slot, *block_time),
+            Event::Transfer(transfer) => sink.on_transfer(transfer),
+            Event::Swap(swap) => sink.on_swap(swap),
+            Event::Discrepancy(discrepancy) => sink.on_discrepancy(discrepancy),
+            Event::Pattern(pattern) => sink.on_pattern(pattern),
+            Event::ScreeningHit(hit) => sink.on_screening_hit(hit),
+            Event::BlockEnd { slot } => sink.on_block_end(*slot),
+        }
+    }
+}
+
+type TransferFilter = Box<dyn Fn(&Transfer) -> bool + Send>;
+
+/// Builds a `Monitor`, polling the RPC in `Config` unless another source is given
+///
+/// ```no_run
+/// use solana_transfer_monitor::monitor::{Event, Monitor};
+///
+/// let monitor = Monitor::builder()
+///     .rpc_url("https://api.mainnet-beta.solana.com")
+///     .filter(|transfer| transfer.amount >= 1_000_000_000_000)
+///     .build()?;
+/// for event in monitor {
+///     if let Event::Transfer(transfer) = event? {
+///         println!("{transfer}");
+///     }
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Default)]
+pub struct MonitorBuilder {
+    config: Config,
+    source: Option<Box<dyn BlockSource + Send>>,
+    filters: Vec<TransferFilter>,
+    shutdown: Shutdown,
+}
+
+impl MonitorBuilder {
+    /// Replace every option set so far with `config`
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn rpc_url(mut self, rpc_url: impl Into<String>) -> Self {
+        self.config.rpc_url = rpc_url.into();
+        self
+    }
+
+    /// Mints whose transfers are emitted, replacing the default of USDC
+    pub fn mints<I: IntoIterator<Item = S>, S: Into<String>>(mut self, mints: I) -> Self {
+        self.config.mints = mints.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn start_slot(mut self, start_slot: u64) -> Self {
+        self.config.start_slot = Some(start_slot);
+        self
+    }
+
+    pub fn max_blocks(mut self, max_blocks: usize) -> Self {
+        self.config.max_blocks = Some(max_blocks);
+        self
+    }
+
+    /// Only emit transfers matching every filter, other events are not filtered
+    pub fn filter(mut self, filter: impl Fn(&Transfer) -> bool + Send + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Read blocks from `source` instead of polling the RPC
+    pub fn source(mut self, source: impl BlockSource + Send + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    /// Stop after the block being processed once `shutdown` is requested, e.g. by `Shutdown::on_signals`
+    pub fn shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Connects to the RPC if no other source was given, resuming after the checkpoint if there is one
+    pub fn build(mut self) -> Result<Monitor> {
+        if let (None, Some(path)) = (self.config.start_slot, &self.config.checkpoint_path) {
+            if let Some(last_slot) = read_checkpoint(path)? {
+                info!("resuming after checkpoint at slot {last_slot}");
+                self.config.start_slot = Some(last_slot + 1);
+            }
+        }
+        let source = match self.source {
+            Some(source) => source,
+            None => {
+                Box::new(RpcBlockSource::new(&self.config)?.with_shutdown(self.shutdown.clone()))
+            }
+        };
+        let recorder = self
+            .config
+            .record_dir
+            .as_ref()
+            .map(Recorder::new)
+            .transpose()?;
+        let dedup = self
+            .config
+            .dedup
+            .as_ref()
+            .map(Deduplicator::open)
+            .transpose()?;
+        let analysis = Analysis::new(&self.config)?;
+        Ok(Monitor {
+            config: self.config,
+            source,
+            recorder,
+            events: EventQueue {
+                events: VecDeque::new(),
+                filters: self.filters,
+                dedup,
+                analysis,
+            },
+            blocks_processed: 0,
+            last_slot: None,
+            handled_slot: None,
+            shutdown: self.shutdown,
+            finished: false,
+        })
+    }
+}
+
+/// Iterator of the events decoded from a block source, ends when the source is exhausted, after `Config::max_blocks` blocks, on shutdown or after the first error
+///
+/// The last slot whose events the consumer handled is written to `Config::checkpoint_path` when the iterator ends or is dropped.
+pub struct Monitor {
+    config: Config,
+    source: Box<dyn BlockSource + Send>,
+    recorder: Option<Recorder>,
+    events: EventQueue,
+    blocks_processed: usize,
+    last_slot: Option<u64>,
+    /// Last slot whose events the consumer took before asking for more
+    handled_slot: Option<u64>,
+    shutdown: Shutdown,
+    finished: bool,
+}
+
+impl Monitor {
+    pub fn builder() -> MonitorBuilder {
+        MonitorBuilder::default()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Last slot whose events have all been queued
+    pub fn last_slot(&self) -> Option<u64> {
+        self.last_slot
+    }
+
+    fn process_next_block(&mut self) -> Result<bool> {
+        if self.config.max_blocks == Some(self.blocks_processed) || self.shutdown.is_requested() {
+            return Ok(false);
+        }
+        let Some((slot, block)) = self.source.next_block()? else {
+            return Ok(false);
+        };
+        // Recorded after the source resolved lookup tables, so replaying never needs the RPC
+        if let Some(recorder) = &self.recorder {
+            recorder.record(slot, &block)?;
+        }
+        process_block(block, slot, &self.config, &mut self.events)?;
+        self.blocks_processed += 1;
+        self.last_slot = Some(slot);
+        Ok(true)
+    }
+
+    /// Stop iterating and write the checkpoint
+    fn finish(&mut self) -> Result<()> {
+        self.finished = true;
+        if let (Some(path), Some(slot)) = (&self.config.checkpoint_path, self.handled_slot) {
+            write_checkpoint(path, slot)?;
+            info!("checkpoint written at slot {slot}");
+        }
+        Ok(())
+    }
+}
+
+/// Checkpoints when the consumer stops before the iterator ends, e.g. on an error of its own
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(e) = self.finish() {
+                warn!("{e:#}");
+            }
+        }
+    }
+}
+
+impl Iterator for Monitor {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.events.events.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.finished {
+                return None;
+            }
+            // The consumer asked for more, so it handled every event of the previous block
+            if let Err(e) = self.events.commit() {
+                if let Err(checkpoint_error) = self.finish() {
+                    warn!("{checkpoint_error:#}");
+                }
+                return Some(Err(e));
+            }
+            self.handled_slot = self.last_slot;
+            match self.process_next_block() {
+                Ok(true) => {}
+                Ok(false) => {
+                    if let Err(e) = self.finish() {
+                        return Some(Err(e));
+                    }
+                }
+                Err(e) => {
+                    self.events.discard();
+                    if let Err(checkpoint_error) = self.finish() {
+                        warn!("{checkpoint_error:#}");
+                    }
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Sink buffering the events of one block for the iterator
+///
+/// Events already emitted are dropped first, their keys persisted only once the consumer asks for the events of the next block, then patterns are detected and transfers screened over every transfer, including those the filters drop.
+struct EventQueue {
+    events: VecDeque<Event>,
+    filters: Vec<TransferFilter>,
+    dedup: Option<Deduplicator>,
+    analysis: Analysis,
+}
+
+impl EventQueue {
+    fn is_new(&mut self, key: DedupKey) -> bool {
+        match &mut self.dedup {
+            Some(dedup) => dedup.check(key),
+            None => true,
+        }
+    }
+
+    /// Persist the keys of the events the consumer took, called once the queue is drained
+    fn commit(&mut self) -> Result<()> {
+        match &mut self.dedup {
+            Some(dedup) => dedup.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Drop the events and keys of a partially processed block
+    fn discard(&mut self) {
+        self.events.clear();
+        if let Some(dedup) = &mut self.dedup {
+            dedup.discard();
+        }
+    }
+}
+
+impl TransferSink for EventQueue {
+    fn on_block_start(&mut self, slot: u64, block_time: Option<i64>) -> Result<()> {
+        self.analysis.start_block(slot)?;
+        self.events
+            .push_back(Event::BlockStart { slot, block_time });
+        Ok(())
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        if !self.is_new(DedupKey::transfer(transfer)) {
+            return Ok(());
+        }
+        if self.filters.iter().all(|filter| filter(transfer)) {
+            self.events.push_back(Event::Transfer(transfer.clone()));
+        }
+        let (hit, patterns) = self.analysis.observe(transfer)?;
+        self.events.extend(hit.map(Event::ScreeningHit));
+        self.events.extend(patterns.into_iter().map(Event::Pattern));
+        Ok(())
+    }
+
+    fn on_swap(&mut self, swap: &Swap) -> Result<()> {
+        self.analysis.observe_swap(swap);
+        if self.is_new(DedupKey::swap(swap)) {
+            self.events.push_back(Event::Swap(swap.clone()));
+        }
+        Ok(())
+    }
+
+    fn on_discrepancy(&mut self, discrepancy: &Discrepancy) -> Result<()> {
+        if self.is_new(DedupKey::discrepancy(discrepancy)) {
+            self.events
+                .push_back(Event::Discrepancy(discrepancy.clone()));
+        }
+        Ok(())
+    }
+
+    fn on_block_end(&mut self, slot: u64) -> Result<()> {
+        let patterns = self.analysis.end_block();
+        self.events.extend(patterns.into_iter().map(Event::Pattern));
+        self.events.push_back(Event::BlockEnd { slot });
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+use crate::{
+    instructions::Transfer,
+    labels::{Category, Label},
+    mints::mint_label,
+    swaps::Swap,
+};
+
+/// Options of `PatternDetector`
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatternConfig {
+    /// Transfers are compared with those of this many earlier slots, about a minute by default
+    pub window_slots: u64,
+    /// Longest cycle reported, in owners, cycles of 2 owners are round trips
+    pub max_cycle_length: usize,
+    /// Equal transfers back and forth between two owners before they are reported as ping-pong
+    pub ping_pong_repeats: usize,
+    /// Transfers kept in the window at most, the oldest are dropped beyond this
+    pub max_transfers: usize,
+    /// Latest transfers from each owner followed when searching for cycles
+    pub max_fan_out: usize,
+}
+
+impl Default for PatternConfig {
+    fn default() -> Self {
+        PatternConfig {
+            window_slots: 150,
+            max_cycle_length: 4,
+            ping_pong_repeats: 4,
+            max_transfers: 10_000,
+            max_fan_out: 16,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatternKind {
+    /// A sends to B, then B sends back to A
+    RoundTrip,
+    /// Tokens return to an owner through 3 or more owners
+    Cycle,
+    /// The same amount sent back and forth between two owners repeatedly
+    PingPong,
+}
+
+impl PatternKind {
+    /// Written to JSON, e.g. "roundTrip"
+    pub fn key(&self) -> &'static str {
+        match self {
+            PatternKind::RoundTrip => "roundTrip",
+            PatternKind::Cycle => "cycle",
+            PatternKind::PingPong => "pingPong",
+        }
+    }
+}
+
+impl fmt::Display for PatternKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PatternKind::RoundTrip => "round trip",
+            PatternKind::Cycle => "cycle",
+            PatternKind::PingPong => "ping-pong",
+        })
+    }
+}
+
+/// Transfers forming a circular flow
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    /// Owners in the order the tokens moved, starting with the first sender
+    pub owners: Vec<String>,
+    pub mint: String,
+    /// Raw amount of the transfer completing the pattern
+    pub amount: u64,
+    pub decimals: u8,
+    pub transfers: usize,
+    /// Signatures of the transactions involved, oldest first
+    pub signatures: Vec<String>,
+    pub first_slot: u64,
+    pub last_slot: u64,
+}
+
+/// e.g. "Suspicious round trip: Alice -> Bob -> Alice, 2 USDC transfers in slots 10-12 (signatures a, b)"
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self
+            .owners
+            .iter()
+            .chain(self.owners.first())
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        write!(
+            f,
+            "Suspicious {}: {path}, {} {} transfers in slots {}-{} (signatures {})",
+            self.kind,
+            self.transfers,
+            mint_label(&self.mint),
+            self.first_slot,
+            self.last_slot,
+            self.signatures.join(", ")
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Recent {
+    slot: u64,
+    signature: String,
+    instruction_index: u8,
+    source: String,
+    destination: String,
+    mint: String,
+    amount: u64,
+    decimals: u8,
+}
+
+/// Sources of the transfers in the window by mint, each with the ids of its transfers, oldest first
+type SourceIndex = HashMap<String, HashMap<String, VecDeque<usize>>>;
+
+/// Finds round trips, cycles and ping-pong among the transfers of the last `window_slots` slots
+///
+/// Transfers are compared with the window once their transaction is complete, so that the legs of its swaps can be left out. Transfers to or from labeled DEX pools are left out too, as every trader pays and is paid by them.
+pub struct PatternDetector {
+    config: PatternConfig,
+    recent: VecDeque<Recent>,
+    /// Transfers dropped from the front of `recent`, a transfer's id is its position plus this
+    evicted: usize,
+    by_source: SourceIndex,
+    /// Transfers of the latest transaction
+    pending: Vec<Recent>,
+}
+
+fn is_dex_pool(label: &Option<Label>) -> bool {
+    label
+        .as_ref()
+        .is_some_and(|label| label.category == Category::DexPool)
+}
+
+impl PatternDetector {
+    pub fn new(config: PatternConfig) -> Self {
+        PatternDetector {
+            config,
+            recent: VecDeque::new(),
+            evicted: 0,
+            by_source: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Add a transfer, returning the patterns completed by the transfers of the previous transaction
+    pub fn observe(&mut self, slot: u64, transfer: &Transfer) -> Vec<Pattern> {
+        let patterns = match self.pending.first() {
+            Some(pending) if pending.signature != transfer.signature => self.finish(),
+            _ => Vec::new(),
+        };
+        if !is_dex_pool(&transfer.source_label) && !is_dex_pool(&transfer.destination_label) {
+            self.pending.push(Recent {
+                slot,
+                signature: transfer.signature.clone(),
+                instruction_index: transfer.instruction_index,
+                source: transfer.source_owner.clone(),
+                destination: transfer.destination_owner.clone(),
+                mint: transfer.mint.clone(),
+                amount: transfer.amount,
+                decimals: transfer.decimals,
+            });
+        }
+        patterns
+    }
+
+    /// Leave the legs of a swap of the latest transaction out
+    pub fn exclude_swap(&mut self, swap: &Swap) {
+        self.pending.retain(|pending| {
+            pending.signature != swap.signature
+                || pending.instruction_index != swap.instruction_index
+        });
+    }
+
+    /// Compare the transfers of the latest transaction with the window and add them, returning the patterns they complete
+    ///
+    /// Called at the end of every block, `observe` calls it when the next transaction starts.
+    pub fn finish(&mut self) -> Vec<Pattern> {
+        let mut patterns = Vec::new();
+        for current in std::mem::take(&mut self.pending) {
+            patterns.extend(self.compare(current));
+        }
+        patterns
+    }
+
+    fn compare(&mut self, current: Recent) -> Vec<Pattern> {
+        self.evict(current.slot);
+        let mut patterns = Vec::new();
+        if current.source != current.destination {
+            patterns.extend(self.round_trip(&current));
+            patterns.extend(self.cycle(&current));
+            patterns.extend(self.ping_pong(&current));
+        }
+        let patterns = patterns
+            .into_iter()
+            .map(|(kind, legs)| self.pattern(kind, &legs, &current))
+            .collect();
+        if self.config.max_transfers > 0 {
+            self.by_source
+                .entry(current.mint.clone())
+                .or_default()
+                .entry(current.source.clone())
+                .or_default()
+                .push_back(self.evicted + self.recent.len());
+            self.recent.push_back(current);
+        }
+        patterns
+    }
+
+    /// Drop the transfers older than the window or beyond `max_transfers`
+    fn evict(&mut self, slot: u64) {
+        while let Some(oldest) = self.recent.front() {
+            if oldest.slot + self.config.window_slots >= slot
+                && self.recent.len() < self.config.max_transfers
+            {
+                break;
+            }
+            let Some(oldest) = self.recent.pop_front() else {
+                break;
+            };
+            self.evicted += 1;
+            let Some(sources) = self.by_source.get_mut(&oldest.mint) else {
+                continue;
+            };
+            if let Some(ids) = sources.get_mut(&oldest.source) {
+                ids.pop_front();
+                if ids.is_empty() {
+                    sources.remove(&oldest.source);
+                }
+            }
+            if sources.is_empty() {
+                self.by_source.remove(&oldest.mint);
+            }
+        }
+    }
+
+    /// Positions in the window of the transfers from `source` in `mint`, oldest first
+    fn sent_by(
+        &self,
+        source: &str,
+        mint: &str,
+    ) -> impl DoubleEndedIterator<Item = usize> + ExactSizeIterator + '_ {
+        static NONE: VecDeque<usize> = VecDeque::new();
+        let ids = self
+            .by_source
+            .get(mint)
+            .and_then(|sources| sources.get(source))
+            .unwrap_or(&NONE);
+        ids.iter().map(|id| id - self.evicted)
+    }
+
+    /// Latest transfer back from the destination
+    fn round_trip(&self, current: &Recent) -> Option<(PatternKind, Vec<usize>)> {
+        let index = self
+            .sent_by(&current.destination, &current.mint)
+            .rev()
+            .find(|index| self.recent[*index].destination == current.source)?;
+        Some((PatternKind::RoundTrip, vec![index]))
+    }
+
+    /// Shortest path of earlier transfers in order from the destination back to the source, through distinct owners
+    fn cycle(&self, current: &Recent) -> Option<(PatternKind, Vec<usize>)> {
+        // A cycle of n owners closed by the current transfer has n - 1 earlier legs
+        for legs in 2..self.config.max_cycle_length {
+            let mut path = Vec::new();
+            if self.find_path(current, &current.destination, 0, legs, &mut path) {
+                return Some((PatternKind::Cycle, path));
+            }
+        }
+        None
+    }
+
+    fn find_path(
+        &self,
+        current: &Recent,
+        owner: &str,
+        after: usize,
+        legs: usize,
+        path: &mut Vec<usize>,
+    ) -> bool {
+        let indexes = self.sent_by(owner, &current.mint);
+        // Only the latest transfers of busy owners such as exchanges, so the search stays bounded
+        let skipped = indexes.len().saturating_sub(self.config.max_fan_out);
+        for index in indexes.skip(skipped).filter(|index| *index >= after) {
+            let next = &self.recent[index];
+            let closes = next.destination == current.source;
+            let revisits = next.destination == current.destination
+                || path
+                    .iter()
+                    .any(|leg| self.recent[*leg].destination == next.destination);
+            if revisits || closes != (path.len() + 1 == legs) {
+                continue;
+            }
+            path.push(index);
+            if closes || self.find_path(current, &next.destination, index + 1, legs, path) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+
+    /// Reported once, when the equal transfers between the two owners reach `ping_pong_repeats`
+    fn ping_pong(&self, current: &Recent) -> Option<(PatternKind, Vec<usize>)> {
+        let mut legs = Vec::new();
+        for (source, destination) in [
+            (&current.source, &current.destination),
+            (&current.destination, &current.source),
+        ] {
+            legs.extend(self.sent_by(source, &current.mint).filter(|index| {
+                let earlier = &self.recent[*index];
+                earlier.destination == *destination && earlier.amount == current.amount
+            }));
+        }
+        legs.sort_unstable();
+        let back = legs
+            .iter()
+            .any(|index| self.recent[*index].source == current.destination);
+        (back && legs.len() + 1 == self.config.ping_pong_repeats.max(2))
+            .then_some((PatternKind::PingPong, legs))
+    }
+
+    fn pattern(&self, kind: PatternKind, legs: &[usize], current: &Recent) -> Pattern {
+        let transfers = legs
+            .iter()
+            .map(|index| &self.recent[*index])
+            .chain([current])
+            .collect::<Vec<_>>();
+        let owners = match kind {
+            PatternKind::PingPong => vec![
+                transfers[0].source.clone(),
+                transfers[0].destination.clone(),
+            ],
+            _ => transfers
+                .iter()
+                .map(|transfer| transfer.source.clone())
+                .collect(),
+        };
+        let mut signatures = Vec::<String>::new();
+        for transfer in &transfers {
+            if !signatures.contains(&transfer.signature) {
+                signatures.push(transfer.signature.clone());
+            }
+        }
+        Pattern {
+            kind,
+            owners,
+            mint: current.mint.clone(),
+            amount: current.amount,
+            decimals: current.decimals,
+            transfers: transfers.len(),
+            signatures,
+            first_slot: transfers[0].slot,
+            last_slot: current.slot,
+        }
+    }
+}
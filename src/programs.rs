@@ -0,0 +1,64 @@
+use crate::decode::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+
+/// Well-known program ids and their names
+#[rustfmt::skip]
+pub const KNOWN_PROGRAMS: [(&str, &str); 19] = [
+    ("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4", "Jupiter v6"),
+    ("JUP4Fb2cqiRUcaTHdrPC8h2gNsA2ETXiPDD33WcGuJB", "Jupiter v4"),
+    ("DCA265Vj8a9CEuX1eb1LWRnDT7uK6q1xMipnNyatn23M", "Jupiter DCA"),
+    ("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8", "Raydium AMM v4"),
+    ("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK", "Raydium CLMM"),
+    ("CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C", "Raydium CPMM"),
+    ("LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo", "Meteora DLMM"),
+    ("Eo7WjKq67rjJQSZxS6z3YkapzY3eMj6Xy8X5EQVn5UaB", "Meteora Pools"),
+    ("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc", "Orca Whirlpools"),
+    ("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP", "Orca Token Swap v2"),
+    ("PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY", "Phoenix"),
+    ("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX", "OpenBook"),
+    ("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin", "Serum DEX v3"),
+    ("wormDTUJ6AWPNvk59vGQbDvGJmqbDTdgWgAqcLBCgUb", "Wormhole Token Bridge"),
+    ("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL", "Associated Token Account"),
+    ("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr", "Memo"),
+    ("11111111111111111111111111111111", "System Program"),
+    (TOKEN_PROGRAM_ID, "Token Program"),
+    (TOKEN_2022_PROGRAM_ID, "Token-2022 Program"),
+];
+
+pub fn program_name(program_id: &str) -> Option<&'static str> {
+    KNOWN_PROGRAMS
+        .iter()
+        .find(|(id, _)| *id == program_id)
+        .map(|(_, name)| *name)
+}
+
+/// Name of a program if it is well known, otherwise its id
+pub fn program_label(program_id: &str) -> &str {
+    program_name(program_id).unwrap_or(program_id)
+}
+
+/// Tracks the chain of programs invoking each other while walking the inner instructions of one top-level instruction
+pub struct CallStack {
+    programs: Vec<String>,
+}
+
+impl CallStack {
+    pub fn new(top_level_program: String) -> Self {
+        CallStack {
+            programs: vec![top_level_program],
+        }
+    }
+
+    /// Record an inner instruction and return the programs that invoked it, starting with the top-level program
+    ///
+    /// `stack_height` is 1 for top-level instructions and increases by one for every CPI. Blocks from before the RPC reported stack heights only give the top-level program.
+    pub fn invoke(&mut self, program_id: &str, stack_height: Option<u32>) -> Vec<String> {
+        let Some(stack_height) = stack_height else {
+            return self.programs[..1].to_vec();
+        };
+        let depth = (stack_height as usize).saturating_sub(1).max(1);
+        self.programs.truncate(depth);
+        let callers = self.programs.clone();
+        self.programs.push(program_id.to_string());
+        callers
+    }
+}
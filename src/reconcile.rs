@@ -0,0 +1,106 @@
+use anyhow::{bail, Result};
+use solana_transaction_status::{option_serializer::OptionSerializer, UiTransactionTokenBalance};
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    instructions::{format_amount, Transfer},
+    USDC_MINT_ADDRESS,
+};
+
+/// An owner whose USDC balance change is not explained by the transfers decoded from the transaction's instructions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Discrepancy {
+    pub signature: String,
+    pub owner: String,
+    /// Net change of the owner's USDC balance according to pre/post token balances
+    pub balance_delta: i128,
+    /// Net change of the owner's USDC balance according to decoded transfers
+    pub transfer_delta: i128,
+}
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Discrepancy {
+            signature,
+            owner,
+            balance_delta,
+            transfer_delta,
+        } = self;
+        let balance_delta = format_delta(*balance_delta).map_err(|_| fmt::Error)?;
+        let transfer_delta = format_delta(*transfer_delta).map_err(|_| fmt::Error)?;
+        write!(
+            f,
+            "Discrepancy detected: {owner} balance changed by {balance_delta} USDC but transfers account for {transfer_delta} USDC in {signature}"
+        )
+    }
+}
+
+fn format_delta(delta: i128) -> Result<String> {
+    let sign = if delta < 0 { "-" } else { "" };
+    Ok(format!(
+        "{sign}{}",
+        format_amount(&delta.unsigned_abs().to_string())?
+    ))
+}
+
+/// Net USDC balance change per owner, computed from the pre and post token balances of a transaction
+pub fn balance_deltas(
+    pre_token_balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+    post_token_balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+) -> Result<BTreeMap<String, i128>> {
+    let mut deltas = BTreeMap::new();
+    for (token_balances, sign) in [(pre_token_balances, -1), (post_token_balances, 1)] {
+        let OptionSerializer::Some(token_balances) = token_balances else {
+            bail!("expected OptionSerializer::Some");
+        };
+        for token_balance in token_balances {
+            if token_balance.mint != USDC_MINT_ADDRESS {
+                continue;
+            }
+            let OptionSerializer::Some(owner) = &token_balance.owner else {
+                bail!("expected OptionSerializer::Some");
+            };
+            let amount: i128 = token_balance.ui_token_amount.amount.parse()?;
+            *deltas.entry(owner.clone()).or_insert(0) += sign * amount;
+        }
+    }
+    Ok(deltas)
+}
+
+/// Net USDC balance change per owner, computed from decoded transfers
+pub fn transfer_deltas(transfers: &[Transfer]) -> BTreeMap<String, i128> {
+    let mut deltas = BTreeMap::new();
+    for transfer in transfers {
+        let amount = i128::from(transfer.amount);
+        *deltas.entry(transfer.source_owner.clone()).or_insert(0) -= amount;
+        *deltas
+            .entry(transfer.destination_owner.clone())
+            .or_insert(0) += amount;
+    }
+    deltas
+}
+
+/// Compare balance deltas with transfer deltas and return a discrepancy for every owner where they differ
+pub fn reconcile_transaction(
+    signature: &str,
+    balance_deltas: &BTreeMap<String, i128>,
+    transfer_deltas: &BTreeMap<String, i128>,
+) -> Vec<Discrepancy> {
+    let mut owners = balance_deltas.keys().collect::<Vec<_>>();
+    owners.extend(transfer_deltas.keys());
+    owners.sort();
+    owners.dedup();
+
+    owners
+        .into_iter()
+        .filter_map(|owner| {
+            let balance_delta = balance_deltas.get(owner).copied().unwrap_or_default();
+            let transfer_delta = transfer_deltas.get(owner).copied().unwrap_or_default();
+            (balance_delta != transfer_delta).then(|| Discrepancy {
+                signature: signature.to_string(),
+                owner: owner.clone(),
+                balance_delta,
+                transfer_delta,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use solana_transaction_status::UiConfirmedBlock;
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::Duration,
+};
+use tracing::{info, trace};
+
+use crate::{config::Config, sources::BlockSource, write_source_transfers};
+
+/// Saves every block fetched by the monitor as gzipped `getBlock` JSON named by slot, e.g. `250684537.json.gz`
+pub struct Recorder {
+    dir: PathBuf,
+}
+
+impl Recorder {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create record directory {}", dir.display()))?;
+        Ok(Recorder { dir })
+    }
+
+    pub fn record(&self, slot: u64, block: &UiConfirmedBlock) -> Result<()> {
+        let path = block_path(&self.dir, slot);
+        trace!("record block {slot} to {}", path.display());
+        let file =
+            File::create(&path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+        serde_json::to_writer(&mut encoder, block)?;
+        encoder.finish()?.flush()?;
+        Ok(())
+    }
+}
+
+fn block_path(dir: &Path, slot: u64) -> PathBuf {
+    dir.join(format!("{slot}.json.gz"))
+}
+
+/// Slots recorded in a directory, in ascending order
+pub fn recorded_slots(dir: &Path) -> Result<Vec<u64>> {
+    let mut slots = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let file_name = entry?.file_name();
+        let Some(slot) = file_name
+            .to_str()
+            .and_then(|file_name| file_name.strip_suffix(".json.gz"))
+            .and_then(|slot| slot.parse().ok())
+        else {
+            continue;
+        };
+        slots.push(slot);
+    }
+    slots.sort_unstable();
+    Ok(slots)
+}
+
+pub fn read_recorded_block(dir: &Path, slot: u64) -> Result<UiConfirmedBlock> {
+    let path = block_path(dir, slot);
+    let file = File::open(&path).with_context(|| format!("failed to open {}", path.display()))?;
+    serde_json::from_reader(GzDecoder::new(BufReader::new(file)))
+        .with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Recorded blocks in slot order, spaced by the difference in their block times divided by `speed`
+///
+/// A speed of 1.0 is the original speed, 10.0 ten times faster and 0.0 as fast as possible.
+pub struct ReplayBlockSource {
+    dir: PathBuf,
+    slots: VecDeque<u64>,
+    speed: f64,
+    previous_block_time: Option<i64>,
+}
+
+impl ReplayBlockSource {
+    pub fn new(dir: &Path, speed: f64) -> Result<Self> {
+        let slots = recorded_slots(dir)?;
+        info!("replaying {} blocks from {}", slots.len(), dir.display());
+        Ok(ReplayBlockSource {
+            dir: dir.to_path_buf(),
+            slots: slots.into(),
+            speed,
+            previous_block_time: None,
+        })
+    }
+}
+
+impl BlockSource for ReplayBlockSource {
+    fn next_block(&mut self) -> Result<Option<(u64, UiConfirmedBlock)>> {
+        let Some(slot) = self.slots.pop_front() else {
+            return Ok(None);
+        };
+        let block = read_recorded_block(&self.dir, slot)?;
+        if let (Some(previous), Some(block_time)) = (self.previous_block_time, block.block_time) {
+            if self.speed > 0.0 && block_time > previous {
+                sleep(Duration::from_secs_f64(
+                    (block_time - previous) as f64 / self.speed,
+                ));
+            }
+        }
+        self.previous_block_time = block.block_time.or(self.previous_block_time);
+        Ok(Some((slot, block)))
+    }
+}
+
+/// Feed recorded blocks back through the monitor's decoding, giving the same output it wrote while recording
+pub fn replay<W: Write>(dir: &Path, speed: f64, config: &Config, writer: &mut W) -> Result<()> {
+    let mut source = ReplayBlockSource::new(dir, speed)?;
+    write_source_transfers(&mut source, config, writer)
+}
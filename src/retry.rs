@@ -0,0 +1,130 @@
+use anyhow::{Error, Result};
+use rand::Rng;
+use solana_rpc_client_api::{
+    client_error::{Error as ClientError, ErrorKind},
+    custom_error::{
+        JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP, JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE,
+        JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET,
+        JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED,
+        JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+        JSON_RPC_SERVER_ERROR_SLOT_SKIPPED,
+    },
+    request::RpcError,
+};
+use std::{thread::sleep, time::Duration};
+use tracing::warn;
+
+use crate::metrics::metrics;
+
+/// How failed RPC requests are retried
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts including the first, 1 disables retrying
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further retry
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff before retry `retry` (from 1), with jitter so concurrent monitors do not retry in lockstep
+    ///
+    /// The delay is between half and all of `base_delay * 2^(retry - 1)`, capped at `max_delay`.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << (retry.max(1) - 1).min(16))
+            .min(self.max_delay);
+        rand::thread_rng().gen_range(backoff / 2..=backoff)
+    }
+}
+
+/// What to do about a failed RPC request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Likely to succeed later, e.g. a timeout, a 429 or a block that is not available yet (-32004)
+    Retryable,
+    /// The slot has no block to fetch, e.g. it was skipped (-32007) or is missing from long-term storage (-32009)
+    SkippedSlot,
+    /// Retrying cannot help, e.g. invalid params
+    Fatal,
+}
+
+fn client_error(error: &Error) -> Option<&ClientError> {
+    error
+        .chain()
+        .find_map(|error| error.downcast_ref::<ClientError>())
+}
+
+/// Code of a JSON-RPC error response, e.g. -32009
+pub fn rpc_error_code(error: &Error) -> Option<i64> {
+    match client_error(error)?.kind() {
+        ErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => Some(*code),
+        _ => None,
+    }
+}
+
+/// Errors other than `solana_client` errors are `ErrorClass::Fatal`
+pub fn classify(error: &Error) -> ErrorClass {
+    let Some(client_error) = client_error(error) else {
+        return ErrorClass::Fatal;
+    };
+    match client_error.kind() {
+        ErrorKind::Io(_) => ErrorClass::Retryable,
+        ErrorKind::Reqwest(error) => match error.status() {
+            Some(status) if status.as_u16() == 429 || status.is_server_error() => {
+                ErrorClass::Retryable
+            }
+            Some(_) => ErrorClass::Fatal,
+            // Timeouts and connection errors
+            None => ErrorClass::Retryable,
+        },
+        ErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => match *code {
+            JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE
+            | JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+            | JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET
+            | JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED => ErrorClass::Retryable,
+            JSON_RPC_SERVER_ERROR_SLOT_SKIPPED
+            | JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED
+            | JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP => ErrorClass::SkippedSlot,
+            _ => ErrorClass::Fatal,
+        },
+        // Failures sending the request, e.g. the cluster version query failing to connect
+        ErrorKind::RpcError(RpcError::RpcRequestError(_)) => ErrorClass::Retryable,
+        _ => ErrorClass::Fatal,
+    }
+}
+
+/// Run `request` until it succeeds, fails with an error that is not `ErrorClass::Retryable` or `policy.max_attempts` attempts fail
+pub fn retry<T>(
+    policy: &RetryPolicy,
+    method: &str,
+    mut request: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        match request() {
+            Err(e) if attempt < policy.max_attempts && classify(&e) == ErrorClass::Retryable => {
+                let delay = policy.delay(attempt);
+                warn!(
+                    "{method} attempt {attempt} of {} failed, retrying in {delay:?}: {e:#}",
+                    policy.max_attempts
+                );
+                metrics().rpc_retries.with_label_values(&[method]).inc();
+                sleep(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use anyhow::{bail, Context, Result};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use tracing::{trace, warn};
+
+use crate::{
+    config::Config,
+    metrics::metrics,
+    retry::{classify, retry, rpc_error_code, ErrorClass, RetryPolicy},
+};
+
+// https://solana.com/docs/core/clusters
+const RATE_LIMIT_PERIOD: u64 = 10;
+// const MAX_REQUESTS_PER_PERIOD: usize = 100;
+const MAX_REQUESTS_PER_PERIOD: usize = 40;
+
+/// Longest an endpoint is skipped after consecutive failures
+const MAX_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// A JSON-RPC endpoint and its share of round-robin requests
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcEndpoint {
+    pub url: String,
+    pub weight: u32,
+}
+
+impl RpcEndpoint {
+    pub fn new(url: impl Into<String>, weight: u32) -> Self {
+        RpcEndpoint {
+            url: url.into(),
+            weight,
+        }
+    }
+
+    /// Parse a URL with an optional weight fragment, e.g. "https://api.mainnet-beta.solana.com#3", the weight defaults to 1
+    pub fn parse(endpoint: &str) -> Result<Self> {
+        match endpoint.rsplit_once('#') {
+            Some((url, weight)) => {
+                let weight = weight
+                    .parse()
+                    .with_context(|| format!("invalid weight in {endpoint}"))?;
+                if weight == 0 {
+                    bail!("weight of {url} must be at least 1");
+                }
+                Ok(RpcEndpoint::new(url, weight))
+            }
+            None => Ok(RpcEndpoint::new(endpoint, 1)),
+        }
+    }
+}
+
+struct Endpoint {
+    url: String,
+    client: RpcClient,
+    weight: i64,
+    /// Smooth weighted round-robin state
+    current_weight: i64,
+    /// Moving average of request successes, from 0 to 1
+    score: f64,
+    consecutive_failures: u32,
+    /// Skipped until this time after a failure, unless every endpoint is cooling down
+    retry_at: Option<Instant>,
+    request_instants: Vec<Instant>,
+}
+
+impl Endpoint {
+    fn available(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|retry_at| retry_at <= now)
+    }
+
+    /// Host of the URL, so API keys in the path or query are not logged or exported
+    fn host(&self) -> &str {
+        host(&self.url)
+    }
+}
+
+fn host(url: &str) -> &str {
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    url.split(['/', '?', '#']).next().unwrap_or(url)
+}
+
+/// RPC endpoints that fail over to each other, each rate limited separately
+///
+/// Endpoints are scored on their recent successes. After an error or timeout an endpoint is skipped for an exponentially growing cooldown and the request is retried on the next one. Once every endpoint has failed the request is retried according to `Config::retry`.
+pub struct RpcPool {
+    endpoints: Vec<Endpoint>,
+    /// Applied to each round of failover, so every endpoint is tried before backing off
+    retry_policy: RetryPolicy,
+}
+
+impl RpcPool {
+    /// Endpoints from `config.rpc_endpoints`, or `config.rpc_url` alone when there are none
+    pub fn new(config: &Config) -> Result<Self> {
+        let endpoints = if config.rpc_endpoints.is_empty() {
+            vec![RpcEndpoint::new(config.rpc_url.clone(), 1)]
+        } else {
+            config.rpc_endpoints.clone()
+        };
+        Ok(RpcPool {
+            endpoints: endpoints
+                .into_iter()
+                .map(|endpoint| {
+                    let client = RpcClient::new_with_timeout_and_commitment(
+                        endpoint.url.clone(),
+                        config.rpc_timeout,
+                        CommitmentConfig::finalized(),
+                    );
+                    metrics()
+                        .rpc_endpoint_score
+                        .with_label_values(&[host(&endpoint.url)])
+                        .set(1.0);
+                    Endpoint {
+                        url: endpoint.url,
+                        client,
+                        weight: endpoint.weight.into(),
+                        current_weight: 0,
+                        score: 1.0,
+                        consecutive_failures: 0,
+                        retry_at: None,
+                        request_instants: Vec::new(),
+                    }
+                })
+                .collect(),
+            retry_policy: config.retry,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    pub fn url(&self, index: usize) -> &str {
+        &self.endpoints[index].url
+    }
+
+    /// Host of an endpoint's URL, safe to log when the URL contains an API key
+    pub fn host(&self, index: usize) -> &str {
+        self.endpoints[index].host()
+    }
+
+    /// Call the healthiest endpoint, failing over to the others
+    pub fn call<T>(
+        &mut self,
+        method: &str,
+        request: impl Fn(&RpcClient) -> Result<T>,
+    ) -> Result<T> {
+        let retry_policy = self.retry_policy;
+        retry(&retry_policy, method, || {
+            let order = self.by_health(None);
+            self.call_in_order(method, order, &request)
+        })
+        .map(|(_, result)| result)
+    }
+
+    /// Call endpoints in weighted round-robin, failing over to the others, returns the index of the endpoint that answered
+    pub fn call_round_robin<T>(
+        &mut self,
+        method: &str,
+        request: impl Fn(&RpcClient) -> Result<T>,
+    ) -> Result<(usize, T)> {
+        let retry_policy = self.retry_policy;
+        retry(&retry_policy, method, || {
+            let mut order = self.by_health(None);
+            if let Some(next) = self.next_round_robin() {
+                order.retain(|index| *index != next);
+                order.insert(0, next);
+            }
+            self.call_in_order(method, order, &request)
+        })
+    }
+
+    /// Call the healthiest endpoint other than `excluded`, returns the index of the endpoint that answered
+    pub fn call_excluding<T>(
+        &mut self,
+        method: &str,
+        excluded: usize,
+        request: impl Fn(&RpcClient) -> Result<T>,
+    ) -> Result<(usize, T)> {
+        let retry_policy = self.retry_policy;
+        retry(&retry_policy, method, || {
+            let order = self.by_health(Some(excluded));
+            self.call_in_order(method, order, &request)
+        })
+    }
+
+    /// Available endpoints by descending score, then those cooling down by when they can be retried
+    fn by_health(&self, excluded: Option<usize>) -> Vec<usize> {
+        let now = Instant::now();
+        let mut order = (0..self.endpoints.len())
+            .filter(|index| Some(*index) != excluded)
+            .collect::<Vec<_>>();
+        order.sort_by(|a, b| {
+            let (a, b) = (&self.endpoints[*a], &self.endpoints[*b]);
+            match (a.available(now), b.available(now)) {
+                (true, true) => b.score.total_cmp(&a.score),
+                (false, false) => a.retry_at.cmp(&b.retry_at),
+                (a_available, b_available) => b_available.cmp(&a_available),
+            }
+        });
+        order
+    }
+
+    /// Smooth weighted round-robin over the available endpoints, as in nginx
+    fn next_round_robin(&mut self) -> Option<usize> {
+        let now = Instant::now();
+        let mut total = 0;
+        let mut next: Option<(usize, i64)> = None;
+        for (index, endpoint) in self.endpoints.iter_mut().enumerate() {
+            if !endpoint.available(now) {
+                continue;
+            }
+            endpoint.current_weight += endpoint.weight;
+            total += endpoint.weight;
+            if next.is_none_or(|(_, weight)| endpoint.current_weight > weight) {
+                next = Some((index, endpoint.current_weight));
+            }
+        }
+        let (next, _) = next?;
+        self.endpoints[next].current_weight -= total;
+        Some(next)
+    }
+
+    fn call_in_order<T>(
+        &mut self,
+        method: &str,
+        order: Vec<usize>,
+        request: impl Fn(&RpcClient) -> Result<T>,
+    ) -> Result<(usize, T)> {
+        let mut last_error = None;
+        for index in order {
+            let endpoint = &mut self.endpoints[index];
+            check_request_instants(&mut endpoint.request_instants);
+            let result = metrics().record_rpc(method, request(&endpoint.client));
+            endpoint.request_instants.push(Instant::now());
+            match result {
+                Ok(result) => {
+                    self.record_success(index);
+                    return Ok((index, result));
+                }
+                Err(e) => {
+                    let host = endpoint.host().to_string();
+                    warn!("{method} failed on {host}: {e:#}");
+                    // An endpoint answering that a slot was skipped or that the request is invalid is still healthy
+                    let unhealthy = match classify(&e) {
+                        ErrorClass::Retryable => true,
+                        ErrorClass::SkippedSlot => false,
+                        ErrorClass::Fatal => rpc_error_code(&e).is_none(),
+                    };
+                    if unhealthy {
+                        self.record_failure(index);
+                    }
+                    last_error = Some(e.context(format!("{method} failed on {host}")));
+                }
+            }
+        }
+        match last_error {
+            Some(e) => Err(e),
+            None => bail!("no RPC endpoints to call {method}"),
+        }
+    }
+
+    fn record_success(&mut self, index: usize) {
+        let endpoint = &mut self.endpoints[index];
+        endpoint.score = endpoint.score * 0.8 + 0.2;
+        endpoint.consecutive_failures = 0;
+        endpoint.retry_at = None;
+        self.export_score(index);
+    }
+
+    fn record_failure(&mut self, index: usize) {
+        let endpoint = &mut self.endpoints[index];
+        endpoint.score *= 0.8;
+        endpoint.consecutive_failures += 1;
+        let cooldown = Duration::from_secs(1 << (endpoint.consecutive_failures - 1).min(6));
+        endpoint.retry_at = Some(Instant::now() + cooldown.min(MAX_COOLDOWN));
+        self.export_score(index);
+    }
+
+    fn export_score(&self, index: usize) {
+        let endpoint = &self.endpoints[index];
+        metrics()
+            .rpc_endpoint_score
+            .with_label_values(&[endpoint.host()])
+            .set(endpoint.score);
+    }
+}
+
+fn check_request_instants(request_instants: &mut Vec<Instant>) {
+    trace!("checking request instants");
+    // Remove requests older than the rate limit period (10secs)
+    for i in 0..request_instants.len() {
+        if request_instants[i].elapsed() < Duration::from_secs(RATE_LIMIT_PERIOD) {
+            request_instants.drain(..i);
+            break;
+        }
+    }
+    if request_instants.len() > MAX_REQUESTS_PER_PERIOD {
+        // Exceeded rate limit so wait a while before the next iteration to avoid rate limiting
+        trace!("exceeded rate limit, waiting 500ms");
+        sleep(Duration::from_millis(500));
+        metrics().rate_limit_wait_seconds.inc_by(0.5);
+        check_request_instants(request_instants);
+    }
+}
@@ -0,0 +1,291 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{error, info, warn};
+
+use crate::{instructions::Transfer, metrics::metrics};
+
+/// A denylisted address and who it belongs to, e.g. an SDN entry
+#[derive(Clone, Debug, PartialEq)]
+pub struct DenylistEntry {
+    pub address: String,
+    /// Empty when the list only has addresses
+    pub name: String,
+}
+
+/// Addresses transfers are screened against
+#[derive(Clone, Debug, Default)]
+pub struct Denylist {
+    entries: HashMap<String, DenylistEntry>,
+}
+
+impl Denylist {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Denylist::from_csv(&contents)
+            .with_context(|| format!("invalid denylist in {}", path.display()))
+    }
+
+    /// One address per line, optionally followed by `,name` and further columns which are ignored
+    ///
+    /// Blank lines, `#` comments and a header whose first column is "address" are skipped, and fields may be double-quoted.
+    pub fn from_csv(csv: &str) -> Result<Self> {
+        let mut entries = HashMap::new();
+        for (number, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut columns = line
+                .split(',')
+                .map(|column| column.trim().trim_matches('"'));
+            let address = columns.next().unwrap_or_default();
+            if address.eq_ignore_ascii_case("address") {
+                continue;
+            }
+            if address.is_empty() || address.contains(char::is_whitespace) {
+                bail!("on line {}: expected an address", number + 1);
+            }
+            let entry = DenylistEntry {
+                address: address.to_string(),
+                name: columns.next().unwrap_or_default().to_string(),
+            };
+            entries.insert(entry.address.clone(), entry);
+        }
+        Ok(Denylist { entries })
+    }
+
+    pub fn get(&self, address: &str) -> Option<&DenylistEntry> {
+        self.entries.get(address)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Owners and token accounts of the transfer found in the list
+    pub fn screen(&self, transfer: &Transfer) -> Vec<ScreeningMatch> {
+        [
+            (Party::SourceOwner, &transfer.source_owner),
+            (Party::SourceAccount, &transfer.source_account),
+            (Party::DestinationOwner, &transfer.destination_owner),
+            (Party::DestinationAccount, &transfer.destination_account),
+        ]
+        .into_iter()
+        .filter_map(|(party, address)| {
+            Some(ScreeningMatch {
+                party,
+                entry: self.get(address)?.clone(),
+            })
+        })
+        .collect()
+    }
+}
+
+/// Which address of a transfer matched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Party {
+    SourceOwner,
+    SourceAccount,
+    DestinationOwner,
+    DestinationAccount,
+}
+
+impl Party {
+    /// Written to JSON, e.g. "sourceOwner"
+    pub fn key(&self) -> &'static str {
+        match self {
+            Party::SourceOwner => "sourceOwner",
+            Party::SourceAccount => "sourceAccount",
+            Party::DestinationOwner => "destinationOwner",
+            Party::DestinationAccount => "destinationAccount",
+        }
+    }
+}
+
+impl fmt::Display for Party {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Party::SourceOwner => "sender",
+            Party::SourceAccount => "sending token account",
+            Party::DestinationOwner => "receiver",
+            Party::DestinationAccount => "receiving token account",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreeningMatch {
+    pub party: Party,
+    pub entry: DenylistEntry,
+}
+
+/// A transfer involving a denylisted address, always high severity
+#[derive(Clone, Debug)]
+pub struct ScreeningHit {
+    pub slot: u64,
+    pub transfer: Transfer,
+    pub matches: Vec<ScreeningMatch>,
+}
+
+/// e.g. "DENYLIST HIT: Alice sent 25 USDC to Bob in sig100, receiver Bob (Lazarus Group)"
+impl fmt::Display for ScreeningHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let transfer = self.transfer.to_string();
+        let transfer = transfer.strip_prefix("TX detected: ").unwrap_or(&transfer);
+        write!(f, "DENYLIST HIT: {transfer} in {}", self.transfer.signature)?;
+        for (index, matched) in self.matches.iter().enumerate() {
+            let separator = if index == 0 { "," } else { ";" };
+            write!(f, "{separator} {} {}", matched.party, matched.entry.address)?;
+            if !matched.entry.name.is_empty() {
+                write!(f, " ({})", matched.entry.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The row `JsonlSink` and the audit log write for a hit
+pub fn screening_hit_json(hit: &ScreeningHit) -> Value {
+    let transfer = &hit.transfer;
+    json!({
+        "type": "screening",
+        "severity": "high",
+        "slot": hit.slot,
+        "signature": transfer.signature,
+        "source": transfer.source_owner,
+        "destination": transfer.destination_owner,
+        "sourceAccount": transfer.source_account,
+        "destinationAccount": transfer.destination_account,
+        "mint": transfer.mint,
+        "amount": transfer.amount,
+        "decimals": transfer.decimals,
+        "matches": hit
+            .matches
+            .iter()
+            .map(|matched| {
+                json!({
+                    "party": matched.party.key(),
+                    "address": matched.entry.address,
+                    "name": matched.entry.name,
+                })
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Screens transfers against a denylist file, reloading it whenever the file changes
+///
+/// Every hit and every load of the list is appended to the audit log as a JSON line with a `time` in Unix seconds, and synced to disk before the hit is reported.
+pub struct Screener {
+    path: PathBuf,
+    denylist: Denylist,
+    /// Modification time and length of the file when it was last read
+    version: Option<(SystemTime, u64)>,
+    audit_log: Option<File>,
+}
+
+impl Screener {
+    pub fn new(path: &Path, audit_log: Option<&Path>) -> Result<Self> {
+        let audit_log = audit_log
+            .map(|audit_path| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(audit_path)
+                    .with_context(|| format!("failed to open {}", audit_path.display()))
+            })
+            .transpose()?;
+        let mut screener = Screener {
+            path: path.to_path_buf(),
+            denylist: Denylist::default(),
+            version: None,
+            audit_log,
+        };
+        screener.reload()?;
+        Ok(screener)
+    }
+
+    pub fn denylist(&self) -> &Denylist {
+        &self.denylist
+    }
+
+    /// Read the file again, keeping the current list if it is invalid
+    pub fn reload(&mut self) -> Result<()> {
+        self.version = file_version(&self.path);
+        let denylist = Denylist::load(&self.path)?;
+        info!(
+            "loaded {} denylisted addresses from {}",
+            denylist.len(),
+            self.path.display()
+        );
+        metrics().denylist_entries.set(denylist.len() as i64);
+        self.audit(json!({
+            "type": "denylistLoaded",
+            "path": self.path.display().to_string(),
+            "addresses": denylist.len(),
+        }))?;
+        self.denylist = denylist;
+        Ok(())
+    }
+
+    /// Reload if the file's modification time or length changed, a failed reload is logged once per change
+    pub fn reload_if_changed(&mut self) -> Result<()> {
+        if file_version(&self.path) == self.version {
+            return Ok(());
+        }
+        if let Err(e) = Denylist::load(&self.path) {
+            self.version = file_version(&self.path);
+            warn!("keeping the previous denylist: {e:#}");
+            return Ok(());
+        }
+        self.reload()
+    }
+
+    /// The hit for a transfer involving a denylisted address, after it was written to the audit log
+    pub fn screen(&mut self, slot: u64, transfer: &Transfer) -> Result<Option<ScreeningHit>> {
+        let matches = self.denylist.screen(transfer);
+        if matches.is_empty() {
+            return Ok(None);
+        }
+        let hit = ScreeningHit {
+            slot,
+            transfer: transfer.clone(),
+            matches,
+        };
+        error!("{hit}");
+        metrics().screening_hits.inc();
+        self.audit(screening_hit_json(&hit))?;
+        Ok(Some(hit))
+    }
+
+    fn audit(&mut self, mut row: Value) -> Result<()> {
+        let Some(audit_log) = &mut self.audit_log else {
+            return Ok(());
+        };
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        row["time"] = time.as_secs().into();
+        writeln!(audit_log, "{row}")
+            .and_then(|()| audit_log.sync_data())
+            .context("failed to write the screening audit log")
+    }
+}
+
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
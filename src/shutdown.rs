@@ -0,0 +1,41 @@
+use anyhow::Result;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tracing::info;
+
+/// Shared flag asking the monitor to stop after the block it is processing
+#[derive(Clone, Debug, Default)]
+pub struct Shutdown(Arc<AtomicBool>);
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Shutdown::default()
+    }
+
+    /// Request shutdown on SIGINT or SIGTERM, a second signal exits immediately with 128 + the signal number
+    pub fn on_signals() -> Result<Self> {
+        let shutdown = Shutdown::new();
+        for signal in [SIGINT, SIGTERM] {
+            // Registered first so it only sees the flag set by an earlier signal
+            signal_hook::flag::register_conditional_shutdown(
+                signal,
+                128 + signal,
+                shutdown.0.clone(),
+            )?;
+            signal_hook::flag::register(signal, shutdown.0.clone())?;
+        }
+        Ok(shutdown)
+    }
+
+    pub fn request(&self) {
+        info!("shutdown requested");
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,442 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::io::Write;
+use tracing::warn;
+
+use crate::{
+    flows::{window_label, FlowEntry, FlowSummary},
+    instructions::Transfer,
+    labels::Label,
+    patterns::Pattern,
+    reconcile::Discrepancy,
+    screening::{screening_hit_json, ScreeningHit},
+    swaps::Swap,
+};
+
+/// Receives the events decoded from each block
+///
+/// Only `on_transfer` is required, the other callbacks default to doing nothing.
+pub trait TransferSink {
+    /// `block_time` is when the block was produced, in seconds since the Unix epoch, if the RPC knows it
+    fn on_block_start(&mut self, _slot: u64, _block_time: Option<i64>) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()>;
+
+    fn on_swap(&mut self, _swap: &Swap) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_discrepancy(&mut self, _discrepancy: &Discrepancy) -> Result<()> {
+        Ok(())
+    }
+
+    /// Periodic net flows, see `flows::FlowSink`
+    fn on_flow_summary(&mut self, _summary: &FlowSummary) -> Result<()> {
+        Ok(())
+    }
+
+    /// Circular flow completed by the last transfer, see `patterns::PatternDetector`
+    fn on_pattern(&mut self, _pattern: &Pattern) -> Result<()> {
+        Ok(())
+    }
+
+    /// Transfer involving a denylisted address, see `screening::Screener`
+    fn on_screening_hit(&mut self, _hit: &ScreeningHit) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_block_end(&mut self, _slot: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<K: TransferSink + ?Sized> TransferSink for Box<K> {
+    fn on_block_start(&mut self, slot: u64, block_time: Option<i64>) -> Result<()> {
+        (**self).on_block_start(slot, block_time)
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        (**self).on_transfer(transfer)
+    }
+
+    fn on_swap(&mut self, swap: &Swap) -> Result<()> {
+        (**self).on_swap(swap)
+    }
+
+    fn on_discrepancy(&mut self, discrepancy: &Discrepancy) -> Result<()> {
+        (**self).on_discrepancy(discrepancy)
+    }
+
+    fn on_flow_summary(&mut self, summary: &FlowSummary) -> Result<()> {
+        (**self).on_flow_summary(summary)
+    }
+
+    fn on_pattern(&mut self, pattern: &Pattern) -> Result<()> {
+        (**self).on_pattern(pattern)
+    }
+
+    fn on_screening_hit(&mut self, hit: &ScreeningHit) -> Result<()> {
+        (**self).on_screening_hit(hit)
+    }
+
+    fn on_block_end(&mut self, slot: u64) -> Result<()> {
+        (**self).on_block_end(slot)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Collects the transfers, e.g. to compare the transfers of two copies of a block
+impl TransferSink for Vec<Transfer> {
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        self.push(transfer.clone());
+        Ok(())
+    }
+}
+
+/// Human readable lines, e.g. "TX detected: Alice sent 25 USDC to Bob"
+pub struct TextSink<W: Write> {
+    writer: W,
+    show_programs: bool,
+}
+
+impl<W: Write> TextSink<W> {
+    /// With `show_programs` each transfer is followed by its invoking programs, e.g. "via Jupiter v6 > Raydium AMM v4"
+    pub fn new(writer: W, show_programs: bool) -> Self {
+        TextSink {
+            writer,
+            show_programs,
+        }
+    }
+}
+
+impl<W: Write> TransferSink for TextSink<W> {
+    fn on_block_start(&mut self, slot: u64, _block_time: Option<i64>) -> Result<()> {
+        writeln!(self.writer, "Latest block: {slot}")?;
+        Ok(())
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        if self.show_programs && !transfer.call_stack.is_empty() {
+            writeln!(self.writer, "{transfer} via {}", transfer.call_path())?;
+        } else {
+            writeln!(self.writer, "{transfer}")?;
+        }
+        Ok(())
+    }
+
+    fn on_swap(&mut self, swap: &Swap) -> Result<()> {
+        writeln!(self.writer, "{swap}")?;
+        Ok(())
+    }
+
+    fn on_discrepancy(&mut self, discrepancy: &Discrepancy) -> Result<()> {
+        writeln!(self.writer, "{discrepancy}")?;
+        Ok(())
+    }
+
+    fn on_flow_summary(&mut self, summary: &FlowSummary) -> Result<()> {
+        write!(self.writer, "{summary}")?;
+        Ok(())
+    }
+
+    fn on_pattern(&mut self, pattern: &Pattern) -> Result<()> {
+        writeln!(self.writer, "{pattern}")?;
+        Ok(())
+    }
+
+    fn on_screening_hit(&mut self, hit: &ScreeningHit) -> Result<()> {
+        writeln!(self.writer, "{hit}")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A transfer as written by `JsonlSink`, with a raw integer amount
+pub fn transfer_json(slot: u64, transfer: &Transfer) -> Value {
+    json!({
+        "type": "transfer",
+        "slot": slot,
+        "signature": transfer.signature,
+        "source": transfer.source_owner,
+        "destination": transfer.destination_owner,
+        "sourceAccount": transfer.source_account,
+        "destinationAccount": transfer.destination_account,
+        "sourceLabel": label_json(&transfer.source_label),
+        "destinationLabel": label_json(&transfer.destination_label),
+        "mint": transfer.mint,
+        "amount": transfer.amount,
+        "decimals": transfer.decimals,
+        "instructionIndex": transfer.instruction_index,
+        "innerInstructionIndex": transfer.inner_instruction_index,
+        "callStack": transfer.call_stack,
+    })
+}
+
+/// `{"name": ..., "category": ...}`, null for unlabeled addresses
+fn label_json(label: &Option<Label>) -> Value {
+    match label {
+        Some(label) => json!({ "name": label.name, "category": label.category.key() }),
+        None => Value::Null,
+    }
+}
+
+/// One JSON object per event, with a `type` of "transfer", "swap", "discrepancy", "flows", "pattern" or "screening" and raw integer amounts
+pub struct JsonlSink<W: Write> {
+    writer: W,
+    slot: u64,
+}
+
+impl<W: Write> JsonlSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonlSink { writer, slot: 0 }
+    }
+}
+
+impl<W: Write> TransferSink for JsonlSink<W> {
+    fn on_block_start(&mut self, slot: u64, _block_time: Option<i64>) -> Result<()> {
+        self.slot = slot;
+        Ok(())
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        writeln!(self.writer, "{}", transfer_json(self.slot, transfer))?;
+        Ok(())
+    }
+
+    fn on_swap(&mut self, swap: &Swap) -> Result<()> {
+        let row = json!({
+            "type": "swap",
+            "slot": self.slot,
+            "signature": swap.signature,
+            "trader": swap.trader,
+            "inMint": swap.in_mint,
+            "inAmount": swap.in_amount,
+            "inDecimals": swap.in_decimals,
+            "outMint": swap.out_mint,
+            "outAmount": swap.out_amount,
+            "outDecimals": swap.out_decimals,
+            "route": swap.route,
+        });
+        writeln!(self.writer, "{row}")?;
+        Ok(())
+    }
+
+    fn on_discrepancy(&mut self, discrepancy: &Discrepancy) -> Result<()> {
+        let row = json!({
+            "type": "discrepancy",
+            "slot": self.slot,
+            "signature": discrepancy.signature,
+            "owner": discrepancy.owner,
+            "balanceDelta": discrepancy.balance_delta,
+            "transferDelta": discrepancy.transfer_delta,
+        });
+        writeln!(self.writer, "{row}")?;
+        Ok(())
+    }
+
+    /// One row per window
+    fn on_flow_summary(&mut self, summary: &FlowSummary) -> Result<()> {
+        let entries = |entries: &[FlowEntry]| {
+            entries
+                .iter()
+                .map(|entry| {
+                    json!({
+                        "owner": entry.owner,
+                        "label": label_json(&entry.label),
+                        "mint": entry.mint,
+                        "inflow": entry.flow.inflow,
+                        "outflow": entry.flow.outflow,
+                        "net": i64::try_from(entry.flow.net()).unwrap_or_default(),
+                        "decimals": entry.flow.decimals,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        for window in &summary.windows {
+            let row = json!({
+                "type": "flows",
+                "slot": self.slot,
+                "window": window_label(window.window),
+                "windowSecs": window.window.as_secs(),
+                "addresses": window.addresses,
+                "evicted": window.evicted,
+                "netInflow": entries(&window.net_inflow),
+                "netOutflow": entries(&window.net_outflow),
+            });
+            writeln!(self.writer, "{row}")?;
+        }
+        Ok(())
+    }
+
+    fn on_pattern(&mut self, pattern: &Pattern) -> Result<()> {
+        let row = json!({
+            "type": "pattern",
+            "slot": self.slot,
+            "kind": pattern.kind.key(),
+            "owners": pattern.owners,
+            "mint": pattern.mint,
+            "amount": pattern.amount,
+            "decimals": pattern.decimals,
+            "transfers": pattern.transfers,
+            "signatures": pattern.signatures,
+            "firstSlot": pattern.first_slot,
+            "lastSlot": pattern.last_slot,
+        });
+        writeln!(self.writer, "{row}")?;
+        Ok(())
+    }
+
+    fn on_screening_hit(&mut self, hit: &ScreeningHit) -> Result<()> {
+        writeln!(self.writer, "{}", screening_hit_json(hit))?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+type TransferFilter = Box<dyn Fn(&Transfer) -> bool>;
+
+struct Route {
+    name: String,
+    sink: Box<dyn TransferSink>,
+    filter: Option<TransferFilter>,
+    required: bool,
+    errors: usize,
+}
+
+/// Sends every event to several sinks, e.g. text to stdout and JSONL to a file
+///
+/// A sink that fails is logged and counted but does not stop the others or the monitor, unless it was added with [`FanOut::with_required_sink`].
+#[derive(Default)]
+pub struct FanOut {
+    routes: Vec<Route>,
+}
+
+impl FanOut {
+    pub fn new() -> Self {
+        FanOut::default()
+    }
+
+    pub fn with_sink(self, name: impl Into<String>, sink: impl TransferSink + 'static) -> Self {
+        self.add(name.into(), Box::new(sink), None, false)
+    }
+
+    /// Add a sink whose errors are returned once every sink received the event, stopping the monitor
+    pub fn with_required_sink(
+        self,
+        name: impl Into<String>,
+        sink: impl TransferSink + 'static,
+    ) -> Self {
+        self.add(name.into(), Box::new(sink), None, true)
+    }
+
+    /// Add a sink that only receives the transfers matching `filter`, block and other events are not filtered
+    pub fn with_filtered_sink(
+        self,
+        name: impl Into<String>,
+        sink: impl TransferSink + 'static,
+        filter: impl Fn(&Transfer) -> bool + 'static,
+    ) -> Self {
+        self.add(name.into(), Box::new(sink), Some(Box::new(filter)), false)
+    }
+
+    fn add(
+        mut self,
+        name: String,
+        sink: Box<dyn TransferSink>,
+        filter: Option<TransferFilter>,
+        required: bool,
+    ) -> Self {
+        self.routes.push(Route {
+            name,
+            sink,
+            filter,
+            required,
+            errors: 0,
+        });
+        self
+    }
+
+    /// Number of errors a sink has returned, 0 for unknown names
+    pub fn errors(&self, name: &str) -> usize {
+        self.routes
+            .iter()
+            .find(|route| route.name == name)
+            .map_or(0, |route| route.errors)
+    }
+
+    /// Send an event to every route, returns the first error of a required sink
+    fn each(&mut self, mut event: impl FnMut(&mut Route) -> Result<()>) -> Result<()> {
+        let mut first_error = None;
+        for route in &mut self.routes {
+            if let Err(e) = event(route) {
+                warn!("sink {} failed: {e:#}", route.name);
+                route.errors += 1;
+                if route.required && first_error.is_none() {
+                    first_error = Some(e.context(format!("sink {} failed", route.name)));
+                }
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TransferSink for FanOut {
+    fn on_block_start(&mut self, slot: u64, block_time: Option<i64>) -> Result<()> {
+        self.each(|route| route.sink.on_block_start(slot, block_time))
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        self.each(|route| match &route.filter {
+            Some(filter) if !filter(transfer) => Ok(()),
+            _ => route.sink.on_transfer(transfer),
+        })
+    }
+
+    fn on_swap(&mut self, swap: &Swap) -> Result<()> {
+        self.each(|route| route.sink.on_swap(swap))
+    }
+
+    fn on_discrepancy(&mut self, discrepancy: &Discrepancy) -> Result<()> {
+        self.each(|route| route.sink.on_discrepancy(discrepancy))
+    }
+
+    fn on_flow_summary(&mut self, summary: &FlowSummary) -> Result<()> {
+        self.each(|route| route.sink.on_flow_summary(summary))
+    }
+
+    fn on_pattern(&mut self, pattern: &Pattern) -> Result<()> {
+        self.each(|route| route.sink.on_pattern(pattern))
+    }
+
+    fn on_screening_hit(&mut self, hit: &ScreeningHit) -> Result<()> {
+        self.each(|route| route.sink.on_screening_hit(hit))
+    }
+
+    fn on_block_end(&mut self, slot: u64) -> Result<()> {
+        self.each(|route| route.sink.on_block_end(slot))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.each(|route| route.sink.flush())
+    }
+}
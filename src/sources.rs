@@ -0,0 +1,326 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{Deserializer, Value};
+use solana_client::{rpc_client::RpcClient, rpc_config::RpcBlockConfig};
+use solana_transaction_status::UiConfirmedBlock;
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use tracing::{debug, error, trace, warn};
+
+use crate::{
+    block_transfers,
+    config::Config,
+    instructions::Transfer,
+    lookup_tables::LookupTableCache,
+    make_block_config_with_encoding,
+    metrics::metrics,
+    retry::{classify, ErrorClass},
+    rpc_pool::RpcPool,
+    shutdown::Shutdown,
+};
+
+/// Somewhere blocks come from, in slot order
+pub trait BlockSource {
+    /// Next block and its slot, `None` once the source is exhausted
+    fn next_block(&mut self) -> Result<Option<(u64, UiConfirmedBlock)>>;
+}
+
+impl<S: BlockSource + ?Sized> BlockSource for Box<S> {
+    fn next_block(&mut self) -> Result<Option<(u64, UiConfirmedBlock)>> {
+        (**self).next_block()
+    }
+}
+
+/// Blocks already fetched, with their slots
+impl BlockSource for VecDeque<(u64, UiConfirmedBlock)> {
+    fn next_block(&mut self) -> Result<Option<(u64, UiConfirmedBlock)>> {
+        Ok(self.pop_front())
+    }
+}
+
+/// Polls an RPC pool for finalized blocks from the current slot onwards, never exhausted
+pub struct RpcBlockSource {
+    pool: RpcPool,
+    rpc_block_config: RpcBlockConfig,
+    lookup_table_cache: Option<LookupTableCache>,
+    starting_slot: u64,
+    slots: VecDeque<u64>,
+    /// Also request the RPC's slot on every poll to report slot lag
+    track_slot_lag: bool,
+    tip: u64,
+    /// Set in quorum mode to decode the second copy of each block
+    quorum_config: Option<Config>,
+    shutdown: Shutdown,
+}
+
+impl RpcBlockSource {
+    pub fn new(config: &Config) -> Result<Self> {
+        let mut pool = RpcPool::new(config)?;
+        if config.quorum && pool.len() < 2 {
+            bail!("quorum mode needs at least two RPC endpoints");
+        }
+
+        let lookup_table_cache = config.resolve_lookup_tables.then(LookupTableCache::new);
+        let starting_slot = match config.start_slot {
+            Some(start_slot) => start_slot,
+            None => pool.call("getSlot", |client| Ok(client.get_slot()?))?,
+        };
+        Ok(RpcBlockSource {
+            pool,
+            rpc_block_config: make_block_config_with_encoding(config.encoding),
+            lookup_table_cache,
+            starting_slot,
+            slots: VecDeque::new(),
+            track_slot_lag: config.track_slot_lag || config.metrics_addr.is_some(),
+            tip: starting_slot,
+            quorum_config: config.quorum.then(|| config.clone()),
+            shutdown: Shutdown::new(),
+        })
+    }
+
+    /// Stop polling, as if exhausted, once `shutdown` is requested
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Fetch one block, e.g. a slot given on the command line, with lookup tables resolved as for polled blocks
+    pub fn fetch_block(&mut self, slot: u64) -> Result<UiConfirmedBlock> {
+        self.get_block(slot, None).map(|(_, block)| block)
+    }
+
+    fn get_block(
+        &mut self,
+        slot: u64,
+        excluded: Option<usize>,
+    ) -> Result<(usize, UiConfirmedBlock)> {
+        let get_block_start = Instant::now();
+        trace!("request block {slot}");
+        let rpc_block_config = self.rpc_block_config;
+        let get_block =
+            |client: &RpcClient| Ok(client.get_block_with_config(slot, rpc_block_config)?);
+        let (endpoint, mut block) = match excluded {
+            Some(excluded) => self.pool.call_excluding("getBlock", excluded, get_block)?,
+            None => self.pool.call_round_robin("getBlock", get_block)?,
+        };
+        trace!(
+            "get_block_with_config took: {:?}",
+            get_block_start.elapsed()
+        );
+        if let Some(lookup_table_cache) = &mut self.lookup_table_cache {
+            lookup_table_cache.fill_loaded_addresses(&mut self.pool, &mut block)?;
+        }
+        Ok((endpoint, block))
+    }
+
+    /// Fetch the block again from another endpoint and compare their transfers
+    fn check_quorum(&mut self, slot: u64, endpoint: usize, block: &UiConfirmedBlock) -> Result<()> {
+        let (other_endpoint, other_block) = match self.get_block(slot, Some(endpoint)) {
+            Ok(other) => other,
+            Err(e) => {
+                warn!("no second endpoint could return block {slot} for quorum: {e:#}");
+                return Ok(());
+            }
+        };
+        let Some(config) = &self.quorum_config else {
+            return Ok(());
+        };
+        let transfers = transfer_keys(block_transfers(block.clone(), slot, config)?);
+        let other_transfers = transfer_keys(block_transfers(other_block, slot, config)?);
+        if transfers != other_transfers {
+            metrics().quorum_mismatches.inc();
+            error!(
+                "quorum mismatch in block {slot}: {} returned {} transfers and {} returned {}, {} differ",
+                self.pool.host(endpoint),
+                transfers.len(),
+                self.pool.host(other_endpoint),
+                other_transfers.len(),
+                transfers.symmetric_difference(&other_transfers).count(),
+            );
+        }
+        Ok(())
+    }
+}
+
+type TransferKey = (String, String, String, String, u64);
+
+fn transfer_keys(transfers: Vec<Transfer>) -> BTreeSet<TransferKey> {
+    transfers
+        .into_iter()
+        .map(|transfer| {
+            (
+                transfer.signature,
+                transfer.source_owner,
+                transfer.destination_owner,
+                transfer.mint,
+                transfer.amount,
+            )
+        })
+        .collect()
+}
+
+impl BlockSource for RpcBlockSource {
+    fn next_block(&mut self) -> Result<Option<(u64, UiConfirmedBlock)>> {
+        loop {
+            while self.slots.is_empty() {
+                if self.shutdown.is_requested() {
+                    return Ok(None);
+                }
+                if self.track_slot_lag {
+                    self.tip = self.pool.call("getSlot", |client| Ok(client.get_slot()?))?;
+                }
+
+                let starting_slot = self.starting_slot;
+                let slots = self.pool.call("getBlocks", |client| {
+                    Ok(client.get_blocks(starting_slot, None)?)
+                })?;
+                debug!("client.get_blocks slots.len(): {}", slots.len());
+
+                if let Some(last_slot) = slots.last() {
+                    trace!("increment starting slot");
+                    self.starting_slot = last_slot + 1;
+                    self.slots.extend(slots);
+                } else {
+                    trace!("no slots returned, waiting 500ms");
+                    sleep(Duration::from_millis(500));
+                }
+            }
+            let slot = self.slots.pop_front().unwrap_or_default();
+
+            let (endpoint, block) = match self.get_block(slot, None) {
+                Ok(block) => block,
+                Err(e) if classify(&e) == ErrorClass::SkippedSlot => {
+                    warn!("skipping slot {slot} without a block: {e:#}");
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if self.track_slot_lag {
+                metrics().slot_lag.set(self.tip.saturating_sub(slot) as i64);
+            }
+            if self.quorum_config.is_some() {
+                self.check_quorum(slot, endpoint, &block)?;
+            }
+            return Ok(Some((slot, block)));
+        }
+    }
+}
+
+/// Reads a stream of `getBlock` results, either one per line (JSONL) or whitespace separated pretty printed JSON, e.g. piped `solana block --output json` dumps
+pub struct ReaderBlockSource<R: Read> {
+    values: serde_json::StreamDeserializer<'static, serde_json::de::IoRead<R>, Value>,
+    name: String,
+}
+
+impl<R: Read> ReaderBlockSource<R> {
+    /// `name` identifies the stream in error messages
+    pub fn new(reader: R, name: impl Into<String>) -> Self {
+        ReaderBlockSource {
+            values: Deserializer::from_reader(reader).into_iter(),
+            name: name.into(),
+        }
+    }
+}
+
+/// Blocks piped to stdin
+pub fn stdin_source() -> ReaderBlockSource<io::Stdin> {
+    ReaderBlockSource::new(io::stdin(), "stdin")
+}
+
+impl<R: Read> BlockSource for ReaderBlockSource<R> {
+    fn next_block(&mut self) -> Result<Option<(u64, UiConfirmedBlock)>> {
+        let Some(value) = self.values.next() else {
+            return Ok(None);
+        };
+        let value = value.with_context(|| format!("failed to read block from {}", self.name))?;
+        parse_block(value, None).map(Some)
+    }
+}
+
+/// Reads every `.json` (one block) and `.jsonl` (one block per line) file in a directory, or a single such file
+///
+/// Files named by their slot, e.g. `250684537.json` or `block_250684537.json`, are read in slot order, then the others in name order.
+pub struct FileBlockSource {
+    paths: VecDeque<PathBuf>,
+    current: Option<ReaderBlockSource<BufReader<File>>>,
+    current_slot: Option<u64>,
+}
+
+impl FileBlockSource {
+    pub fn new(path: &Path) -> Result<Self> {
+        let mut paths = if path.is_dir() {
+            fs::read_dir(path)
+                .with_context(|| format!("failed to read {}", path.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()?
+                .into_iter()
+                .filter(|path| {
+                    matches!(
+                        path.extension().and_then(|extension| extension.to_str()),
+                        Some("json" | "jsonl")
+                    )
+                })
+                .collect()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        paths.sort_by_cached_key(|path| (slot_in_name(path).unwrap_or(u64::MAX), path.clone()));
+        Ok(FileBlockSource {
+            paths: paths.into(),
+            current: None,
+            current_slot: None,
+        })
+    }
+}
+
+impl BlockSource for FileBlockSource {
+    fn next_block(&mut self) -> Result<Option<(u64, UiConfirmedBlock)>> {
+        loop {
+            if let Some(current) = &mut self.current {
+                if let Some(value) = current.values.next() {
+                    let value = value
+                        .with_context(|| format!("failed to read block from {}", current.name))?;
+                    return parse_block(value, self.current_slot).map(Some);
+                }
+            }
+            let Some(path) = self.paths.pop_front() else {
+                return Ok(None);
+            };
+            trace!("read blocks from {}", path.display());
+            let file =
+                File::open(&path).with_context(|| format!("failed to open {}", path.display()))?;
+            // A single block file named by its slot
+            self.current_slot = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("json") => slot_in_name(&path),
+                _ => None,
+            };
+            self.current = Some(ReaderBlockSource::new(
+                BufReader::new(file),
+                path.display().to_string(),
+            ));
+        }
+    }
+}
+
+/// e.g. 250684537 for `250684537.json` or `block_250684537.jsonl`
+fn slot_in_name(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    stem.trim_start_matches("block_").parse().ok()
+}
+
+/// The slot is taken from a `slot` field as in `solana block --output json`, then the file name
+///
+/// The parent slot does not tell the slot, as slots without a block are skipped.
+fn parse_block(value: Value, file_slot: Option<u64>) -> Result<(u64, UiConfirmedBlock)> {
+    let Some(slot) = value["slot"].as_u64().or(file_slot) else {
+        bail!("block has no slot field and its file is not named by its slot");
+    };
+    let block: UiConfirmedBlock =
+        serde_json::from_value(value).context("failed to parse block JSON")?;
+    Ok((slot, block))
+}
@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+use crate::{instructions::Transfer, reconcile::Discrepancy, sinks::TransferSink, swaps::Swap};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transfers (
+    slot INTEGER NOT NULL,
+    signature TEXT NOT NULL,
+    instruction_path TEXT NOT NULL,
+    source TEXT NOT NULL,
+    destination TEXT NOT NULL,
+    source_account TEXT NOT NULL,
+    destination_account TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    decimals INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS swaps (
+    slot INTEGER NOT NULL,
+    signature TEXT NOT NULL,
+    trader TEXT NOT NULL,
+    in_mint TEXT NOT NULL,
+    in_amount INTEGER NOT NULL,
+    in_decimals INTEGER NOT NULL,
+    out_mint TEXT NOT NULL,
+    out_amount INTEGER NOT NULL,
+    out_decimals INTEGER NOT NULL,
+    route TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS discrepancies (
+    slot INTEGER NOT NULL,
+    signature TEXT NOT NULL,
+    owner TEXT NOT NULL,
+    balance_delta INTEGER NOT NULL,
+    transfer_delta INTEGER NOT NULL
+);
+";
+
+/// One row per transfer, swap and discrepancy in the `transfers`, `swaps` and `discrepancies` tables of a SQLite database, with raw integer amounts
+///
+/// The rows of a block are inserted in one transaction committed at the end of the block, so a failed block leaves no rows behind. Amounts above `i64::MAX` fail the block.
+pub struct SqliteSink {
+    connection: Connection,
+    slot: u64,
+}
+
+impl SqliteSink {
+    /// Creates the database and its tables if they do not exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let connection =
+            Connection::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        SqliteSink::new(connection)
+    }
+
+    pub fn new(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(SqliteSink {
+            connection,
+            slot: 0,
+        })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+fn integer(value: impl TryInto<i64>) -> Result<i64> {
+    value
+        .try_into()
+        .ok()
+        .context("amount does not fit in a SQLite integer")
+}
+
+impl TransferSink for SqliteSink {
+    fn on_block_start(&mut self, slot: u64, _block_time: Option<i64>) -> Result<()> {
+        self.slot = slot;
+        // Left open by a block that failed before its end
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("ROLLBACK")?;
+        }
+        self.connection.execute_batch("BEGIN")?;
+        Ok(())
+    }
+
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO transfers VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                integer(self.slot)?,
+                transfer.signature,
+                transfer.instruction_path(),
+                transfer.source_owner,
+                transfer.destination_owner,
+                transfer.source_account,
+                transfer.destination_account,
+                transfer.mint,
+                integer(transfer.amount)?,
+                transfer.decimals,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn on_swap(&mut self, swap: &Swap) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO swaps VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                integer(self.slot)?,
+                swap.signature,
+                swap.trader,
+                swap.in_mint,
+                integer(swap.in_amount)?,
+                swap.in_decimals,
+                swap.out_mint,
+                integer(swap.out_amount)?,
+                swap.out_decimals,
+                swap.route.join(","),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn on_discrepancy(&mut self, discrepancy: &Discrepancy) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO discrepancies VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                integer(self.slot)?,
+                discrepancy.signature,
+                discrepancy.owner,
+                integer(discrepancy.balance_delta)?,
+                integer(discrepancy.transfer_delta)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn on_block_end(&mut self, _slot: u64) -> Result<()> {
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+}
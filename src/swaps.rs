@@ -0,0 +1,144 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    instructions::Transfer,
+    mints::{format_token_amount, mint_label},
+    programs::program_label,
+};
+
+/// A trader exchanging one mint for another within a single top-level instruction, possibly routed through several pools
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Swap {
+    /// First signature of the transaction
+    pub signature: String,
+    /// Top-level instruction the swap was made in
+    pub instruction_index: u8,
+    pub trader: String,
+    pub in_mint: String,
+    /// Raw amount the trader sent
+    pub in_amount: u64,
+    pub in_decimals: u8,
+    pub out_mint: String,
+    /// Raw amount the trader received
+    pub out_amount: u64,
+    pub out_decimals: u8,
+    /// Programs that moved the tokens for each hop, in the order they were invoked
+    pub route: Vec<String>,
+    /// Indexes of the transfers making up the swap, including intermediate hops
+    pub legs: Vec<usize>,
+}
+
+impl Swap {
+    /// Names of the programs in the route, e.g. "Raydium AMM v4 > Meteora DLMM"
+    pub fn route_path(&self) -> String {
+        self.route
+            .iter()
+            .map(|program_id| program_label(program_id))
+            .collect::<Vec<_>>()
+            .join(" > ")
+    }
+}
+
+impl fmt::Display for Swap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let in_amount =
+            format_token_amount(self.in_amount, self.in_decimals).map_err(|_| fmt::Error)?;
+        let out_amount =
+            format_token_amount(self.out_amount, self.out_decimals).map_err(|_| fmt::Error)?;
+        write!(
+            f,
+            "Swap detected: {} swapped {in_amount} {} for {out_amount} {} via {}",
+            self.trader,
+            mint_label(&self.in_mint),
+            mint_label(&self.out_mint),
+            self.route_path()
+        )
+    }
+}
+
+/// Collapse the transfers of one transaction into swaps
+///
+/// Transfers are grouped by top-level instruction. Within a group the trader is an owner whose balance goes down in one mint and up in another, preferring transaction signers over pools which look the same from their side. Intermediate hops net to zero for the router and are folded into the route.
+pub fn detect_swaps(transfers: &[Transfer], signers: &[String]) -> Vec<Swap> {
+    let mut groups: BTreeMap<u8, Vec<usize>> = BTreeMap::new();
+    for (index, transfer) in transfers.iter().enumerate() {
+        groups
+            .entry(transfer.instruction_index)
+            .or_default()
+            .push(index);
+    }
+    groups
+        .into_values()
+        .filter_map(|legs| detect_swap(transfers, legs, signers))
+        .collect()
+}
+
+fn detect_swap(transfers: &[Transfer], legs: Vec<usize>, signers: &[String]) -> Option<Swap> {
+    // Net change per owner and mint, owners in the order they first send tokens
+    let mut owners: Vec<&str> = Vec::new();
+    let mut net: BTreeMap<(&str, &str), i128> = BTreeMap::new();
+    let mut decimals: BTreeMap<&str, u8> = BTreeMap::new();
+    for transfer in legs.iter().map(|index| &transfers[*index]) {
+        if !owners.contains(&transfer.source_owner.as_str()) {
+            owners.push(&transfer.source_owner);
+        }
+        let amount = transfer.amount as i128;
+        *net.entry((&transfer.source_owner, &transfer.mint))
+            .or_default() -= amount;
+        *net.entry((&transfer.destination_owner, &transfer.mint))
+            .or_default() += amount;
+        decimals.insert(&transfer.mint, transfer.decimals);
+    }
+
+    let sides = |owner: &str| {
+        let deltas = net
+            .iter()
+            .filter(|((net_owner, _), _)| *net_owner == owner)
+            .map(|((_, mint), delta)| (*mint, *delta));
+        let sent = deltas
+            .clone()
+            .filter(|(_, delta)| *delta < 0)
+            .min_by_key(|(_, delta)| *delta)?;
+        let received = deltas
+            .filter(|(_, delta)| *delta > 0)
+            .max_by_key(|(_, delta)| *delta)?;
+        Some((sent, received))
+    };
+    let candidates = owners
+        .into_iter()
+        .filter_map(|owner| sides(owner).map(|sides| (owner, sides)))
+        .collect::<Vec<_>>();
+    let (trader, ((in_mint, in_delta), (out_mint, out_delta))) = candidates
+        .iter()
+        .find(|(owner, _)| signers.iter().any(|signer| signer == owner))
+        .or(candidates.first())
+        .copied()?;
+
+    // When the top-level program is a router, each hop is a program it invoked, transfers made by the router itself are not hops
+    let routed = legs
+        .iter()
+        .any(|index| transfers[*index].call_stack.len() > 1);
+    let hop_depth = if routed { 1 } else { 0 };
+    let mut route: Vec<String> = Vec::new();
+    for transfer in legs.iter().map(|index| &transfers[*index]) {
+        if let Some(program_id) = transfer.call_stack.get(hop_depth) {
+            if route.last() != Some(program_id) {
+                route.push(program_id.clone());
+            }
+        }
+    }
+
+    Some(Swap {
+        signature: transfers[legs[0]].signature.clone(),
+        instruction_index: transfers[legs[0]].instruction_index,
+        trader: trader.to_string(),
+        in_mint: in_mint.to_string(),
+        in_amount: in_delta.unsigned_abs() as u64,
+        in_decimals: decimals[in_mint],
+        out_mint: out_mint.to_string(),
+        out_amount: out_delta as u64,
+        out_decimals: decimals[out_mint],
+        route,
+        legs,
+    })
+}
@@ -1,30 +1,111 @@
+use anyhow::{bail, Result};
 use solana_transaction_status::{EncodedTransaction, UiConfirmedBlock};
 
-use crate::USDC_MINT_ADDRESS;
+use crate::{
+    config::Config, decode::decode_transaction, instructions::Transfer, process_block,
+    reconcile::Discrepancy, sinks::TransferSink, USDC_MINT_ADDRESS,
+};
 
 /// Get all successful USDC transactions
 /// Do text search of data returned by get_block so we can verify the parsing functions are successfully accounting for all transactions involving USDC
-pub fn get_all_successful_usdc_transactions(block: UiConfirmedBlock) -> Vec<String> {
+pub fn get_all_successful_usdc_transactions(block: UiConfirmedBlock) -> Result<Vec<String>> {
+    let Some(transactions) = block.transactions else {
+        bail!("no transactions found for block");
+    };
     let mut transaction_signatures = Vec::new();
-    if let Some(transactions) = block.transactions {
-        for transaction in transactions {
-            let debug_string = format!("{transaction:?}");
-
-            if transaction.meta.unwrap().err.is_none() && debug_string.contains(USDC_MINT_ADDRESS)
-            {
-                let signature = match transaction.transaction {
-                    EncodedTransaction::LegacyBinary(_) => todo!(),
-                    EncodedTransaction::Binary(_, _) => todo!(),
-                    EncodedTransaction::Json(ui_transaction) => ui_transaction.signatures,
-                    EncodedTransaction::Accounts(_) => todo!(),
-                };
-                if let Some(signature) = signature.into_iter().next() {
-                    transaction_signatures.push(signature);
-                }
+    for transaction in transactions {
+        let debug_string = format!("{transaction:?}");
+        let Some(meta) = &transaction.meta else {
+            bail!("transaction without status meta");
+        };
+        if meta.err.is_some() || !debug_string.contains(USDC_MINT_ADDRESS) {
+            continue;
+        }
+        let signatures = match transaction.transaction {
+            EncodedTransaction::LegacyBinary(_) | EncodedTransaction::Binary(_, _) => {
+                decode_transaction(&transaction.transaction, &meta.loaded_addresses)?.signatures
             }
+            EncodedTransaction::Json(ui_transaction) => ui_transaction.signatures,
+            EncodedTransaction::Accounts(_) => bail!("expected full transaction details"),
+        };
+        if let Some(signature) = signatures.into_iter().next() {
+            transaction_signatures.push(signature);
+        }
+    }
+    Ok(transaction_signatures)
+}
+
+/// Cross-check of the transfers decoded from a block against `get_all_successful_usdc_transactions`
+#[derive(Debug, Default)]
+pub struct BlockVerification {
+    /// Successful transactions mentioning the USDC mint
+    pub usdc_transactions: Vec<String>,
+    /// USDC transactions without a decoded transfer, fine when their USDC balances did not change
+    pub without_transfers: Vec<String>,
+    /// Transactions with decoded USDC transfers that the text search did not find
+    pub unexpected: Vec<String>,
+    /// Differences between decoded transfers and token balance changes
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl BlockVerification {
+    pub fn passed(&self) -> bool {
+        self.unexpected.is_empty() && self.discrepancies.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct VerificationSink {
+    transfers: Vec<Transfer>,
+    discrepancies: Vec<Discrepancy>,
+}
+
+impl TransferSink for VerificationSink {
+    fn on_transfer(&mut self, transfer: &Transfer) -> Result<()> {
+        self.transfers.push(transfer.clone());
+        Ok(())
+    }
+
+    fn on_discrepancy(&mut self, discrepancy: &Discrepancy) -> Result<()> {
+        self.discrepancies.push(discrepancy.clone());
+        Ok(())
+    }
+}
+
+/// Decode the USDC transfers of a block with `config`, reconciling them with balance changes, and compare the transactions they came from with a text search of the block
+///
+/// Swap legs are never hidden, so every decoded transfer is counted.
+pub fn verify_block(
+    block: UiConfirmedBlock,
+    slot: u64,
+    config: &Config,
+) -> Result<BlockVerification> {
+    let usdc_transactions = get_all_successful_usdc_transactions(block.clone())?;
+    let config = Config {
+        reconcile: true,
+        hide_swap_legs: false,
+        ..config.clone()
+    };
+    let mut sink = VerificationSink::default();
+    process_block(block, slot, &config, &mut sink)?;
+
+    let mut with_transfers = Vec::new();
+    for transfer in sink.transfers {
+        if !with_transfers.contains(&transfer.signature) {
+            with_transfers.push(transfer.signature);
         }
-    } else {
-        panic!("Error: no transactions found for block");
     }
-    transaction_signatures
+    Ok(BlockVerification {
+        without_transfers: usdc_transactions
+            .iter()
+            .filter(|signature| !with_transfers.contains(signature))
+            .cloned()
+            .collect(),
+        unexpected: with_transfers
+            .into_iter()
+            .filter(|signature| !usdc_transactions.contains(signature))
+            .collect(),
+        usdc_transactions,
+        discrepancies: sink.discrepancies,
+    })
 }
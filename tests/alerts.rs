@@ -0,0 +1,262 @@
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use solana_transfer_monitor::{
+    alerts::{deliver, webhook_client, Alert, AlertRules, AlertSink, Channel},
+    instructions::Transfer,
+    sinks::TransferSink,
+};
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+use tiny_http::{Response, Server};
+
+mod common;
+
+use common::transfer;
+
+const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+
+fn alert(channel: Channel) -> Alert {
+    Alert {
+        rule: "whales".to_string(),
+        slot: 100,
+        transfer: transfer("Alice", "Bob", 2_000_000),
+        channels: vec![channel],
+    }
+}
+
+/// Local webhook receiving JSON posts
+fn fake_webhook() -> (String, Receiver<Value>) {
+    let server = Server::http("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let body: Value = serde_json::from_reader(request.as_reader()).unwrap();
+            sender.send(body).unwrap();
+            request.respond(Response::from_string("ok")).unwrap();
+        }
+    });
+    (url, receiver)
+}
+
+/// Local SMTP relay accepting one message, receives the message data
+fn fake_smtp() -> (String, Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let relay = listener.local_addr().unwrap().to_string();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        writer.write_all(b"220 fake ESMTP\r\n").unwrap();
+        while let Some(Ok(line)) = lines.next() {
+            let command = line.to_uppercase();
+            if command.starts_with("DATA") {
+                writer.write_all(b"354 end with .\r\n").unwrap();
+                let mut data = String::new();
+                for line in lines.by_ref() {
+                    let line = line.unwrap();
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                sender.send(data).unwrap();
+                writer.write_all(b"250 queued\r\n").unwrap();
+            } else if command.starts_with("QUIT") {
+                writer.write_all(b"221 bye\r\n").unwrap();
+                break;
+            } else {
+                writer.write_all(b"250 ok\r\n").unwrap();
+            }
+        }
+    });
+    (relay, receiver)
+}
+
+fn rules(channels: Value) -> AlertRules {
+    AlertRules::from_json(&json!({
+        "tags": { "exchanges": ["Exchange"] },
+        "rules": [
+            { "name": "whales", "minAmount": 1000000, "cooldownSecs": 60, "channels": channels },
+            { "name": "exchanges", "minAmount": 1000, "tags": ["exchanges"], "channels": [] },
+            { "name": "usdt", "minAmount": 1, "mint": USDT, "channels": [] },
+        ],
+    }))
+    .unwrap()
+}
+
+fn fired(rules: &mut AlertRules, transfer: &Transfer, now: Instant) -> Vec<String> {
+    rules
+        .check(transfer, 100, now)
+        .into_iter()
+        .map(|alert| alert.rule)
+        .collect()
+}
+
+#[test]
+fn rules_match_amount_owners_and_mint() {
+    let mut rules = rules(json!([]));
+    let now = Instant::now();
+    assert_eq!(
+        vec!["whales"],
+        fired(&mut rules, &transfer("Alice", "Bob", 1_000_000), now)
+    );
+    assert!(fired(&mut rules, &transfer("Alice", "Bob", 999_999), now).is_empty());
+    assert_eq!(
+        vec!["exchanges"],
+        fired(&mut rules, &transfer("Alice", "Exchange", 5_000), now)
+    );
+    assert!(fired(&mut rules, &transfer("Alice", "Bob", 5_000), now).is_empty());
+}
+
+#[test]
+fn rules_cool_down_after_firing() {
+    let mut rules = rules(json!([]));
+    let whale = transfer("Alice", "Bob", 2_000_000);
+    let now = Instant::now();
+    assert_eq!(vec!["whales"], fired(&mut rules, &whale, now));
+    assert!(fired(&mut rules, &whale, now + Duration::from_secs(30)).is_empty());
+    assert_eq!(
+        vec!["whales"],
+        fired(&mut rules, &whale, now + Duration::from_secs(61))
+    );
+}
+
+#[test]
+fn invalid_rules_are_rejected() {
+    let unknown_tag =
+        json!({ "rules": [{ "name": "a", "minAmount": 1, "tags": ["x"], "channels": [] }] });
+    let error = AlertRules::from_json(&unknown_tag).err().unwrap();
+    assert_eq!("in rule a: unknown tag x", format!("{error:#}"));
+
+    let unknown_channel =
+        json!({ "rules": [{ "name": "a", "minAmount": 1, "channels": [{ "type": "pager" }] }] });
+    assert!(AlertRules::from_json(&unknown_channel).is_err());
+}
+
+#[test]
+fn slack_and_discord_webhooks() {
+    let (url, received) = fake_webhook();
+    let client = webhook_client().unwrap();
+    deliver(
+        &client,
+        &Channel::Slack { url: url.clone() },
+        &alert(Channel::Slack { url: url.clone() }),
+    )
+    .unwrap();
+    deliver(
+        &client,
+        &Channel::Discord { url: url.clone() },
+        &alert(Channel::Discord { url }),
+    )
+    .unwrap();
+
+    let message = "Whale alert whales: TX detected: Alice sent 2000000 USDC to Bob in slot 100 (signature sig)";
+    assert_eq!(json!({ "text": message }), received.recv().unwrap());
+    assert_eq!(json!({ "content": message }), received.recv().unwrap());
+}
+
+#[test]
+fn email_through_smtp_relay() {
+    let (relay, received) = fake_smtp();
+    let client = webhook_client().unwrap();
+    let channel = Channel::Email {
+        relay,
+        from: "monitor@example.com".to_string(),
+        to: vec!["ops@example.com".to_string()],
+    };
+    deliver(&client, &channel, &alert(channel.clone())).unwrap();
+
+    let data = received.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert!(data.contains("Subject: Whale alert whales"), "{data}");
+    assert!(data.contains("To: ops@example.com"), "{data}");
+    assert!(data.contains("Alice sent 2000000 USDC to Bob"), "{data}");
+}
+
+#[test]
+fn command_hook_receives_alert_json() {
+    let path = env::temp_dir().join(format!("alert-hook-{}.json", std::process::id()));
+    let client = webhook_client().unwrap();
+    let channel = Channel::Command {
+        program: "sh".to_string(),
+        args: vec!["-c".to_string(), format!("cat > {}", path.display())],
+    };
+    deliver(&client, &channel, &alert(channel.clone())).unwrap();
+
+    let json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!("alert", json["type"]);
+    assert_eq!("whales", json["rule"]);
+    assert_eq!(2_000_000_000_000u64, json["amount"]);
+
+    let failing = Channel::Command {
+        program: "false".to_string(),
+        args: Vec::new(),
+    };
+    assert!(deliver(&client, &failing, &alert(failing.clone())).is_err());
+}
+
+#[test]
+fn sink_delivers_alerts_in_the_background() {
+    let (url, received) = fake_webhook();
+    let mut sink = AlertSink::new(rules(json!([{ "type": "slack", "url": url }]))).unwrap();
+    sink.on_block_start(250684537, None).unwrap();
+    sink.on_transfer(&transfer("Alice", "Bob", 2_000_000))
+        .unwrap();
+    // Cooling down
+    sink.on_transfer(&transfer("Carol", "Dave", 3_000_000))
+        .unwrap();
+    drop(sink);
+
+    let body = received.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert!(body["text"]
+        .as_str()
+        .unwrap()
+        .contains("Alice sent 2000000 USDC to Bob in slot 250684537"));
+    assert!(received.try_recv().is_err());
+}
+
+#[test]
+fn thresholds_are_compared_in_raw_units() {
+    let mut rules = AlertRules::from_json(&json!({
+        "rules": [
+            { "name": "cents", "minAmount": 0.000001, "channels": [] },
+            { "name": "exact", "minAmount": "1000000.000001", "channels": [] },
+        ],
+    }))
+    .unwrap();
+    assert_eq!(1, rules.rules()[0].min_amount);
+    assert_eq!(1_000_000_000_001, rules.rules()[1].min_amount);
+
+    let just_below = Transfer {
+        amount: 1_000_000_000_000,
+        ..transfer("Alice", "Bob", 1)
+    };
+    assert_eq!(
+        vec!["cents"],
+        fired(&mut rules, &just_below, Instant::now())
+    );
+}
+
+#[test]
+fn rules_for_unknown_mints_need_decimals() {
+    let rule = |decimals: Value| {
+        let mut rule =
+            json!({ "name": "a", "minAmount": 1.5, "mint": "Unknown111", "channels": [] });
+        if !decimals.is_null() {
+            rule["decimals"] = decimals;
+        }
+        AlertRules::from_json(&json!({ "rules": [rule] }))
+    };
+    assert!(rule(Value::Null).is_err());
+    assert_eq!(15, rule(json!(1)).unwrap().rules()[0].min_amount);
+    assert!(rule(json!(0)).is_err());
+}
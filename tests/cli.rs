@@ -0,0 +1,114 @@
+use pretty_assertions::assert_eq;
+use solana_sdk::signature::Signature;
+use std::process::{Command, Output};
+
+mod common;
+
+use common::{load_block_json, MockRpc};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_solana-transfer-monitor"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn block_writes_the_transfers_of_one_slot() {
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let output = run(&["block", "101", "--rpc-url", &mock_rpc.url]);
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(
+        "Latest block: 101\nTX detected: Carol sent 1,989.48 USDC to Dave\n",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn missing_block_exits_with_not_found() {
+    let mock_rpc = MockRpc::start_with_blocks(vec![
+        (100, load_block_json(100)),
+        (101, serde_json::Value::Null),
+    ]);
+    let output = run(&["block", "101", "--rpc-url", &mock_rpc.url]);
+    assert_eq!(Some(4), output.status.code());
+    assert_eq!("", stdout(&output));
+}
+
+#[test]
+fn tx_explains_one_transaction() {
+    let signature = Signature::new_unique().to_string();
+    let mut block = load_block_json(101);
+    block["transactions"][0]["transaction"]["signatures"][0] = signature.clone().into();
+    let mock_rpc = MockRpc::start_with_blocks(vec![(101, block)]);
+
+    let output = run(&["tx", &signature, "--rpc-url", &mock_rpc.url]);
+    assert_eq!(Some(0), output.status.code());
+    let stdout = stdout(&output);
+    assert!(stdout.starts_with(&format!("Transaction {signature}\n")));
+    assert!(stdout.contains("matched: TX detected: Carol sent 1,989.48 USDC to Dave\n"));
+
+    let unknown = Signature::new_unique().to_string();
+    let output = run(&["tx", &unknown, "--rpc-url", &mock_rpc.url]);
+    assert_eq!(Some(4), output.status.code());
+}
+
+#[test]
+fn verify_passes_for_a_consistent_block() {
+    let mock_rpc = MockRpc::start(&[100]);
+    let output = run(&["verify", "100", "--rpc-url", &mock_rpc.url]);
+    assert_eq!(Some(0), output.status.code(), "{}", stdout(&output));
+    assert!(stdout(&output).ends_with("Verification passed\n"));
+}
+
+#[test]
+fn invalid_arguments_exit_with_usage_error() {
+    assert_eq!(Some(2), run(&["block", "not-a-slot"]).status.code());
+    assert_eq!(
+        Some(2),
+        run(&["--rpc-url", "http://localhost#0"]).status.code()
+    );
+}
+
+#[test]
+fn unreachable_rpc_exits_with_error() {
+    let output = run(&[
+        "block",
+        "100",
+        "--rpc-url",
+        "http://127.0.0.1:1",
+        "--max-attempts",
+        "1",
+    ]);
+    assert_eq!(Some(1), output.status.code());
+}
+
+#[test]
+fn graph_exports_a_slot_range() {
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let path = std::env::temp_dir().join(format!("graph-{}.dot", std::process::id()));
+    let output = run(&[
+        "graph",
+        "100",
+        "101",
+        "--output",
+        path.to_str().unwrap(),
+        "--rpc-url",
+        &mock_rpc.url,
+    ]);
+    assert_eq!(Some(0), output.status.code());
+    let dot = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        dot.contains(r#""Alice" -> "Bob" [label="25 USDC x1""#),
+        "{dot}"
+    );
+    assert!(
+        dot.contains(r#""Carol" -> "Dave" [label="1989.48 USDC x1""#),
+        "{dot}"
+    );
+}
@@ -0,0 +1,323 @@
+#![allow(dead_code)]
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use solana_transaction_status::UiConfirmedBlock;
+use solana_transfer_monitor::{
+    config::Config,
+    decode::TOKEN_PROGRAM_ID,
+    instructions::Transfer,
+    lookup_tables::LookupTableFetcher,
+    monitor::Monitor,
+    sinks::{TextSink, TransferSink},
+    sources::BlockSource,
+    USDC_MINT_ADDRESS,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+use tiny_http::{Response, Server};
+
+pub fn fixture_path(slot: u64) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(format!("block_{slot}.json"))
+}
+
+/// `getBlock` result for a slot as stored under `tests/fixtures`
+///
+/// Tests never fetch blocks, a missing fixture has to be recorded as described in the README.
+pub fn load_block_json(slot: u64) -> Value {
+    let path = fixture_path(slot);
+    let json = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "missing fixture {} ({e}), record the block as described under \"run tests\" in the README",
+            path.display()
+        )
+    });
+    serde_json::from_str(&json)
+        .unwrap_or_else(|e| panic!("invalid fixture {}: {e}", path.display()))
+}
+
+pub fn load_block(slot: u64) -> UiConfirmedBlock {
+    serde_json::from_value(load_block_json(slot)).unwrap()
+}
+
+/// Transfer of whole USDC between two owners in transaction "sig"
+pub fn transfer(from: &str, to: &str, usdc: u64) -> Transfer {
+    Transfer {
+        signature: "sig".to_string(),
+        source_owner: from.to_string(),
+        destination_owner: to.to_string(),
+        formatted_amount: usdc.to_string(),
+        amount: usdc * 1_000_000,
+        mint: USDC_MINT_ADDRESS.to_string(),
+        decimals: 6,
+        ..Default::default()
+    }
+}
+
+const JUPITER_V6: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+
+/// Pre or post token balance of a USDC account
+pub fn token_balance(account_index: u8, owner: &str, amount: &str) -> Value {
+    mint_token_balance(account_index, USDC_MINT_ADDRESS, 6, owner, amount)
+}
+
+pub fn mint_token_balance(
+    account_index: u8,
+    mint: &str,
+    decimals: u8,
+    owner: &str,
+    amount: &str,
+) -> Value {
+    json!({
+        "accountIndex": account_index,
+        "mint": mint,
+        "uiTokenAmount": { "uiAmount": null, "decimals": decimals, "amount": amount, "uiAmountString": "" },
+        "owner": owner,
+        "programId": TOKEN_PROGRAM_ID,
+    })
+}
+
+/// Invocation of a program the RPC could not parse, without accounts or data
+pub fn program_instruction(program_id: &str, stack_height: Option<u32>) -> Value {
+    json!({ "programId": program_id, "accounts": [], "data": "", "stackHeight": stack_height })
+}
+
+/// Parsed token transfer signed by "Trader", invoked by a pool program invoked by a top-level program
+pub fn transfer_instruction(source: &str, destination: &str, amount: &str) -> Value {
+    json!({
+        "program": "spl-token",
+        "programId": TOKEN_PROGRAM_ID,
+        "parsed": {
+            "type": "transfer",
+            "info": { "source": source, "destination": destination, "authority": "Trader", "amount": amount },
+        },
+        "stackHeight": 3,
+    })
+}
+
+/// Block with a single transaction "sig1" whose only top-level instruction calls Jupiter v6, which makes the inner instructions
+///
+/// Account keys are given as pubkey and whether it signed.
+pub fn jupiter_route_block(
+    account_keys: &[(&str, bool)],
+    inner_instructions: Vec<Value>,
+    pre_token_balances: Vec<Value>,
+    post_token_balances: Vec<Value>,
+) -> UiConfirmedBlock {
+    let account_keys = account_keys
+        .iter()
+        .map(|(pubkey, signer)| json!({ "pubkey": pubkey, "writable": true, "signer": signer }))
+        .collect::<Vec<_>>();
+    serde_json::from_value(json!({
+        "previousBlockhash": "11111111111111111111111111111111",
+        "blockhash": "11111111111111111111111111111111",
+        "parentSlot": 0,
+        "blockTime": null,
+        "blockHeight": null,
+        "transactions": [{
+            "transaction": {
+                "signatures": ["sig1"],
+                "message": {
+                    "accountKeys": account_keys,
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    "instructions": [program_instruction(JUPITER_V6, Some(1))],
+                },
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [],
+                "postBalances": [],
+                "innerInstructions": [{ "index": 0, "instructions": inner_instructions }],
+                "logMessages": [],
+                "preTokenBalances": pre_token_balances,
+                "postTokenBalances": post_token_balances,
+                "rewards": [],
+            },
+        }],
+    }))
+    .unwrap()
+}
+
+/// Local JSON-RPC server answering `getSlot`, `getBlocks`, `getBlock` and `getTransaction` from fixtures
+///
+/// The chain tip starts at the first slot and advances by one fixture on every `getBlocks` call, like a cluster producing blocks while the monitor polls.
+pub struct MockRpc {
+    pub url: String,
+    get_blocks_calls: Arc<AtomicUsize>,
+    get_block_calls: Arc<AtomicUsize>,
+}
+
+impl MockRpc {
+    pub fn start(slots: &[u64]) -> Self {
+        Self::start_with_blocks(
+            slots
+                .iter()
+                .map(|slot| (*slot, load_block_json(*slot)))
+                .collect(),
+        )
+    }
+
+    /// Serve the given block JSON for each slot, e.g. to make two providers disagree, a null block is a skipped slot
+    pub fn start_with_blocks(blocks: Vec<(u64, Value)>) -> Self {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let get_blocks_calls = Arc::new(AtomicUsize::new(0));
+        let calls = get_blocks_calls.clone();
+        let get_block_calls = Arc::new(AtomicUsize::new(0));
+        let block_calls = get_block_calls.clone();
+        thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let request_json: Value = serde_json::from_str(&body).unwrap();
+                let params = &request_json["params"];
+                let result = match request_json["method"].as_str().unwrap() {
+                    "getVersion" => Ok(json!({ "solana-core": "1.18.26", "feature-set": 0 })),
+                    "getSlot" => Ok(json!(blocks[0].0)),
+                    "getBlocks" => {
+                        let tip = calls.fetch_add(1, Ordering::SeqCst).min(blocks.len() - 1);
+                        let start = params[0].as_u64().unwrap();
+                        let end = params[1].as_u64().unwrap_or(u64::MAX);
+                        let slots = blocks[..=tip]
+                            .iter()
+                            .map(|(slot, _)| *slot)
+                            .filter(|slot| (start..=end).contains(slot))
+                            .collect::<Vec<_>>();
+                        Ok(json!(slots))
+                    }
+                    "getBlock" => {
+                        block_calls.fetch_add(1, Ordering::SeqCst);
+                        let slot = params[0].as_u64().unwrap();
+                        match blocks
+                            .iter()
+                            .find(|(fixture_slot, _)| *fixture_slot == slot)
+                        {
+                            Some((_, Value::Null)) => Err(json!({
+                                "code": -32007,
+                                "message": format!("Slot {slot} was skipped, or missing due to ledger jump to recent snapshot"),
+                            })),
+                            Some((_, block)) => Ok(block.clone()),
+                            None => Err(json!({
+                                "code": -32009,
+                                "message": format!("Slot {slot} was skipped, or missing in long-term storage"),
+                            })),
+                        }
+                    }
+                    "getTransaction" => {
+                        let signature = params[0].as_str().unwrap();
+                        let found = blocks.iter().find_map(|(slot, block)| {
+                            block["transactions"]
+                                .as_array()?
+                                .iter()
+                                .find_map(|transaction| {
+                                    (transaction["transaction"]["signatures"][0] == signature).then(
+                                        || {
+                                            json!({
+                                                "slot": slot,
+                                                "transaction": transaction["transaction"],
+                                                "meta": transaction["meta"],
+                                                "version": transaction["version"],
+                                                "blockTime": block["blockTime"],
+                                            })
+                                        },
+                                    )
+                                })
+                        });
+                        Ok(found.unwrap_or(Value::Null))
+                    }
+                    method => Err(
+                        json!({ "code": -32601, "message": format!("Method not found: {method}") }),
+                    ),
+                };
+                let response = match result {
+                    Ok(result) => {
+                        json!({ "jsonrpc": "2.0", "result": result, "id": request_json["id"] })
+                    }
+                    Err(error) => {
+                        json!({ "jsonrpc": "2.0", "error": error, "id": request_json["id"] })
+                    }
+                };
+                let header = "Content-Type: application/json"
+                    .parse::<tiny_http::Header>()
+                    .unwrap();
+                let response = Response::from_string(response.to_string()).with_header(header);
+                request.respond(response).unwrap();
+            }
+        });
+        MockRpc {
+            url,
+            get_blocks_calls,
+            get_block_calls,
+        }
+    }
+
+    pub fn get_blocks_calls(&self) -> usize {
+        self.get_blocks_calls.load(Ordering::SeqCst)
+    }
+
+    pub fn get_block_calls(&self) -> usize {
+        self.get_block_calls.load(Ordering::SeqCst)
+    }
+}
+
+/// Lookup tables by address, fetched without an RPC
+pub struct LookupTables(pub HashMap<String, Vec<String>>);
+
+impl LookupTableFetcher for LookupTables {
+    fn fetch_lookup_table(&mut self, table: &str) -> Result<Vec<String>> {
+        self.0
+            .get(table)
+            .cloned()
+            .with_context(|| format!("lookup table {table} not found"))
+    }
+}
+
+/// Write the events of a monitor as text, like the CLI
+pub fn write_events(monitor: Monitor, writer: &mut impl Write) -> Result<()> {
+    let mut sink = TextSink::new(writer, monitor.config().show_programs);
+    for event in monitor {
+        event?.send_to(&mut sink)?;
+    }
+    sink.flush()
+}
+
+/// Text the monitor writes for the blocks of a source, with screening, patterns and deduplication as configured
+pub fn write_source(
+    source: impl BlockSource + Send + 'static,
+    config: &Config,
+    writer: &mut impl Write,
+) -> Result<()> {
+    let monitor = Monitor::builder()
+        .config(config.clone())
+        .source(source)
+        .build()?;
+    write_events(monitor, writer)
+}
+
+/// `write_source` for a single block
+pub fn write_block(
+    block: UiConfirmedBlock,
+    slot: u64,
+    config: &Config,
+    writer: &mut impl Write,
+) -> Result<()> {
+    write_source(VecDeque::from([(slot, block)]), config, writer)
+}
+
+/// Text the monitor writes polling the RPC in `config`, set `max_blocks` to return
+pub fn watch(config: &Config, writer: &mut impl Write) -> Result<()> {
+    write_events(Monitor::builder().config(config.clone()).build()?, writer)
+}
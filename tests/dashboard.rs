@@ -0,0 +1,134 @@
+use pretty_assertions::assert_eq;
+use ratatui::{backend::TestBackend, Terminal};
+use solana_transfer_monitor::{
+    dashboard::{Dashboard, FeedFilter, RpcStatus},
+    monitor::Event,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod common;
+
+use common::transfer;
+
+fn transfer_event(from: &str, to: &str, usdc: u64) -> Event {
+    Event::Transfer(transfer(from, to, usdc))
+}
+
+/// The start of a minute, so offsets of less than 60 seconds stay within it
+fn minute_start() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(28_000_000 * 60)
+}
+
+#[test]
+fn top_senders_and_receivers_within_the_window() {
+    let start = minute_start();
+    let mut dashboard = Dashboard::new(Duration::from_secs(5 * 60));
+    dashboard.on_event(&transfer_event("Alice", "Bob", 100), start);
+    dashboard.on_event(&transfer_event("Carol", "Bob", 300), start);
+    dashboard.on_event(
+        &transfer_event("Alice", "Dave", 250),
+        start + Duration::from_secs(120),
+    );
+
+    assert_eq!(
+        vec![("Alice".to_string(), 350.0), ("Carol".to_string(), 300.0)],
+        dashboard.top_senders(2)
+    );
+    assert_eq!(
+        vec![("Bob".to_string(), 400.0), ("Dave".to_string(), 250.0)],
+        dashboard.top_receivers(10)
+    );
+    assert_eq!(
+        vec![0, 0, 400, 0, 250],
+        dashboard.volume_per_minute(start + Duration::from_secs(150))
+    );
+
+    // The first two transfers leave the window
+    dashboard.on_status(RpcStatus::default(), start + Duration::from_secs(6 * 60));
+    assert_eq!(
+        vec![("Alice".to_string(), 250.0)],
+        dashboard.top_senders(10)
+    );
+    // The feed keeps transfers after they leave the window
+    assert_eq!(3, dashboard.feed().count());
+}
+
+#[test]
+fn feed_filters_by_text_and_minimum_amount() {
+    let now = minute_start();
+    let mut dashboard = Dashboard::new(Duration::from_secs(60));
+    dashboard.on_event(&transfer_event("Alice", "Bob", 100), now);
+    dashboard.on_event(&transfer_event("Carol", "Dave", 5_000), now);
+
+    dashboard.filter = FeedFilter {
+        text: "bob".to_string(),
+        min_amount: 0.0,
+    };
+    let feed = dashboard
+        .feed()
+        .map(|t| t.source_owner.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(vec!["Alice"], feed);
+
+    dashboard.filter = FeedFilter {
+        text: "usdc".to_string(),
+        min_amount: 1_000.0,
+    };
+    let feed = dashboard
+        .feed()
+        .map(|t| t.source_owner.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(vec!["Carol"], feed);
+}
+
+#[test]
+fn rate_limit_wait_over_the_last_ten_seconds() {
+    let now = minute_start();
+    let mut dashboard = Dashboard::new(Duration::from_secs(60));
+    let status = |wait| RpcStatus {
+        rate_limit_wait: wait,
+        ..Default::default()
+    };
+    dashboard.on_status(status(1.0), now);
+    dashboard.on_status(status(2.5), now + Duration::from_secs(5));
+    assert_eq!(1.5, dashboard.recent_rate_limit_wait());
+    dashboard.on_status(status(2.5), now + Duration::from_secs(20));
+    assert_eq!(0.0, dashboard.recent_rate_limit_wait());
+}
+
+#[test]
+fn draws_every_panel() {
+    let now = minute_start();
+    let mut dashboard = Dashboard::new(Duration::from_secs(10 * 60));
+    dashboard.on_event(&transfer_event("Alice", "Bob", 1_250), now);
+    dashboard.on_event(&Event::BlockEnd { slot: 250684537 }, now);
+    dashboard.on_status(
+        RpcStatus {
+            slot_lag: 3,
+            requests: 42,
+            ..Default::default()
+        },
+        now,
+    );
+
+    let mut terminal = Terminal::new(TestBackend::new(140, 30)).unwrap();
+    terminal.draw(|frame| dashboard.draw(frame, now)).unwrap();
+    let screen = terminal
+        .backend()
+        .buffer()
+        .content()
+        .chunks(140)
+        .map(|line| line.iter().map(|cell| cell.symbol()).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    for expected in [
+        "Slot 250684537 | lag 3 slots | 1 blocks | RPC 42 requests, 0 errors | rate limit ok",
+        "Volume per minute, last 10 min, 1,250 this minute",
+        "Top senders, last 10 min",
+        "Top receivers, last 10 min",
+        "TX detected: Alice sent 1250 USDC to Bob",
+    ] {
+        assert!(screen.contains(expected), "{expected} not in\n{screen}");
+    }
+}
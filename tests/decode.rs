@@ -0,0 +1,214 @@
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use solana_sdk::{
+    bs58,
+    hash::Hash,
+    instruction::CompiledInstruction,
+    message::Message,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::{Transaction, VersionedTransaction},
+};
+use solana_transaction_status::{
+    EncodableWithMeta, TransactionStatusMeta, UiConfirmedBlock, UiTransactionEncoding,
+};
+use solana_transfer_monitor::{
+    decode::{decode_token_transfer, TokenInstruction, TokenTransfer, TOKEN_PROGRAM_ID},
+    metrics::metrics,
+    write_block_transfers,
+};
+use std::str::from_utf8;
+
+mod common;
+
+use common::{mint_token_balance, token_balance};
+
+const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+const AUTHORITY: &str = "7rhxnLV8C77o6d8oz26AgK8x8m5ePsdeRawjqvojbjnQ";
+const SOURCE: &str = "3YD74MctB2RCNGaMUYQRGrTdPUFh6ZMHjWau5f6Br8mR";
+const DESTINATION: &str = "5ZuR4supLRJ8eQvpqur2pfhNnjuu1guzaLbzeWv4bM7E";
+const JUPITER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+
+fn transfer_checked_data(amount: u64) -> Vec<u8> {
+    let mut data = vec![12];
+    data.extend(amount.to_le_bytes());
+    data.push(6);
+    data
+}
+
+// Account keys: 0 authority, 1 source, 2 destination, 3 mint, 4 token program, 5 Jupiter
+fn block(transaction: Value, inner_instruction: Value) -> UiConfirmedBlock {
+    serde_json::from_value(json!({
+        "previousBlockhash": "11111111111111111111111111111111",
+        "blockhash": "11111111111111111111111111111111",
+        "parentSlot": 0,
+        "blockTime": null,
+        "blockHeight": null,
+        "transactions": [{
+            "transaction": transaction,
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [],
+                "postBalances": [],
+                "innerInstructions": [{ "index": 0, "instructions": [inner_instruction] }],
+                "logMessages": [],
+                "preTokenBalances": [token_balance(1, "Sender", "2000000000"), token_balance(2, "Receiver", "0")],
+                "postTokenBalances": [token_balance(1, "Sender", "10520000"), token_balance(2, "Receiver", "1989480000")],
+                "rewards": [],
+            },
+        }],
+    }))
+    .unwrap()
+}
+
+fn json_parsed_transaction() -> Value {
+    let account_keys = [
+        AUTHORITY,
+        SOURCE,
+        DESTINATION,
+        USDC,
+        TOKEN_PROGRAM_ID,
+        JUPITER,
+    ]
+    .map(|pubkey| json!({ "pubkey": pubkey, "writable": true, "signer": false }));
+    json!({
+        "signatures": ["sig1"],
+        "message": {
+            "accountKeys": account_keys,
+            "recentBlockhash": "11111111111111111111111111111111",
+            "instructions": [{ "programIdIndex": 5, "accounts": [], "data": "", "stackHeight": null }],
+        },
+    })
+}
+
+fn base64_transaction() -> Value {
+    let account_keys = [
+        AUTHORITY,
+        SOURCE,
+        DESTINATION,
+        USDC,
+        TOKEN_PROGRAM_ID,
+        JUPITER,
+    ]
+    .map(|pubkey| pubkey.parse::<Pubkey>().unwrap())
+    .to_vec();
+    let instructions = vec![CompiledInstruction::new_from_raw_parts(5, vec![], vec![])];
+    let message = Message::new_with_compiled_instructions(
+        1,
+        0,
+        3,
+        account_keys,
+        Hash::default(),
+        instructions,
+    );
+    let transaction = VersionedTransaction::from(Transaction {
+        signatures: vec![Signature::default()],
+        message,
+    });
+    let encoded = transaction.encode_with_meta(
+        UiTransactionEncoding::Base64,
+        &TransactionStatusMeta::default(),
+    );
+    serde_json::to_value(encoded).unwrap()
+}
+
+#[test]
+fn unpack_transfer_checked() {
+    assert_eq!(
+        TokenInstruction::TransferChecked {
+            amount: 1_989_480_000,
+            decimals: 6
+        },
+        TokenInstruction::unpack(&transfer_checked_data(1_989_480_000)).unwrap()
+    );
+    assert_eq!(
+        TokenInstruction::Other(7),
+        TokenInstruction::unpack(&[7, 0, 0]).unwrap()
+    );
+    assert!(TokenInstruction::unpack(&[3, 1, 2]).is_err());
+}
+
+#[test]
+fn decode_transfer_accounts() {
+    let mut data = vec![3];
+    data.extend(42u64.to_le_bytes());
+    assert_eq!(
+        Some(TokenTransfer {
+            source: "a".to_string(),
+            destination: "b".to_string(),
+            amount: 42,
+        }),
+        decode_token_transfer(&data, &["a", "b", "authority"]).unwrap()
+    );
+}
+
+#[test]
+fn same_transfers_for_all_instruction_encodings() {
+    let data = bs58::encode(transfer_checked_data(1_989_480_000)).into_string();
+
+    let parsed = json!({
+        "program": "spl-token",
+        "programId": TOKEN_PROGRAM_ID,
+        "parsed": {
+            "type": "transferChecked",
+            "info": {
+                "source": SOURCE,
+                "mint": USDC,
+                "destination": DESTINATION,
+                "authority": AUTHORITY,
+                "tokenAmount": { "amount": "1989480000", "decimals": 6, "uiAmount": 1989.48, "uiAmountString": "1989.48" },
+            },
+        },
+        "stackHeight": 2,
+    });
+    let partially_decoded = json!({
+        "programId": TOKEN_PROGRAM_ID,
+        "accounts": [SOURCE, USDC, DESTINATION, AUTHORITY],
+        "data": data,
+        "stackHeight": 2,
+    });
+    let compiled = json!({
+        "programIdIndex": 4,
+        "accounts": [1, 3, 2, 0],
+        "data": data,
+        "stackHeight": 2,
+    });
+
+    let expected = "\
+Latest block: 1
+TX detected: Sender sent 1,989.48 USDC to Receiver
+";
+    for block in [
+        block(json_parsed_transaction(), parsed),
+        block(json_parsed_transaction(), partially_decoded),
+        block(base64_transaction(), compiled),
+    ] {
+        let mut buffer: Vec<u8> = Vec::new();
+        write_block_transfers(block, 1, &mut buffer).unwrap();
+        assert_eq!(expected, from_utf8(&buffer).unwrap());
+    }
+}
+
+#[test]
+fn transfers_between_different_mints_are_skipped() {
+    let data = bs58::encode(transfer_checked_data(1_989_480_000)).into_string();
+    let compiled = json!({
+        "programIdIndex": 4,
+        "accounts": [1, 3, 2, 0],
+        "data": data,
+        "stackHeight": 2,
+    });
+    let mut block = serde_json::to_value(block(base64_transaction(), compiled)).unwrap();
+    block["transactions"][0]["meta"]["preTokenBalances"][1] =
+        mint_token_balance(2, JUPITER, 6, "Receiver", "0");
+    let block: UiConfirmedBlock = serde_json::from_value(block).unwrap();
+
+    let mismatches = metrics().mint_mismatches.get();
+    let mut buffer: Vec<u8> = Vec::new();
+    write_block_transfers(block, 1, &mut buffer).unwrap();
+    assert_eq!("Latest block: 1\n", from_utf8(&buffer).unwrap());
+    assert_eq!(mismatches + 1, metrics().mint_mismatches.get());
+}
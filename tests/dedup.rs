@@ -0,0 +1,248 @@
+use anyhow::{bail, Result};
+use pretty_assertions::assert_eq;
+use solana_transfer_monitor::{
+    config::Config,
+    dedup::{DedupConfig, DedupKey, DedupSink, Deduplicator, SeenKeys},
+    instructions::Transfer,
+    monitor::{Event, Monitor},
+    process_block,
+    sinks::{TextSink, TransferSink},
+    swaps::Swap,
+};
+use std::{env, fs, str::from_utf8};
+
+mod common;
+
+use common::{load_block, load_block_json, write_block, MockRpc};
+
+fn key(signature: &str, path: &str) -> DedupKey {
+    DedupKey {
+        signature: signature.to_string(),
+        path: path.to_string(),
+    }
+}
+
+#[test]
+fn keys_by_signature_and_instruction_path() {
+    let transfer = Transfer {
+        signature: "sig".to_string(),
+        instruction_index: 2,
+        inner_instruction_index: Some(5),
+        ..Default::default()
+    };
+    assert_eq!(key("sig", "2.5"), DedupKey::transfer(&transfer));
+    let swap = Swap {
+        signature: "sig".to_string(),
+        instruction_index: 3,
+        legs: vec![0, 3],
+        ..Default::default()
+    };
+    assert_eq!(key("sig", "swap:3"), DedupKey::swap(&swap));
+    // Legs are positions among the decoded transfers, which depend on the configured mints
+    let same_swap_other_legs = Swap {
+        legs: vec![1, 4],
+        ..swap.clone()
+    };
+    assert_eq!(DedupKey::swap(&swap), DedupKey::swap(&same_swap_other_legs));
+    assert_eq!(key("sig", "swap:3"), DedupKey::parse("sig swap:3").unwrap());
+    assert!(DedupKey::parse("sig").is_err());
+}
+
+#[test]
+fn least_recently_seen_keys_are_evicted() {
+    let mut seen = SeenKeys::new(2);
+    assert!(seen.insert(key("a", "0.0")));
+    assert!(seen.insert(key("b", "0.0")));
+    // Seeing a again makes b the least recently seen
+    assert!(!seen.insert(key("a", "0.0")));
+    assert!(seen.insert(key("c", "0.0")));
+    assert!(seen.contains(&key("a", "0.0")));
+    assert!(!seen.contains(&key("b", "0.0")));
+    assert_eq!(
+        vec![&key("a", "0.0"), &key("c", "0.0")],
+        seen.iter().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn store_keeps_transfers_written_once_across_runs() {
+    let path = env::temp_dir().join(format!("dedup-{}.keys", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let config = |capacity| Config {
+        dedup: Some(DedupConfig {
+            capacity,
+            path: Some(path.clone()),
+        }),
+        ..Config::default()
+    };
+    let write = |slot, capacity| {
+        let mut output = Vec::new();
+        write_block(load_block(slot), slot, &config(capacity), &mut output).unwrap();
+        from_utf8(&output).unwrap().to_string()
+    };
+
+    assert_eq!(
+        "Latest block: 100\nTX detected: Alice sent 25 USDC to Bob\n",
+        write(100, 10)
+    );
+    assert_eq!("Latest block: 100\n", write(100, 10));
+    assert_eq!(
+        "Latest block: 101\nTX detected: Carol sent 1,989.48 USDC to Dave\n",
+        write(101, 10)
+    );
+    assert_eq!(
+        "sig100 0.0\nsig101 0.0\n",
+        fs::read_to_string(&path).unwrap()
+    );
+
+    // Reopening with a smaller capacity compacts the store to the most recent keys, forgetting sig100
+    assert_eq!("Latest block: 101\n", write(101, 1));
+    assert_eq!("sig101 0.0\n", fs::read_to_string(&path).unwrap());
+    assert_eq!(
+        "Latest block: 100\nTX detected: Alice sent 25 USDC to Bob\n",
+        write(100, 1)
+    );
+    let store = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!("sig101 0.0\nsig100 0.0\n", store);
+}
+
+#[test]
+fn monitor_drops_transactions_seen_in_an_earlier_block() {
+    // An overlapping source returning the transaction of block 100 again
+    let mock_rpc = MockRpc::start_with_blocks(vec![
+        (100, load_block_json(100)),
+        (101, load_block_json(100)),
+    ]);
+    let monitor = Monitor::builder()
+        .config(Config {
+            rpc_url: mock_rpc.url.clone(),
+            start_slot: Some(100),
+            max_blocks: Some(2),
+            dedup: Some(DedupConfig::default()),
+            ..Config::default()
+        })
+        .build()
+        .unwrap();
+    let events = monitor
+        .map(|event| match event.unwrap() {
+            Event::BlockStart { slot, .. } => format!("start {slot}"),
+            Event::Transfer(transfer) => transfer.to_string(),
+            Event::BlockEnd { slot } => format!("end {slot}"),
+            _ => String::new(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            "start 100",
+            "TX detected: Alice sent 25 USDC to Bob",
+            "end 100",
+            "start 101",
+            "end 101",
+        ],
+        events
+    );
+}
+
+/// Counts transfers and fails to end blocks when `fail` is set, like a writer whose disk is full
+struct FailingSink {
+    fail: bool,
+    transfers: usize,
+}
+
+impl TransferSink for FailingSink {
+    fn on_transfer(&mut self, _transfer: &Transfer) -> Result<()> {
+        self.transfers += 1;
+        Ok(())
+    }
+
+    fn on_block_end(&mut self, _slot: u64) -> Result<()> {
+        if self.fail {
+            bail!("disk full");
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn keys_are_persisted_only_after_the_inner_sink_wrote_the_block() {
+    let path = env::temp_dir().join(format!("dedup-failing-{}.keys", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut dedup = Deduplicator::open(&DedupConfig {
+        capacity: 10,
+        path: Some(path.clone()),
+    })
+    .unwrap();
+    let failing = FailingSink {
+        fail: true,
+        transfers: 0,
+    };
+    let mut sink = DedupSink::new(failing, &mut dedup);
+    assert!(process_block(load_block(100), 100, &Config::default(), &mut sink).is_err());
+    assert_eq!("", fs::read_to_string(&path).unwrap());
+    assert!(!dedup.seen().contains(&key("sig100", "0.0")));
+
+    // Retrying the block writes the transfer again, then remembers it
+    let working = FailingSink {
+        fail: false,
+        transfers: 0,
+    };
+    let mut sink = DedupSink::new(working, &mut dedup);
+    process_block(load_block(100), 100, &Config::default(), &mut sink).unwrap();
+    process_block(load_block(100), 100, &Config::default(), &mut sink).unwrap();
+    assert!(dedup.seen().contains(&key("sig100", "0.0")));
+    let store = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!("sig100 0.0\n", store);
+}
+
+#[test]
+fn one_deduplicator_across_blocks() {
+    let mut dedup = Deduplicator::open(&DedupConfig::default()).unwrap();
+    let mut output = Vec::new();
+    {
+        let mut sink = DedupSink::new(TextSink::new(&mut output, false), &mut dedup);
+        for slot in [100, 100, 101] {
+            process_block(load_block(slot), slot, &Config::default(), &mut sink).unwrap();
+        }
+    }
+    assert_eq!(
+        "Latest block: 100\nTX detected: Alice sent 25 USDC to Bob\nLatest block: 100\nLatest block: 101\nTX detected: Carol sent 1,989.48 USDC to Dave\n",
+        from_utf8(&output).unwrap()
+    );
+    assert_eq!(2, dedup.seen().len());
+}
+
+#[test]
+fn monitor_persists_keys_once_the_consumer_asks_for_the_next_block() {
+    let path = env::temp_dir().join(format!("dedup-monitor-{}.keys", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let mut monitor = Monitor::builder()
+        .config(Config {
+            rpc_url: mock_rpc.url.clone(),
+            start_slot: Some(100),
+            max_blocks: Some(2),
+            dedup: Some(DedupConfig {
+                capacity: 10,
+                path: Some(path.clone()),
+            }),
+            ..Config::default()
+        })
+        .build()
+        .unwrap();
+    for _ in 0..3 {
+        monitor.next().unwrap().unwrap();
+    }
+    // The consumer has block 100's end but may not have handled it yet
+    assert_eq!("", fs::read_to_string(&path).unwrap());
+    assert!(matches!(
+        monitor.next().unwrap().unwrap(),
+        Event::BlockStart { slot: 101, .. }
+    ));
+    assert_eq!("sig100 0.0\n", fs::read_to_string(&path).unwrap());
+    assert_eq!(2, monitor.count());
+    let store = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!("sig100 0.0\nsig101 0.0\n", store);
+}
@@ -0,0 +1,231 @@
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use solana_transfer_monitor::{
+    config::Config,
+    flows::{Flow, FlowAggregator, FlowOrder, FlowSink, FlowWindow},
+    patterns::{Pattern, PatternKind},
+    process_block,
+    sinks::{JsonlSink, TextSink, TransferSink},
+};
+use std::{
+    str::from_utf8,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+mod common;
+
+use common::{load_block, transfer};
+
+const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+fn start() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+}
+
+fn usdc(inflow: u64, outflow: u64) -> Flow {
+    Flow {
+        inflow: inflow * 1_000_000,
+        outflow: outflow * 1_000_000,
+        decimals: 6,
+    }
+}
+
+fn owners(flows: Vec<((String, String), Flow)>) -> Vec<String> {
+    flows.into_iter().map(|((owner, _), _)| owner).collect()
+}
+
+#[test]
+fn net_flow_per_address() {
+    let mut flows = FlowAggregator::default();
+    flows.record(&transfer("Alice", "Bob", 100), start());
+    flows.record(&transfer("Bob", "Carol", 30), start());
+    let minute = flows.window(Duration::from_secs(60)).unwrap();
+
+    assert_eq!(usdc(100, 30), minute.flow("Bob", USDC));
+    assert_eq!(70_000_000, minute.flow("Bob", USDC).net());
+    assert_eq!(Flow::default(), minute.flow("Dave", USDC));
+    assert_eq!(
+        vec!["Bob", "Carol"],
+        owners(minute.top(10, FlowOrder::NetInflow))
+    );
+    assert_eq!(vec!["Alice"], owners(minute.top(10, FlowOrder::NetOutflow)));
+    assert_eq!(vec!["Alice"], owners(minute.top(1, FlowOrder::Outflow)));
+    assert_eq!(vec!["Bob"], owners(minute.top(1, FlowOrder::Inflow)));
+}
+
+#[test]
+fn transfers_leave_each_window_separately() {
+    let mut flows = FlowAggregator::default();
+    flows.record(&transfer("Alice", "Bob", 100), start());
+    flows.record(
+        &transfer("Alice", "Bob", 50),
+        start() + Duration::from_secs(30),
+    );
+
+    flows.advance(start() + Duration::from_secs(61));
+    let minute = flows.window(Duration::from_secs(60)).unwrap();
+    let hour = flows.window(Duration::from_secs(3600)).unwrap();
+    assert_eq!(usdc(50, 0), minute.flow("Bob", USDC));
+    assert_eq!(usdc(150, 0), hour.flow("Bob", USDC));
+
+    flows.advance(start() + Duration::from_secs(2 * 3600));
+    let minute = flows.window(Duration::from_secs(60)).unwrap();
+    let hour = flows.window(Duration::from_secs(3600)).unwrap();
+    let day = flows.window(Duration::from_secs(24 * 3600)).unwrap();
+    assert!(minute.is_empty());
+    assert!(hour.is_empty());
+    assert_eq!(usdc(0, 150), day.flow("Alice", USDC));
+}
+
+#[test]
+fn smallest_addresses_are_evicted_beyond_capacity() {
+    let mut window = FlowWindow::new(Duration::from_secs(60), 10);
+    for amount in 1..=11 {
+        let flow = usdc(amount, 0);
+        window.record((format!("owner{amount}"), USDC.to_string()), flow, start());
+    }
+    // Evicted down to 90% of the capacity
+    assert_eq!(9, window.len());
+    assert_eq!(2, window.evicted());
+    assert_eq!(Flow::default(), window.flow("owner2", USDC));
+    assert_eq!(usdc(11, 0), window.flow("owner11", USDC));
+
+    window.advance(start() + Duration::from_secs(60));
+    assert!(window.is_empty());
+}
+
+#[test]
+fn sink_writes_summaries_after_blocks() {
+    let config = Config::default();
+    let mut text = Vec::new();
+    let mut sink = FlowSink::new(
+        TextSink::new(&mut text, false),
+        FlowAggregator::new(&[Duration::from_secs(60)], 100),
+        Duration::ZERO,
+        5,
+    );
+    process_block(load_block(100), 100, &config, &mut sink).unwrap();
+    assert_eq!(
+        usdc(25, 0),
+        sink.aggregator().windows()[0].flow("Bob", USDC)
+    );
+    drop(sink);
+    assert_eq!(
+        "\
+Latest block: 100
+TX detected: Alice sent 25 USDC to Bob
+Net flow, last 1m (2 addresses):
+  +25 USDC Bob (in 25, out 0)
+  -25 USDC Alice (in 0, out 25)
+",
+        from_utf8(&text).unwrap()
+    );
+
+    let mut jsonl = Vec::new();
+    let mut sink = FlowSink::new(
+        JsonlSink::new(&mut jsonl),
+        FlowAggregator::default(),
+        Duration::ZERO,
+        1,
+    );
+    process_block(load_block(100), 100, &config, &mut sink).unwrap();
+    drop(sink);
+    let rows = from_utf8(&jsonl)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<Value>>();
+    assert_eq!(4, rows.len());
+    assert_eq!(
+        json!({
+            "type": "flows",
+            "slot": 100,
+            "window": "24h",
+            "windowSecs": 86400,
+            "addresses": 2,
+            "evicted": 0,
+            "netInflow": [{
+                "owner": "Bob",
+                "label": null,
+                "mint": USDC,
+                "inflow": 25_000_000,
+                "outflow": 0,
+                "net": 25_000_000,
+                "decimals": 6,
+            }],
+            "netOutflow": [{
+                "owner": "Alice",
+                "label": null,
+                "mint": USDC,
+                "inflow": 0,
+                "outflow": 25_000_000,
+                "net": -25_000_000,
+                "decimals": 6,
+            }],
+        }),
+        rows[3]
+    );
+}
+
+#[test]
+fn sink_uses_block_times() {
+    let config = Config::default();
+    let mut text = Vec::new();
+    let mut sink = FlowSink::new(
+        TextSink::new(&mut text, false),
+        FlowAggregator::new(&[Duration::from_secs(60)], 100),
+        Duration::from_secs(30),
+        5,
+    );
+    // Replayed blocks a minute and a half apart, long before the wall clock
+    for (slot, block_time) in [(100, 1_700_000_000), (101, 1_700_000_090)] {
+        let mut block = load_block(slot);
+        block.block_time = Some(block_time);
+        process_block(block, slot, &config, &mut sink).unwrap();
+    }
+    assert_eq!(
+        Flow::default(),
+        sink.aggregator().windows()[0].flow("Bob", USDC)
+    );
+    drop(sink);
+    assert_eq!(
+        "\
+Latest block: 100
+TX detected: Alice sent 25 USDC to Bob
+Latest block: 101
+TX detected: Carol sent 1,989.48 USDC to Dave
+Net flow, last 1m (2 addresses):
+  +1,989.48 USDC Dave (in 1,989.48, out 0)
+  -1,989.48 USDC Carol (in 0, out 1,989.48)
+",
+        from_utf8(&text).unwrap()
+    );
+}
+
+#[test]
+fn sink_forwards_other_events() {
+    let mut text = Vec::new();
+    let mut sink = FlowSink::new(
+        TextSink::new(&mut text, false),
+        FlowAggregator::default(),
+        Duration::from_secs(3600),
+        5,
+    );
+    sink.on_pattern(&Pattern {
+        kind: PatternKind::RoundTrip,
+        owners: vec!["Alice".to_string(), "Bob".to_string()],
+        mint: USDC.to_string(),
+        amount: 1_000_000,
+        decimals: 6,
+        transfers: 2,
+        signatures: vec!["sig1".to_string()],
+        first_slot: 10,
+        last_slot: 10,
+    })
+    .unwrap();
+    drop(sink);
+    assert_eq!(
+        "Suspicious round trip: Alice -> Bob -> Alice, 2 USDC transfers in slots 10-10 (signatures sig1)\n",
+        from_utf8(&text).unwrap()
+    );
+}
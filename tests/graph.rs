@@ -0,0 +1,127 @@
+use pretty_assertions::assert_eq;
+use solana_transfer_monitor::{
+    graph::{Direction, GraphFormat, TransferGraph},
+    instructions::Transfer,
+    labels::{Category, Label},
+};
+use std::{path::Path, str::from_utf8};
+
+mod common;
+
+use common::transfer;
+
+const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+
+/// Eve -> Alice -> Bob -> Carol -> Dave, with Alice paying Bob twice in USDC and once in USDT
+fn graph() -> TransferGraph {
+    let mut graph = TransferGraph::new();
+    graph.add(10, &transfer("Eve", "Alice", 500));
+    graph.add(10, &transfer("Alice", "Bob", 100));
+    graph.add(12, &transfer("Alice", "Bob", 50));
+    graph.add(
+        12,
+        &Transfer {
+            mint: USDT.to_string(),
+            ..transfer("Alice", "Bob", 7)
+        },
+    );
+    graph.add(13, &transfer("Bob", "Carol", 80));
+    graph.add(14, &transfer("Carol", "Dave", 60));
+    graph
+}
+
+fn edges(graph: &TransferGraph) -> Vec<String> {
+    graph
+        .edges()
+        .map(|edge| format!("{}->{}", edge.source, edge.destination))
+        .collect()
+}
+
+#[test]
+fn edges_sum_amount_and_count_per_mint() {
+    let graph = graph();
+    let usdc = graph
+        .edge(
+            "Alice",
+            "Bob",
+            "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+        )
+        .unwrap();
+    assert_eq!(150_000_000, usdc.amount);
+    assert_eq!(150.0, usdc.weight());
+    assert_eq!(2, usdc.count);
+    assert_eq!((10, 12), (usdc.first_slot, usdc.last_slot));
+    assert_eq!(1, graph.edge("Alice", "Bob", USDT).unwrap().count);
+    assert_eq!(5, graph.nodes().count());
+    assert_eq!(5, graph.edges().count());
+}
+
+#[test]
+fn traces_hops_forward_and_backward() {
+    let graph = graph();
+    assert_eq!(
+        vec!["Alice->Bob", "Alice->Bob"],
+        edges(&graph.trace("Alice", 1, Direction::Forward))
+    );
+    assert_eq!(
+        vec!["Alice->Bob", "Alice->Bob", "Bob->Carol"],
+        edges(&graph.trace("Alice", 2, Direction::Forward))
+    );
+    assert_eq!(
+        vec!["Alice->Bob", "Alice->Bob", "Bob->Carol", "Eve->Alice"],
+        edges(&graph.trace("Carol", 3, Direction::Backward))
+    );
+    assert_eq!(
+        0,
+        graph.trace("Dave", 5, Direction::Forward).edges().count()
+    );
+}
+
+#[test]
+fn writes_dot_graphml_and_gexf() {
+    let mut graph = TransferGraph::new();
+    graph.add(
+        10,
+        &Transfer {
+            source_label: Some(Label {
+                name: "A&B \"Exchange\"".to_string(),
+                category: Category::Cex,
+            }),
+            ..transfer("Alice", "Bob", 25)
+        },
+    );
+
+    let write = |format| {
+        let mut output = Vec::new();
+        graph.write(format, &mut output).unwrap();
+        from_utf8(&output).unwrap().to_string()
+    };
+    assert_eq!(
+        r#"digraph transfers {
+  "Alice" [label="A&B \"Exchange\"", category="cex"];
+  "Bob";
+  "Alice" -> "Bob" [label="25 USDC x1", mint="EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", amount=25000000, count=1];
+}
+"#,
+        write(GraphFormat::Dot)
+    );
+
+    let graphml = write(GraphFormat::GraphMl);
+    assert!(graphml.contains(r#"<graph id="transfers" edgedefault="directed">"#));
+    assert!(graphml.contains(r#"<node id="Alice"><data key="label">A&amp;B &quot;Exchange&quot;</data><data key="category">cex</data></node>"#));
+    assert!(graphml.contains(r#"<edge id="e0" source="Alice" target="Bob"><data key="mint">EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v</data><data key="amount">25000000</data><data key="weight">25</data><data key="count">1</data>"#));
+
+    let gexf = write(GraphFormat::Gexf);
+    assert!(gexf.contains(r#"<node id="Alice" label="A&amp;B &quot;Exchange&quot;">"#));
+    assert!(gexf.contains(r#"<node id="Bob" label="Bob"/>"#));
+    assert!(gexf.contains(r#"<edge id="0" source="Alice" target="Bob" weight="25" label="USDC">"#));
+}
+
+#[test]
+fn format_follows_the_extension() {
+    let format = |path: &str| GraphFormat::from_path(Path::new(path)).ok();
+    assert_eq!(Some(GraphFormat::GraphMl), format("flows.graphml"));
+    assert_eq!(Some(GraphFormat::Gexf), format("flows.GEXF"));
+    assert_eq!(Some(GraphFormat::Dot), format("flows.gv"));
+    assert_eq!(None, format("flows.csv"));
+}
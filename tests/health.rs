@@ -0,0 +1,55 @@
+use solana_transfer_monitor::{config::Config, metrics};
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+mod common;
+
+use common::{watch, MockRpc};
+
+fn get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn health_and_readiness_follow_processed_blocks() {
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let config = Config {
+        rpc_url: mock_rpc.url.clone(),
+        max_blocks: Some(2),
+        metrics_addr: Some("127.0.0.1:0".to_string()),
+        ..Default::default()
+    };
+    let addr = metrics::serve("127.0.0.1:0", config.max_block_age, config.max_slot_lag).unwrap();
+    // A block is required within zero seconds, so only healthy until startup is older than that
+    let stale_addr = metrics::serve("127.0.0.1:0", Duration::ZERO, config.max_slot_lag).unwrap();
+
+    assert!(get(addr, "/healthz").starts_with("HTTP/1.1 200"));
+    let response = get(addr, "/readyz");
+    assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+    assert!(response.ends_with("no block processed yet"));
+
+    watch(&config, &mut Vec::new()).unwrap();
+    assert!(get(addr, "/healthz").starts_with("HTTP/1.1 200"));
+    assert!(get(addr, "/readyz").starts_with("HTTP/1.1 200"));
+
+    std::thread::sleep(Duration::from_millis(10));
+    let response = get(stale_addr, "/healthz");
+    assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+    assert!(response.contains("no block processed for"));
+
+    metrics::metrics().slot_lag.set(1_000);
+    let response = get(addr, "/readyz");
+    assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+    assert!(response.ends_with("1000 slots behind"));
+}
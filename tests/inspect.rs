@@ -0,0 +1,145 @@
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use solana_transaction_status::EncodedTransactionWithStatusMeta;
+use solana_transfer_monitor::{
+    config::Config,
+    decode::TOKEN_2022_PROGRAM_ID,
+    inspect::{inspect_transaction, Outcome, SkipReason},
+    write_transaction_transfers,
+};
+use std::str::from_utf8;
+
+mod common;
+
+use common::load_block_json;
+
+fn token_instruction(type_: &str, info: Value) -> Value {
+    json!({
+        "program": "spl-token",
+        "programId": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+        "parsed": { "type": type_, "info": info },
+        "stackHeight": 2,
+    })
+}
+
+/// The transaction of block 101 with extra inner instructions
+fn transaction_with_inner_instructions(extra: Vec<Value>) -> EncodedTransactionWithStatusMeta {
+    let mut transaction = load_block_json(101)["transactions"][0].take();
+    let instructions = transaction["meta"]["innerInstructions"][0]["instructions"]
+        .as_array_mut()
+        .unwrap();
+    instructions.extend(extra);
+    serde_json::from_value(transaction).unwrap()
+}
+
+#[test]
+fn explains_every_inner_instruction() {
+    let transaction = transaction_with_inner_instructions(vec![
+        token_instruction(
+            "transfer",
+            json!({ "source": "CarolUsdc", "destination": "NewUsdc", "authority": "Carol", "amount": "5" }),
+        ),
+        token_instruction(
+            "closeAccount",
+            json!({ "account": "CarolUsdc", "destination": "Carol", "owner": "Carol" }),
+        ),
+        json!({
+            "program": "system",
+            "programId": "11111111111111111111111111111111",
+            "parsed": { "type": "transfer", "info": { "source": "Carol", "destination": "Dave", "lamports": 1 } },
+            "stackHeight": 2,
+        }),
+    ]);
+    let inspection = inspect_transaction(&transaction, &Config::default()).unwrap();
+
+    let outcomes = inspection
+        .instructions
+        .iter()
+        .map(|instruction| match &instruction.outcome {
+            Outcome::Transfer(transfer) => transfer.to_string(),
+            Outcome::Skipped(reason) => reason.to_string(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            SkipReason::NotTokenProgram.to_string(),
+            "TX detected: Carol sent 1,989.48 USDC to Dave".to_string(),
+            SkipReason::UnknownAccount("NewUsdc".to_string()).to_string(),
+            SkipReason::NotTransfer("closeAccount".to_string()).to_string(),
+            SkipReason::NotTokenProgram.to_string(),
+        ],
+        outcomes
+    );
+    assert_eq!(1, inspection.transfers().count());
+    assert_eq!(
+        Some((
+            "Carol".to_string(),
+            "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(),
+            6
+        )),
+        inspection.accounts.get("CarolUsdc").cloned()
+    );
+
+    let text = inspection.to_string();
+    assert!(text.contains(
+        "    #0.1 Token Program transfer\n      CarolUsdc (owner Carol, USDC) -> NewUsdc (unknown), raw amount 5\n      skipped: NewUsdc has no token balance before the transaction\n"
+    ));
+}
+
+#[test]
+fn untracked_mints_are_skipped() {
+    let transaction = transaction_with_inner_instructions(Vec::new());
+    let config = Config {
+        mints: vec!["Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB".to_string()],
+        ..Default::default()
+    };
+    let inspection = inspect_transaction(&transaction, &config).unwrap();
+    assert!(matches!(
+        &inspection.instructions[1].outcome,
+        Outcome::Skipped(SkipReason::OtherMint(mint)) if mint == "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
+    ));
+}
+
+#[test]
+fn failed_transactions_are_not_decoded() {
+    let mut transaction = load_block_json(101)["transactions"][0].take();
+    transaction["meta"]["err"] = json!({ "InstructionError": [0, "InvalidAccountData"] });
+    let transaction: EncodedTransactionWithStatusMeta =
+        serde_json::from_value(transaction).unwrap();
+    let inspection = inspect_transaction(&transaction, &Config::default()).unwrap();
+    assert!(inspection.error.is_some());
+    assert!(inspection.instructions.is_empty());
+}
+
+/// The transaction of block 101 with its transfer made by a top-level spl-token-2022 instruction instead of Jupiter
+fn transaction_with_top_level_token_2022_transfer() -> EncodedTransactionWithStatusMeta {
+    let mut transaction = load_block_json(101)["transactions"][0].take();
+    let mut instruction = transaction["meta"]["innerInstructions"][0]["instructions"][0].take();
+    instruction["program"] = json!("spl-token-2022");
+    instruction["programId"] = json!(TOKEN_2022_PROGRAM_ID);
+    instruction["stackHeight"] = json!(null);
+    transaction["transaction"]["message"]["instructions"] = json!([instruction]);
+    transaction["meta"]["innerInstructions"] = json!([]);
+    serde_json::from_value(transaction).unwrap()
+}
+
+#[test]
+fn top_level_token_2022_transfers_are_decoded() {
+    let transaction = transaction_with_top_level_token_2022_transfer();
+
+    let mut buffer: Vec<u8> = Vec::new();
+    write_transaction_transfers(transaction.clone(), &Config::default(), &mut buffer).unwrap();
+    assert_eq!(
+        "TX detected: Carol sent 1,989.48 USDC to Dave\n",
+        from_utf8(&buffer).unwrap()
+    );
+
+    let inspection = inspect_transaction(&transaction, &Config::default()).unwrap();
+    assert_eq!(1, inspection.instructions.len());
+    let transfer = inspection.transfers().next().unwrap();
+    assert_eq!("0", transfer.instruction_path());
+    assert!(transfer.call_stack.is_empty());
+    assert!(inspection
+        .to_string()
+        .contains("  #0 Token-2022 Program transfer\n"));
+}
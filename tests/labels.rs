@@ -0,0 +1,99 @@
+use pretty_assertions::assert_eq;
+use serde_json::json;
+use solana_transfer_monitor::{
+    config::Config,
+    instructions::Transfer,
+    labels::{Category, Label, Labels},
+    process_block,
+    sinks::transfer_json,
+};
+use std::{env, fs, str::from_utf8};
+
+mod common;
+
+use common::{load_block, write_block};
+
+fn label(name: &str, category: Category) -> Option<Label> {
+    Some(Label {
+        name: name.to_string(),
+        category,
+    })
+}
+
+#[test]
+fn bundled_labels_name_known_pools() {
+    let labels = Labels::bundled();
+    assert_eq!(
+        label("Raydium Authority V4", Category::DexPool).as_ref(),
+        labels.get("5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1")
+    );
+    assert!(labels.get("Alice").is_none());
+}
+
+#[test]
+fn csv_and_json_labels() {
+    let mut labels = Labels::default();
+    labels
+        .extend_csv(
+            "\
+address,name,category
+# comment
+
+Alice,Alice's Exchange, Hot Wallet,CEX
+Bob,Bob,
+",
+        )
+        .unwrap();
+    assert_eq!(
+        label("Alice's Exchange, Hot Wallet", Category::Cex).as_ref(),
+        labels.get("Alice")
+    );
+    assert_eq!(label("Bob", Category::Other).as_ref(), labels.get("Bob"));
+
+    let path = env::temp_dir().join(format!("labels-{}.json", std::process::id()));
+    fs::write(
+        &path,
+        r#"[{"address": "Alice", "name": "Alice Treasury", "category": "treasury"}]"#,
+    )
+    .unwrap();
+    let result = labels.load(&path);
+    fs::remove_file(&path).unwrap();
+    result.unwrap();
+    assert_eq!(
+        label("Alice Treasury", Category::Treasury).as_ref(),
+        labels.get("Alice")
+    );
+    assert_eq!(2, labels.len());
+
+    let error = labels.extend_csv("Carol,Carol,casino").unwrap_err();
+    assert_eq!("on line 1: unknown category casino", format!("{error:#}"));
+}
+
+#[test]
+fn transfers_show_owner_or_token_account_labels() {
+    let mut config = Config::default();
+    config
+        .labels
+        .extend_csv("Alice,Alice Exchange,CEX\nBobUsdc,Bob's Vault,dex-pool")
+        .unwrap();
+
+    let mut output = Vec::new();
+    write_block(load_block(100), 100, &config, &mut output).unwrap();
+    assert_eq!(
+        "Latest block: 100\nTX detected: Alice Exchange sent 25 USDC to Bob's Vault\n",
+        from_utf8(&output).unwrap()
+    );
+
+    let mut transfers: Vec<Transfer> = Vec::new();
+    process_block(load_block(100), 100, &config, &mut transfers).unwrap();
+    let json = transfer_json(100, &transfers[0]);
+    assert_eq!("Alice", json["source"]);
+    assert_eq!(
+        json!({ "name": "Alice Exchange", "category": "cex" }),
+        json["sourceLabel"]
+    );
+    assert_eq!(
+        json!({ "name": "Bob's Vault", "category": "dex-pool" }),
+        json["destinationLabel"]
+    );
+}
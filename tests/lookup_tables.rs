@@ -0,0 +1,152 @@
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use solana_sdk::{
+    bs58,
+    hash::Hash,
+    instruction::CompiledInstruction,
+    message::{
+        v0::{self, MessageAddressTableLookup},
+        MessageHeader, VersionedMessage,
+    },
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::VersionedTransaction,
+};
+use solana_transaction_status::{
+    EncodableWithMeta, TransactionStatusMeta, UiConfirmedBlock, UiTransactionEncoding,
+};
+use solana_transfer_monitor::{
+    decode::TOKEN_PROGRAM_ID, lookup_tables::LookupTableCache,
+    utils::get_all_successful_usdc_transactions, write_block_transfers,
+};
+use std::{collections::HashMap, str::from_utf8};
+
+mod common;
+
+use common::{token_balance, LookupTables};
+
+const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+const AUTHORITY: &str = "7rhxnLV8C77o6d8oz26AgK8x8m5ePsdeRawjqvojbjnQ";
+const SOURCE: &str = "3YD74MctB2RCNGaMUYQRGrTdPUFh6ZMHjWau5f6Br8mR";
+const DESTINATION: &str = "5ZuR4supLRJ8eQvpqur2pfhNnjuu1guzaLbzeWv4bM7E";
+const JUPITER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+const LOOKUP_TABLE: &str = "CTz5UMLQm2SRWHzQnU62Pi4yJqbNGjgRBHqqp6oDHfF7";
+
+// Static account keys: 0 authority, 1 source, 2 token program, 3 Jupiter
+// Loaded from the lookup table: 4 destination (writable), 5 mint (readonly)
+fn v0_transaction() -> Value {
+    let message = v0::Message {
+        header: MessageHeader {
+            num_required_signatures: 1,
+            num_readonly_signed_accounts: 0,
+            num_readonly_unsigned_accounts: 2,
+        },
+        account_keys: [AUTHORITY, SOURCE, TOKEN_PROGRAM_ID, JUPITER]
+            .map(|pubkey| pubkey.parse::<Pubkey>().unwrap())
+            .to_vec(),
+        recent_blockhash: Hash::default(),
+        instructions: vec![CompiledInstruction::new_from_raw_parts(3, vec![], vec![])],
+        address_table_lookups: vec![MessageAddressTableLookup {
+            account_key: LOOKUP_TABLE.parse().unwrap(),
+            writable_indexes: vec![1],
+            readonly_indexes: vec![0],
+        }],
+    };
+    let transaction = VersionedTransaction {
+        signatures: vec![Signature::default()],
+        message: VersionedMessage::V0(message),
+    };
+    let encoded = transaction.encode_with_meta(
+        UiTransactionEncoding::Base64,
+        &TransactionStatusMeta::default(),
+    );
+    serde_json::to_value(encoded).unwrap()
+}
+
+fn block(loaded_addresses: Option<Value>) -> UiConfirmedBlock {
+    let mut transfer_data = vec![3];
+    transfer_data.extend(1_989_480_000u64.to_le_bytes());
+    let mut meta = json!({
+        "err": null,
+        "status": { "Ok": null },
+        "fee": 5000,
+        "preBalances": [],
+        "postBalances": [],
+        "innerInstructions": [{
+            "index": 0,
+            "instructions": [{
+                "programIdIndex": 2,
+                "accounts": [1, 4, 0],
+                "data": bs58::encode(transfer_data).into_string(),
+                "stackHeight": 2,
+            }],
+        }],
+        "logMessages": [],
+        "preTokenBalances": [token_balance(1, "Sender", "2000000000"), token_balance(4, "Receiver", "0")],
+        "postTokenBalances": [token_balance(1, "Sender", "10520000"), token_balance(4, "Receiver", "1989480000")],
+        "rewards": [],
+    });
+    if let Some(loaded_addresses) = loaded_addresses {
+        meta["loadedAddresses"] = loaded_addresses;
+    }
+    serde_json::from_value(json!({
+        "previousBlockhash": "11111111111111111111111111111111",
+        "blockhash": "11111111111111111111111111111111",
+        "parentSlot": 0,
+        "blockTime": null,
+        "blockHeight": null,
+        "transactions": [{ "transaction": v0_transaction(), "meta": meta, "version": 0 }],
+    }))
+    .unwrap()
+}
+
+const EXPECTED: &str = "\
+Latest block: 1
+TX detected: Sender sent 1,989.48 USDC to Receiver
+";
+
+#[test]
+fn resolve_loaded_addresses_from_meta() {
+    let loaded_addresses = json!({ "writable": [DESTINATION], "readonly": [USDC] });
+    let mut buffer: Vec<u8> = Vec::new();
+    write_block_transfers(block(Some(loaded_addresses)), 1, &mut buffer).unwrap();
+    assert_eq!(EXPECTED, from_utf8(&buffer).unwrap());
+}
+
+#[test]
+fn missing_loaded_addresses_is_an_error() {
+    let mut buffer: Vec<u8> = Vec::new();
+    assert!(write_block_transfers(block(None), 1, &mut buffer).is_err());
+}
+
+#[test]
+fn resolve_loaded_addresses_from_lookup_table_cache() {
+    let mut tables = LookupTables(HashMap::from([(
+        LOOKUP_TABLE.to_string(),
+        vec![USDC.to_string(), DESTINATION.to_string()],
+    )]));
+    let mut cache = LookupTableCache::new();
+
+    let mut first_block = block(None);
+    cache
+        .fill_loaded_addresses(&mut tables, &mut first_block)
+        .unwrap();
+    let mut second_block = block(None);
+    cache
+        .fill_loaded_addresses(&mut tables, &mut second_block)
+        .unwrap();
+    assert_eq!(1, cache.fetches());
+
+    let mut buffer: Vec<u8> = Vec::new();
+    write_block_transfers(second_block, 1, &mut buffer).unwrap();
+    assert_eq!(EXPECTED, from_utf8(&buffer).unwrap());
+}
+
+#[test]
+fn text_search_decodes_v0_transactions() {
+    let loaded_addresses = json!({ "writable": [DESTINATION], "readonly": [USDC] });
+    let signatures = get_all_successful_usdc_transactions(block(Some(loaded_addresses))).unwrap();
+    assert_eq!(vec![Signature::default().to_string()], signatures);
+    assert!(get_all_successful_usdc_transactions(block(None)).is_err());
+}
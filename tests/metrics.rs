@@ -0,0 +1,61 @@
+use solana_transfer_monitor::{config::Config, metrics, USDC_MINT_ADDRESS};
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+};
+
+mod common;
+
+use common::{watch, MockRpc};
+
+fn get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn metrics_endpoint_reports_polled_blocks() {
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let config = Config {
+        rpc_url: mock_rpc.url.clone(),
+        max_blocks: Some(2),
+        metrics_addr: Some("127.0.0.1:0".to_string()),
+        ..Default::default()
+    };
+    let addr = metrics::serve(
+        config.metrics_addr.as_deref().unwrap(),
+        config.max_block_age,
+        config.max_slot_lag,
+    )
+    .unwrap();
+    watch(&config, &mut Vec::new()).unwrap();
+
+    let response = get(addr, "/metrics");
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    for line in [
+        "transfer_monitor_blocks_processed_total 2".to_string(),
+        format!("transfer_monitor_transfers_total{{mint=\"{USDC_MINT_ADDRESS}\"}} 2"),
+        "transfer_monitor_usdc_volume_total 2014.48".to_string(),
+        "transfer_monitor_rpc_requests_total{method=\"getBlock\"} 2".to_string(),
+        "transfer_monitor_rpc_requests_total{method=\"getBlocks\"} 2".to_string(),
+        // The starting slot and one per poll for slot lag
+        "transfer_monitor_rpc_requests_total{method=\"getSlot\"} 3".to_string(),
+        "transfer_monitor_slot_lag 0".to_string(),
+        "transfer_monitor_block_processing_seconds_count 2".to_string(),
+    ] {
+        assert!(
+            response.lines().any(|l| l == line),
+            "missing {line} in {response}"
+        );
+    }
+    assert!(!response.contains("transfer_monitor_rpc_errors_total"));
+
+    assert!(get(addr, "/").starts_with("HTTP/1.1 404"));
+}
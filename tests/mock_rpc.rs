@@ -0,0 +1,41 @@
+use pretty_assertions::assert_eq;
+use solana_transfer_monitor::{config::Config, write_block_transfers};
+use std::str::from_utf8;
+
+mod common;
+
+use common::{load_block, watch, MockRpc};
+
+#[test]
+fn fixture_block_transfers() {
+    let mut buffer: Vec<u8> = Vec::new();
+    write_block_transfers(load_block(100), 100, &mut buffer).unwrap();
+
+    let expected = "\
+Latest block: 100
+TX detected: Alice sent 25 USDC to Bob
+";
+    assert_eq!(expected, from_utf8(&buffer).unwrap());
+}
+
+#[test]
+fn run_polls_blocks_from_mock_rpc() {
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let config = Config {
+        rpc_url: mock_rpc.url.clone(),
+        max_blocks: Some(2),
+        ..Default::default()
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+    watch(&config, &mut buffer).unwrap();
+
+    let expected = "\
+Latest block: 100
+TX detected: Alice sent 25 USDC to Bob
+Latest block: 101
+TX detected: Carol sent 1,989.48 USDC to Dave
+";
+    assert_eq!(expected, from_utf8(&buffer).unwrap());
+    // The tip only advances one block per poll, so the second block needs a second getBlocks
+    assert_eq!(2, mock_rpc.get_blocks_calls());
+}
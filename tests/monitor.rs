@@ -0,0 +1,114 @@
+use pretty_assertions::assert_eq;
+use solana_transfer_monitor::{
+    config::Config,
+    monitor::{Event, Monitor},
+    patterns::PatternConfig,
+    sources::FileBlockSource,
+};
+use std::path::Path;
+
+mod common;
+
+use common::{fixture_path, load_block_json, MockRpc};
+
+fn describe(event: Event) -> String {
+    match event {
+        Event::BlockStart { slot, .. } => format!("start {slot}"),
+        Event::Transfer(transfer) => transfer.to_string(),
+        Event::Swap(swap) => swap.to_string(),
+        Event::Discrepancy(discrepancy) => discrepancy.to_string(),
+        Event::Pattern(pattern) => pattern.to_string(),
+        Event::ScreeningHit(hit) => hit.to_string(),
+        Event::BlockEnd { slot } => format!("end {slot}"),
+    }
+}
+
+fn describe_all(monitor: Monitor) -> Vec<String> {
+    monitor
+        .map(|event| describe(event.unwrap()))
+        .collect::<Vec<_>>()
+}
+
+#[test]
+fn events_from_rpc_starting_at_a_slot() {
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let monitor = Monitor::builder()
+        .rpc_url(&mock_rpc.url)
+        .start_slot(101)
+        .max_blocks(1)
+        .build()
+        .unwrap();
+
+    let expected = vec![
+        "start 101",
+        "TX detected: Carol sent 1,989.48 USDC to Dave",
+        "end 101",
+    ];
+    assert_eq!(expected, describe_all(monitor));
+}
+
+#[test]
+fn filters_apply_to_transfers_only() {
+    let monitor = Monitor::builder()
+        .source(FileBlockSource::new(&fixture_path(100)).unwrap())
+        .filter(|transfer| transfer.amount > 1_000_000_000)
+        .build()
+        .unwrap();
+    assert_eq!(vec!["start 100", "end 100"], describe_all(monitor));
+}
+
+#[test]
+fn untracked_mints_are_not_emitted() {
+    let monitor = Monitor::builder()
+        .source(FileBlockSource::new(&fixture_path(100)).unwrap())
+        .mints(["Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB"])
+        .build()
+        .unwrap();
+    assert_eq!(vec!["start 100", "end 100"], describe_all(monitor));
+}
+
+#[test]
+fn patterns_are_emitted_after_the_transfer_completing_them() {
+    // Bob sends the 25 USDC back to Alice in the next block
+    let mut block = load_block_json(100);
+    let transaction = &mut block["transactions"][0];
+    transaction["transaction"]["signatures"][0] = "sig101".into();
+    let info =
+        &mut transaction["meta"]["innerInstructions"][0]["instructions"][0]["parsed"]["info"];
+    info["source"] = "BobUsdc".into();
+    info["destination"] = "AliceUsdc".into();
+    info["authority"] = "Bob".into();
+    let mock_rpc = MockRpc::start_with_blocks(vec![(100, load_block_json(100)), (101, block)]);
+
+    let monitor = Monitor::builder()
+        .config(Config {
+            rpc_url: mock_rpc.url.clone(),
+            start_slot: Some(100),
+            max_blocks: Some(2),
+            patterns: Some(PatternConfig::default()),
+            ..Config::default()
+        })
+        // Patterns include transfers the filters drop
+        .filter(|transfer| transfer.source_owner == "Bob")
+        .build()
+        .unwrap();
+    let expected = vec![
+        "start 100",
+        "end 100",
+        "start 101",
+        "TX detected: Bob sent 25 USDC to Alice",
+        "Suspicious round trip: Alice -> Bob -> Alice, 2 USDC transfers in slots 100-101 (signatures sig100, sig101)",
+        "end 101",
+    ];
+    assert_eq!(expected, describe_all(monitor));
+}
+
+#[test]
+fn iteration_stops_after_an_error() {
+    let mut monitor = Monitor::builder()
+        .source(FileBlockSource::new(Path::new("does/not/exist.json")).unwrap())
+        .build()
+        .unwrap();
+    assert!(monitor.next().unwrap().is_err());
+    assert!(monitor.next().is_none());
+}
@@ -0,0 +1,304 @@
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use solana_transfer_monitor::{
+    config::Config,
+    instructions::Transfer,
+    labels::{Category, Label},
+    patterns::{Pattern, PatternConfig, PatternDetector, PatternKind},
+    sinks::{JsonlSink, TransferSink},
+    sources::ReaderBlockSource,
+    swaps::Swap,
+};
+use std::{io::Cursor, str::from_utf8};
+
+mod common;
+
+use common::{load_block_json, write_source};
+
+fn transfer(signature: &str, from: &str, to: &str, usdc: u64) -> Transfer {
+    Transfer {
+        signature: signature.to_string(),
+        ..common::transfer(from, to, usdc)
+    }
+}
+
+/// Patterns found for the transfers, as (kind, owners, signatures)
+fn observe_all(
+    detector: &mut PatternDetector,
+    transfers: &[(u64, Transfer)],
+) -> Vec<(PatternKind, String, Vec<String>)> {
+    transfers
+        .iter()
+        .flat_map(|(slot, transfer)| detector.observe(*slot, transfer))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .chain(detector.finish())
+        .map(|pattern| {
+            (
+                pattern.kind,
+                pattern.owners.join(" -> "),
+                pattern.signatures,
+            )
+        })
+        .collect()
+}
+
+fn signatures(signatures: &[&str]) -> Vec<String> {
+    signatures.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn round_trips_within_one_transaction_or_the_window() {
+    let mut detector = PatternDetector::new(PatternConfig::default());
+    // Back and forth between the same two owners in one transaction, like 3EPNiMGp... on mainnet
+    let found = observe_all(
+        &mut detector,
+        &[
+            (10, transfer("3EPN", "Alice", "Bob", 100)),
+            (10, transfer("3EPN", "Bob", "Alice", 60)),
+            (12, transfer("sig12", "Carol", "Dave", 5)),
+        ],
+    );
+    assert_eq!(
+        vec![(
+            PatternKind::RoundTrip,
+            "Alice -> Bob".to_string(),
+            signatures(&["3EPN"])
+        )],
+        found
+    );
+
+    // Transfers older than the window are forgotten
+    let mut detector = PatternDetector::new(PatternConfig::default());
+    let found = observe_all(
+        &mut detector,
+        &[
+            (10, transfer("sig10", "Alice", "Bob", 100)),
+            (161, transfer("sig161", "Bob", "Alice", 100)),
+        ],
+    );
+    assert!(found.is_empty());
+}
+
+#[test]
+fn cycles_up_to_the_maximum_length() {
+    let transfers = [
+        (10, transfer("sig1", "Alice", "Bob", 100)),
+        (11, transfer("sig2", "Bob", "Carol", 99)),
+        (12, transfer("sig3", "Carol", "Dave", 98)),
+        (13, transfer("sig4", "Dave", "Alice", 97)),
+        // Eve paid Frank before Alice paid her, so the tokens did not go around
+        (14, transfer("sig5", "Eve", "Frank", 1)),
+        (15, transfer("sig6", "Alice", "Eve", 1)),
+        (16, transfer("sig7", "Frank", "Alice", 1)),
+        (17, transfer("sig8", "Eve", "Frank", 1)),
+        (18, transfer("sig9", "Frank", "Alice", 1)),
+    ];
+    let mut detector = PatternDetector::new(PatternConfig::default());
+    assert_eq!(
+        vec![
+            (
+                PatternKind::Cycle,
+                "Alice -> Bob -> Carol -> Dave".to_string(),
+                signatures(&["sig1", "sig2", "sig3", "sig4"])
+            ),
+            (
+                PatternKind::Cycle,
+                "Alice -> Eve -> Frank".to_string(),
+                signatures(&["sig6", "sig8", "sig9"])
+            ),
+        ],
+        observe_all(&mut detector, &transfers)
+    );
+
+    let mut detector = PatternDetector::new(PatternConfig {
+        max_cycle_length: 3,
+        ..PatternConfig::default()
+    });
+    assert_eq!(
+        vec![(
+            PatternKind::Cycle,
+            "Alice -> Eve -> Frank".to_string(),
+            signatures(&["sig6", "sig8", "sig9"])
+        )],
+        observe_all(&mut detector, &transfers)
+    );
+}
+
+#[test]
+fn cycles_only_follow_the_latest_transfers_of_each_owner() {
+    let mut transfers = vec![(10, transfer("sig1", "Alice", "Exchange", 100))];
+    // The exchange paid Bob before paying out to many others
+    transfers.push((11, transfer("sig2", "Exchange", "Bob", 100)));
+    for n in 0..3 {
+        let customer = format!("Customer{n}");
+        transfers.push((
+            12,
+            transfer(&format!("payout{n}"), "Exchange", &customer, 1),
+        ));
+    }
+    transfers.push((13, transfer("sig3", "Bob", "Alice", 100)));
+
+    let mut detector = PatternDetector::new(PatternConfig::default());
+    assert_eq!(
+        vec![(
+            PatternKind::Cycle,
+            "Alice -> Exchange -> Bob".to_string(),
+            signatures(&["sig1", "sig2", "sig3"])
+        )],
+        observe_all(&mut detector, &transfers)
+    );
+
+    let mut detector = PatternDetector::new(PatternConfig {
+        max_fan_out: 3,
+        ..PatternConfig::default()
+    });
+    assert!(observe_all(&mut detector, &transfers).is_empty());
+}
+
+#[test]
+fn swap_legs_and_dex_pools_are_left_out() {
+    let pool = Label {
+        name: "Pool".to_string(),
+        category: Category::DexPool,
+    };
+    let mut detector = PatternDetector::new(PatternConfig::default());
+    let found = observe_all(
+        &mut detector,
+        &[
+            (10, transfer("sig1", "Alice", "Pool", 100)),
+            (
+                11,
+                Transfer {
+                    source_label: Some(pool),
+                    ..transfer("sig2", "Pool", "Alice", 100)
+                },
+            ),
+        ],
+    );
+    assert!(found.is_empty());
+
+    // Bob swaps through a router in instruction 1, then pays Alice back in instruction 2
+    let mut detector = PatternDetector::new(PatternConfig::default());
+    detector.observe(10, &transfer("sig1", "Alice", "Bob", 100));
+    for (instruction_index, from, to) in [
+        (1, "Bob", "Router"),
+        (1, "Router", "Bob"),
+        (2, "Bob", "Alice"),
+    ] {
+        let leg = Transfer {
+            instruction_index,
+            ..transfer("sig2", from, to, 100)
+        };
+        assert!(detector.observe(11, &leg).is_empty());
+    }
+    detector.exclude_swap(&Swap {
+        signature: "sig2".to_string(),
+        instruction_index: 1,
+        ..Default::default()
+    });
+    let found = detector
+        .finish()
+        .into_iter()
+        .map(|pattern| (pattern.kind, pattern.owners.join(" -> ")))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![(PatternKind::RoundTrip, "Alice -> Bob".to_string())],
+        found
+    );
+}
+
+#[test]
+fn ping_pong_of_equal_amounts_is_reported_once() {
+    let mut detector = PatternDetector::new(PatternConfig {
+        ping_pong_repeats: 3,
+        ..PatternConfig::default()
+    });
+    let patterns = [
+        (10, "sig1", "Alice", "Bob", 500),
+        (11, "sig2", "Bob", "Alice", 500),
+        (12, "sig3", "Alice", "Bob", 500),
+        (13, "sig4", "Bob", "Alice", 500),
+        (14, "sig5", "Alice", "Bob", 7),
+    ]
+    .into_iter()
+    .flat_map(|(slot, signature, from, to, usdc)| {
+        detector.observe(slot, &transfer(signature, from, to, usdc))
+    })
+    .filter(|pattern| pattern.kind == PatternKind::PingPong)
+    .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![Pattern {
+            kind: PatternKind::PingPong,
+            owners: vec!["Alice".to_string(), "Bob".to_string()],
+            mint: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(),
+            amount: 500_000_000,
+            decimals: 6,
+            transfers: 3,
+            signatures: signatures(&["sig1", "sig2", "sig3"]),
+            first_slot: 10,
+            last_slot: 12,
+        }],
+        patterns
+    );
+    assert_eq!(
+        "Suspicious ping-pong: Alice -> Bob -> Alice, 3 USDC transfers in slots 10-12 (signatures sig1, sig2, sig3)",
+        patterns[0].to_string()
+    );
+
+    let mut jsonl = Vec::new();
+    let mut sink = JsonlSink::new(&mut jsonl);
+    sink.on_block_start(12, None).unwrap();
+    sink.on_pattern(&patterns[0]).unwrap();
+    let row: Value = serde_json::from_str(from_utf8(&jsonl).unwrap().trim()).unwrap();
+    assert_eq!(
+        json!({
+            "type": "pattern",
+            "slot": 12,
+            "kind": "pingPong",
+            "owners": ["Alice", "Bob"],
+            "mint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+            "amount": 500_000_000,
+            "decimals": 6,
+            "transfers": 3,
+            "signatures": ["sig1", "sig2", "sig3"],
+            "firstSlot": 10,
+            "lastSlot": 12,
+        }),
+        row
+    );
+}
+
+#[test]
+fn source_writer_detects_patterns_across_blocks() {
+    // Block 100, then Bob sending the 25 USDC back to Alice in slot 101
+    let mut back = load_block_json(100);
+    back["slot"] = json!(101);
+    back["transactions"][0]["transaction"]["signatures"][0] = json!("sig101");
+    let info = &mut back["transactions"][0]["meta"]["innerInstructions"][0]["instructions"][0]
+        ["parsed"]["info"];
+    info["source"] = json!("BobUsdc");
+    info["destination"] = json!("AliceUsdc");
+    let mut block = load_block_json(100);
+    block["slot"] = json!(100);
+    let blocks = format!("{block}\n{back}\n");
+    let source = ReaderBlockSource::new(Cursor::new(blocks), "blocks");
+
+    let config = Config {
+        patterns: Some(PatternConfig::default()),
+        ..Config::default()
+    };
+    let mut output = Vec::new();
+    write_source(source, &config, &mut output).unwrap();
+    assert_eq!(
+        "\
+Latest block: 100
+TX detected: Alice sent 25 USDC to Bob
+Latest block: 101
+TX detected: Bob sent 25 USDC to Alice
+Suspicious round trip: Alice -> Bob -> Alice, 2 USDC transfers in slots 100-101 (signatures sig100, sig101)
+",
+        from_utf8(&output).unwrap()
+    );
+}
@@ -0,0 +1,84 @@
+use pretty_assertions::assert_eq;
+use solana_transaction_status::UiConfirmedBlock;
+use solana_transfer_monitor::{
+    config::Config,
+    decode::TOKEN_PROGRAM_ID,
+    programs::{program_label, CallStack},
+};
+use std::str::from_utf8;
+
+mod common;
+
+use common::{program_instruction, token_balance, transfer_instruction, write_block};
+
+const JUPITER_V6: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+const RAYDIUM_AMM_V4: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+const METEORA_DLMM: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+
+#[test]
+fn call_stack_follows_stack_height() {
+    let mut call_stack = CallStack::new(JUPITER_V6.to_string());
+    assert_eq!(vec![JUPITER_V6], call_stack.invoke(RAYDIUM_AMM_V4, Some(2)));
+    assert_eq!(
+        vec![JUPITER_V6, RAYDIUM_AMM_V4],
+        call_stack.invoke(TOKEN_PROGRAM_ID, Some(3))
+    );
+    assert_eq!(vec![JUPITER_V6], call_stack.invoke(METEORA_DLMM, Some(2)));
+    assert_eq!(
+        vec![JUPITER_V6, METEORA_DLMM],
+        call_stack.invoke(TOKEN_PROGRAM_ID, Some(3))
+    );
+    // Without stack heights only the top-level program is known
+    assert_eq!(vec![JUPITER_V6], call_stack.invoke(TOKEN_PROGRAM_ID, None));
+}
+
+#[test]
+fn unknown_programs_are_labelled_by_id() {
+    assert_eq!("Jupiter v6", program_label(JUPITER_V6));
+    assert_eq!("Unknown111", program_label("Unknown111"));
+}
+
+// Trader swaps through Raydium and Meteora pools, routed by Jupiter
+fn jupiter_route_block() -> UiConfirmedBlock {
+    common::jupiter_route_block(
+        &[
+            ("Trader", false),
+            ("TraderUsdc", false),
+            ("RaydiumUsdc", false),
+            ("MeteoraUsdc", false),
+        ],
+        vec![
+            program_instruction(RAYDIUM_AMM_V4, Some(2)),
+            transfer_instruction("TraderUsdc", "RaydiumUsdc", "10000000"),
+            program_instruction(METEORA_DLMM, Some(2)),
+            transfer_instruction("MeteoraUsdc", "TraderUsdc", "12000000"),
+        ],
+        vec![
+            token_balance(1, "Trader", "10000000"),
+            token_balance(2, "RaydiumPool", "0"),
+            token_balance(3, "MeteoraPool", "12000000"),
+        ],
+        vec![
+            token_balance(1, "Trader", "12000000"),
+            token_balance(2, "RaydiumPool", "10000000"),
+            token_balance(3, "MeteoraPool", "0"),
+        ],
+    )
+}
+
+#[test]
+fn transfers_show_invoking_programs() {
+    let config = Config {
+        show_programs: true,
+        ..Default::default()
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+    write_block(jupiter_route_block(), 1, &config, &mut buffer).unwrap();
+
+    let expected = "\
+Latest block: 1
+TX detected: Trader sent 10 USDC to RaydiumPool via Jupiter v6 > Raydium AMM v4
+TX detected: MeteoraPool sent 12 USDC to Trader via Jupiter v6 > Meteora DLMM
+";
+    assert_eq!(expected, from_utf8(&buffer).unwrap());
+}
@@ -0,0 +1,124 @@
+use pretty_assertions::assert_eq;
+use serde_json::json;
+use solana_transaction_status::UiConfirmedBlock;
+use solana_transfer_monitor::{config::Config, utils::verify_block};
+use std::str::from_utf8;
+
+mod common;
+
+use common::{token_balance, write_block};
+
+const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const JUPITER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+
+// Alice sends 25 USDC to Bob, and 20 USDC are minted to Bob in the same transaction.
+// The mint is not a transfer so only Bob's balance change is left unexplained.
+fn block_with_mint_to() -> UiConfirmedBlock {
+    let account_keys = [
+        "Alice",
+        "AliceUsdc",
+        "BobUsdc",
+        USDC,
+        TOKEN_PROGRAM,
+        JUPITER,
+    ]
+    .map(|pubkey| json!({ "pubkey": pubkey, "writable": true, "signer": false }));
+    serde_json::from_value(json!({
+        "previousBlockhash": "11111111111111111111111111111111",
+        "blockhash": "11111111111111111111111111111111",
+        "parentSlot": 0,
+        "blockTime": null,
+        "blockHeight": null,
+        "transactions": [{
+            "transaction": {
+                "signatures": ["sig1"],
+                "message": {
+                    "accountKeys": account_keys,
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    "instructions": [{ "programIdIndex": 5, "accounts": [], "data": "", "stackHeight": null }],
+                },
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [],
+                "postBalances": [],
+                "innerInstructions": [{
+                    "index": 0,
+                    "instructions": [
+                        {
+                            "program": "spl-token",
+                            "programId": TOKEN_PROGRAM,
+                            "parsed": {
+                                "type": "transfer",
+                                "info": { "source": "AliceUsdc", "destination": "BobUsdc", "authority": "Alice", "amount": "25000000" },
+                            },
+                            "stackHeight": 2,
+                        },
+                        {
+                            "program": "spl-token",
+                            "programId": TOKEN_PROGRAM,
+                            "parsed": {
+                                "type": "mintTo",
+                                "info": { "mint": USDC, "account": "BobUsdc", "mintAuthority": "Circle", "amount": "20000000" },
+                            },
+                            "stackHeight": 2,
+                        },
+                    ],
+                }],
+                "logMessages": [],
+                "preTokenBalances": [token_balance(1, "Alice", "30000000"), token_balance(2, "Bob", "0")],
+                "postTokenBalances": [token_balance(1, "Alice", "5000000"), token_balance(2, "Bob", "45000000")],
+                "rewards": [],
+            },
+        }],
+    }))
+    .unwrap()
+}
+
+#[test]
+fn reconcile_reports_unexplained_balance_change() {
+    let config = Config {
+        reconcile: true,
+        ..Default::default()
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+    write_block(block_with_mint_to(), 1, &config, &mut buffer).unwrap();
+
+    let expected = "\
+Latest block: 1
+TX detected: Alice sent 25 USDC to Bob
+Discrepancy detected: Bob balance changed by 45 USDC but transfers account for 25 USDC in sig1
+";
+    assert_eq!(expected, from_utf8(&buffer).unwrap());
+}
+
+#[test]
+fn reconcile_disabled_by_default() {
+    let mut buffer: Vec<u8> = Vec::new();
+    write_block(block_with_mint_to(), 1, &Config::default(), &mut buffer).unwrap();
+
+    let expected = "\
+Latest block: 1
+TX detected: Alice sent 25 USDC to Bob
+";
+    assert_eq!(expected, from_utf8(&buffer).unwrap());
+}
+
+#[test]
+fn verify_decodes_with_the_given_config() {
+    let verification = verify_block(block_with_mint_to(), 1, &Config::default()).unwrap();
+    assert_eq!(vec!["sig1"], verification.usdc_transactions);
+    assert!(verification.without_transfers.is_empty());
+    assert_eq!(1, verification.discrepancies.len());
+
+    // Only USDT transfers are decoded, so the USDC transaction has none
+    let config = Config {
+        mints: vec!["Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB".to_string()],
+        ..Default::default()
+    };
+    let verification = verify_block(block_with_mint_to(), 1, &config).unwrap();
+    assert_eq!(vec!["sig1"], verification.without_transfers);
+}
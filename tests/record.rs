@@ -0,0 +1,63 @@
+use pretty_assertions::assert_eq;
+use solana_transfer_monitor::{
+    config::Config,
+    record::{recorded_slots, replay, Recorder},
+};
+use std::{
+    env, fs,
+    path::PathBuf,
+    str::from_utf8,
+    time::{Duration, Instant},
+};
+
+mod common;
+
+use common::{load_block, watch, MockRpc};
+
+fn record_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn replay_matches_recorded_run() {
+    let dir = record_dir("replay-matches-recorded-run");
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let config = Config {
+        rpc_url: mock_rpc.url.clone(),
+        max_blocks: Some(2),
+        record_dir: Some(dir.clone()),
+        ..Default::default()
+    };
+    let mut run_buffer: Vec<u8> = Vec::new();
+    watch(&config, &mut run_buffer).unwrap();
+    assert_eq!(vec![100, 101], recorded_slots(&dir).unwrap());
+
+    let mut replay_buffer: Vec<u8> = Vec::new();
+    replay(&dir, 0.0, &Config::default(), &mut replay_buffer).unwrap();
+    assert_eq!(
+        from_utf8(&run_buffer).unwrap(),
+        from_utf8(&replay_buffer).unwrap()
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn replay_is_spaced_by_block_time_over_speed() {
+    let dir = record_dir("replay-is-spaced-by-block-time");
+    let recorder = Recorder::new(&dir).unwrap();
+    for (slot, block_time) in [(100, 1_700_000_000), (101, 1_700_000_002)] {
+        let mut block = load_block(slot);
+        block.block_time = Some(block_time);
+        recorder.record(slot, &block).unwrap();
+    }
+
+    let start = Instant::now();
+    let mut buffer: Vec<u8> = Vec::new();
+    replay(&dir, 10.0, &Config::default(), &mut buffer).unwrap();
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,131 @@
+use anyhow::{anyhow, bail};
+use pretty_assertions::assert_eq;
+use solana_rpc_client_api::{
+    client_error::Error as ClientError,
+    request::{RpcError, RpcResponseErrorData},
+};
+use solana_transfer_monitor::{
+    config::Config,
+    retry::{classify, retry, ErrorClass, RetryPolicy},
+};
+use std::{io, str::from_utf8, time::Duration};
+
+mod common;
+
+use common::{load_block_json, watch, MockRpc};
+
+fn rpc_response_error(code: i64) -> anyhow::Error {
+    ClientError::from(RpcError::RpcResponseError {
+        code,
+        message: "error".to_string(),
+        data: RpcResponseErrorData::Empty,
+    })
+    .into()
+}
+
+fn fast_policy(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(4),
+    }
+}
+
+#[test]
+fn classify_errors() {
+    assert_eq!(ErrorClass::Retryable, classify(&rpc_response_error(-32004)));
+    assert_eq!(
+        ErrorClass::SkippedSlot,
+        classify(&rpc_response_error(-32007))
+    );
+    assert_eq!(
+        ErrorClass::SkippedSlot,
+        classify(&rpc_response_error(-32009))
+    );
+    assert_eq!(ErrorClass::Fatal, classify(&rpc_response_error(-32602)));
+    let timeout = ClientError::from(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+    assert_eq!(ErrorClass::Retryable, classify(&timeout.into()));
+    // Context added on the way up does not hide the client error
+    let wrapped = rpc_response_error(-32004).context("getBlock failed on a.example");
+    assert_eq!(ErrorClass::Retryable, classify(&wrapped));
+    assert_eq!(ErrorClass::Fatal, classify(&anyhow!("not an RPC error")));
+}
+
+#[test]
+fn backoff_grows_with_jitter_up_to_max_delay() {
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_secs(1),
+    };
+    for (retry, backoff) in [(1, 100), (2, 200), (3, 400), (4, 800), (5, 1000), (9, 1000)] {
+        let backoff = Duration::from_millis(backoff);
+        let delay = policy.delay(retry);
+        assert!(
+            backoff / 2 <= delay && delay <= backoff,
+            "retry {retry} waited {delay:?}"
+        );
+    }
+}
+
+#[test]
+fn retries_retryable_errors() {
+    let mut attempts = 0;
+    let result = retry(&fast_policy(5), "getBlock", || {
+        attempts += 1;
+        if attempts < 3 {
+            return Err(rpc_response_error(-32004));
+        }
+        Ok(attempts)
+    });
+    assert_eq!(3, result.unwrap());
+}
+
+#[test]
+fn gives_up_after_max_attempts() {
+    let mut attempts = 0;
+    let result: anyhow::Result<()> = retry(&fast_policy(3), "getBlock", || {
+        attempts += 1;
+        Err(rpc_response_error(-32004))
+    });
+    assert!(result.is_err());
+    assert_eq!(3, attempts);
+}
+
+#[test]
+fn does_not_retry_other_errors() {
+    for error in [rpc_response_error(-32007), rpc_response_error(-32602)] {
+        let mut attempts = 0;
+        let mut error = Some(error);
+        let result: anyhow::Result<()> = retry(&fast_policy(5), "getBlock", || {
+            attempts += 1;
+            match error.take() {
+                Some(error) => Err(error),
+                None => bail!("retried"),
+            }
+        });
+        assert!(result.is_err());
+        assert_eq!(1, attempts);
+    }
+}
+
+#[test]
+fn skipped_slot_is_not_written() {
+    let mock_rpc = MockRpc::start_with_blocks(vec![
+        (100, serde_json::Value::Null),
+        (101, load_block_json(101)),
+    ]);
+    let config = Config {
+        rpc_url: mock_rpc.url.clone(),
+        max_blocks: Some(1),
+        ..Default::default()
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+    watch(&config, &mut buffer).unwrap();
+
+    let expected = "\
+Latest block: 101
+TX detected: Carol sent 1,989.48 USDC to Dave
+";
+    assert_eq!(expected, from_utf8(&buffer).unwrap());
+}
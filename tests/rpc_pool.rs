@@ -0,0 +1,132 @@
+use anyhow::bail;
+use pretty_assertions::assert_eq;
+use solana_transfer_monitor::{
+    config::Config,
+    metrics::metrics,
+    rpc_pool::{RpcEndpoint, RpcPool},
+};
+use std::str::from_utf8;
+
+mod common;
+
+use common::{load_block_json, watch, MockRpc};
+
+/// Nothing listens on port 1, so connections are refused immediately
+const DEAD_RPC_URL: &str = "http://127.0.0.1:1";
+
+#[test]
+fn parse_endpoint_weight() {
+    assert_eq!(
+        RpcEndpoint::new("https://a.example/key", 3),
+        RpcEndpoint::parse("https://a.example/key#3").unwrap()
+    );
+    assert_eq!(
+        RpcEndpoint::new("https://a.example", 1),
+        RpcEndpoint::parse("https://a.example").unwrap()
+    );
+    assert!(RpcEndpoint::parse("https://a.example#0").is_err());
+    assert!(RpcEndpoint::parse("https://a.example#x").is_err());
+}
+
+#[test]
+fn weighted_round_robin() {
+    let config = Config {
+        rpc_endpoints: vec![
+            RpcEndpoint::new("http://a.example", 2),
+            RpcEndpoint::new("http://b.example", 1),
+        ],
+        ..Default::default()
+    };
+    let mut pool = RpcPool::new(&config).unwrap();
+    let order = (0..6)
+        .map(|_| pool.call_round_robin("getBlock", |_| Ok(())).unwrap().0)
+        .collect::<Vec<_>>();
+    assert_eq!(vec![0, 1, 0, 0, 1, 0], order);
+}
+
+#[test]
+fn failed_endpoint_is_skipped_until_cooled_down() {
+    let config = Config {
+        rpc_endpoints: vec![
+            RpcEndpoint::new("http://a.example", 1),
+            RpcEndpoint::new("http://b.example", 1),
+        ],
+        ..Default::default()
+    };
+    let mut pool = RpcPool::new(&config).unwrap();
+    let answered = pool
+        .call("getSlot", |client| {
+            if client.url().contains("a.example") {
+                bail!("timed out");
+            }
+            Ok(client.url())
+        })
+        .unwrap();
+    assert_eq!("http://b.example", answered);
+
+    // a.example is cooling down, so round-robin only picks b.example
+    for _ in 0..3 {
+        assert_eq!(1, pool.call_round_robin("getBlock", |_| Ok(())).unwrap().0);
+    }
+
+    let error = pool
+        .call("getSlot", |_| -> anyhow::Result<()> { bail!("timed out") })
+        .unwrap_err();
+    assert!(format!("{error:#}").contains("getSlot failed on a.example"));
+}
+
+#[test]
+fn fails_over_to_working_endpoint() {
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let config = Config {
+        rpc_endpoints: vec![
+            RpcEndpoint::new(DEAD_RPC_URL, 1),
+            RpcEndpoint::new(mock_rpc.url.clone(), 1),
+        ],
+        max_blocks: Some(2),
+        ..Default::default()
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+    watch(&config, &mut buffer).unwrap();
+
+    let expected = "\
+Latest block: 100
+TX detected: Alice sent 25 USDC to Bob
+Latest block: 101
+TX detected: Carol sent 1,989.48 USDC to Dave
+";
+    assert_eq!(expected, from_utf8(&buffer).unwrap());
+    assert_eq!(2, mock_rpc.get_block_calls());
+}
+
+#[test]
+fn quorum_detects_different_transfers() {
+    let honest_rpc = MockRpc::start(&[100]);
+    // Serves block 101's transactions as slot 100
+    let faulty_rpc = MockRpc::start_with_blocks(vec![(100, load_block_json(101))]);
+    let config = Config {
+        rpc_endpoints: vec![
+            RpcEndpoint::new(honest_rpc.url.clone(), 1),
+            RpcEndpoint::new(faulty_rpc.url.clone(), 1),
+        ],
+        quorum: true,
+        max_blocks: Some(1),
+        ..Default::default()
+    };
+    let mismatches = metrics().quorum_mismatches.get();
+    let mut buffer: Vec<u8> = Vec::new();
+    watch(&config, &mut buffer).unwrap();
+
+    assert_eq!(1, honest_rpc.get_block_calls());
+    assert_eq!(1, faulty_rpc.get_block_calls());
+    assert_eq!(mismatches + 1, metrics().quorum_mismatches.get());
+}
+
+#[test]
+fn quorum_needs_two_endpoints() {
+    let config = Config {
+        quorum: true,
+        ..Default::default()
+    };
+    assert!(watch(&config, &mut Vec::new()).is_err());
+}
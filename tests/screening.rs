@@ -0,0 +1,176 @@
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use solana_transfer_monitor::{
+    config::Config,
+    monitor::{Event, Monitor},
+    screening::{Denylist, DenylistEntry},
+    sources::FileBlockSource,
+};
+use std::{env, fs, path::PathBuf, str::from_utf8};
+
+mod common;
+
+use common::{fixture_path, load_block, write_block, MockRpc};
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("{name}-{}", std::process::id()))
+}
+
+fn describe(event: Event) -> Option<String> {
+    match event {
+        Event::Transfer(transfer) => Some(transfer.to_string()),
+        Event::ScreeningHit(hit) => Some(hit.to_string()),
+        _ => None,
+    }
+}
+
+#[test]
+fn denylist_csv_with_or_without_names() {
+    let denylist = Denylist::from_csv(
+        "\
+address,name,program
+# OFAC SDN digital currency addresses
+
+\"Alice\",\"Lazarus Group\",DPRK3
+Bob
+",
+    )
+    .unwrap();
+    assert_eq!(2, denylist.len());
+    assert_eq!(
+        Some(&DenylistEntry {
+            address: "Alice".to_string(),
+            name: "Lazarus Group".to_string(),
+        }),
+        denylist.get("Alice")
+    );
+    assert_eq!("", denylist.get("Bob").unwrap().name);
+    assert!(denylist.get("Carol").is_none());
+
+    let error = Denylist::from_csv("Alice\nnot an address,x").unwrap_err();
+    assert_eq!("on line 2: expected an address", format!("{error:#}"));
+}
+
+#[test]
+fn hits_on_owners_and_token_accounts_are_audited() {
+    let denylist_path = temp_path("denylist.csv");
+    let audit_path = temp_path("screening-audit.jsonl");
+    fs::write(&denylist_path, "Alice,Lazarus Group\nBobUsdc\n").unwrap();
+    let _ = fs::remove_file(&audit_path);
+
+    let monitor = Monitor::builder()
+        .config(Config {
+            denylist_path: Some(denylist_path.clone()),
+            screening_audit_path: Some(audit_path.clone()),
+            ..Config::default()
+        })
+        .source(FileBlockSource::new(&fixture_path(100)).unwrap())
+        // Hits are reported even for transfers the filters drop
+        .filter(|_| false)
+        .build()
+        .unwrap();
+    let events = monitor
+        .filter_map(|event| describe(event.unwrap()))
+        .collect::<Vec<_>>();
+    let audit = fs::read_to_string(&audit_path).unwrap();
+    fs::remove_file(&denylist_path).unwrap();
+    fs::remove_file(&audit_path).unwrap();
+
+    assert_eq!(
+        vec!["DENYLIST HIT: Alice sent 25 USDC to Bob in sig100, sender Alice (Lazarus Group); receiving token account BobUsdc"],
+        events
+    );
+    let mut rows = audit
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<Value>>();
+    assert_eq!(2, rows.len());
+    for row in &mut rows {
+        assert!(row["time"].as_u64().unwrap() > 1_700_000_000);
+        row.as_object_mut().unwrap().remove("time");
+    }
+    assert_eq!("denylistLoaded", rows[0]["type"]);
+    assert_eq!(2, rows[0]["addresses"]);
+    assert_eq!(
+        json!({
+            "type": "screening",
+            "severity": "high",
+            "slot": 100,
+            "signature": "sig100",
+            "source": "Alice",
+            "destination": "Bob",
+            "sourceAccount": "AliceUsdc",
+            "destinationAccount": "BobUsdc",
+            "mint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+            "amount": 25_000_000,
+            "decimals": 6,
+            "matches": [
+                { "party": "sourceOwner", "address": "Alice", "name": "Lazarus Group" },
+                { "party": "destinationAccount", "address": "BobUsdc", "name": "" },
+            ],
+        }),
+        rows[1]
+    );
+}
+
+#[test]
+fn denylist_is_reloaded_when_the_file_changes() {
+    let denylist_path = temp_path("reloaded-denylist.csv");
+    fs::write(&denylist_path, "Nobody\n").unwrap();
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let mut monitor = Monitor::builder()
+        .config(Config {
+            rpc_url: mock_rpc.url.clone(),
+            start_slot: Some(100),
+            max_blocks: Some(2),
+            denylist_path: Some(denylist_path.clone()),
+            ..Config::default()
+        })
+        .build()
+        .unwrap();
+
+    let mut events = Vec::new();
+    for event in monitor.by_ref() {
+        let event = event.unwrap();
+        let end = matches!(event, Event::BlockEnd { .. });
+        events.extend(describe(event));
+        if end {
+            break;
+        }
+    }
+    fs::write(&denylist_path, "Dave,Sanctioned Exchange\n").unwrap();
+    events.extend(monitor.filter_map(|event| describe(event.unwrap())));
+    fs::remove_file(&denylist_path).unwrap();
+
+    assert_eq!(
+        vec![
+            "TX detected: Alice sent 25 USDC to Bob",
+            "TX detected: Carol sent 1,989.48 USDC to Dave",
+            "DENYLIST HIT: Carol sent 1,989.48 USDC to Dave in sig101, receiver Dave (Sanctioned Exchange)",
+        ],
+        events
+    );
+}
+
+#[test]
+fn block_writers_screen_transfers() {
+    let denylist_path = temp_path("block-denylist.csv");
+    fs::write(&denylist_path, "Dave,Sanctioned Exchange\n").unwrap();
+    let config = Config {
+        denylist_path: Some(denylist_path.clone()),
+        ..Config::default()
+    };
+    let mut output = Vec::new();
+    let written = write_block(load_block(101), 101, &config, &mut output);
+    fs::remove_file(&denylist_path).unwrap();
+    written.unwrap();
+
+    assert_eq!(
+        "\
+Latest block: 101
+TX detected: Carol sent 1,989.48 USDC to Dave
+DENYLIST HIT: Carol sent 1,989.48 USDC to Dave in sig101, receiver Dave (Sanctioned Exchange)
+",
+        from_utf8(&output).unwrap()
+    );
+}
@@ -0,0 +1,115 @@
+use pretty_assertions::assert_eq;
+use solana_transfer_monitor::{
+    checkpoint::read_checkpoint,
+    config::Config,
+    monitor::{Event, Monitor},
+    shutdown::Shutdown,
+};
+use std::{
+    env, fs,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+mod common;
+
+use common::MockRpc;
+
+fn checkpoint_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("{name}-{}.json", std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn block_slots(monitor: Monitor, shutdown: Option<&Shutdown>) -> Vec<u64> {
+    let mut slots = Vec::new();
+    for event in monitor {
+        if let Event::BlockEnd { slot } = event.unwrap() {
+            slots.push(slot);
+            if let Some(shutdown) = shutdown {
+                shutdown.request();
+            }
+        }
+    }
+    slots
+}
+
+#[test]
+fn shutdown_checkpoints_and_resumes() {
+    let path = checkpoint_path("shutdown-checkpoints-and-resumes");
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let config = Config {
+        rpc_url: mock_rpc.url.clone(),
+        checkpoint_path: Some(path.clone()),
+        ..Default::default()
+    };
+
+    let shutdown = Shutdown::new();
+    let monitor = Monitor::builder()
+        .config(config.clone())
+        .shutdown(shutdown.clone())
+        .build()
+        .unwrap();
+    assert_eq!(vec![100], block_slots(monitor, Some(&shutdown)));
+    assert_eq!(Some(100), read_checkpoint(&path).unwrap());
+
+    // The mock RPC's slot is still 100, so only the checkpoint moves the start to 101
+    let monitor = Monitor::builder()
+        .config(config)
+        .max_blocks(1)
+        .build()
+        .unwrap();
+    assert_eq!(vec![101], block_slots(monitor, None));
+    assert_eq!(Some(101), read_checkpoint(&path).unwrap());
+}
+
+#[test]
+fn dropping_the_monitor_checkpoints_the_blocks_handled() {
+    let path = checkpoint_path("dropping-the-monitor-checkpoints");
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let config = Config {
+        rpc_url: mock_rpc.url.clone(),
+        checkpoint_path: Some(path.clone()),
+        ..Default::default()
+    };
+
+    let mut monitor = Monitor::builder().config(config).build().unwrap();
+    // A consumer stopping within block 101, e.g. on a sink error
+    for event in monitor.by_ref() {
+        if let Event::BlockStart { slot: 101, .. } = event.unwrap() {
+            break;
+        }
+    }
+    assert_eq!(None, read_checkpoint(&path).unwrap());
+    drop(monitor);
+    assert_eq!(Some(100), read_checkpoint(&path).unwrap());
+}
+
+#[test]
+fn sigterm_flushes_and_exits_cleanly() {
+    let path = checkpoint_path("sigterm-flushes-and-exits-cleanly");
+    let mock_rpc = MockRpc::start(&[100, 101]);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_solana-transfer-monitor"))
+        .args(["--rpc-url", &mock_rpc.url, "--checkpoint"])
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut output = Vec::new();
+    while output.last().map(String::as_str) != Some("TX detected: Carol sent 1,989.48 USDC to Dave")
+    {
+        output.push(lines.next().unwrap().unwrap());
+    }
+
+    // Both blocks are written, the monitor is now polling for the next one
+    let status = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(child.wait().unwrap().success());
+    assert_eq!(None, lines.next().transpose().unwrap());
+    assert_eq!(Some(101), read_checkpoint(&path).unwrap());
+}
@@ -0,0 +1,280 @@
+use anyhow::{bail, Result};
+use pretty_assertions::assert_eq;
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use solana_transfer_monitor::{
+    config::Config,
+    instructions::Transfer,
+    process_block,
+    sinks::{FanOut, JsonlSink, TextSink, TransferSink},
+    sqlite::SqliteSink,
+    swaps::Swap,
+};
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+mod common;
+
+use common::load_block;
+
+/// Writer whose output can still be read after it is moved into a sink
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct FailingSink;
+
+impl TransferSink for FailingSink {
+    fn on_transfer(&mut self, _transfer: &Transfer) -> Result<()> {
+        bail!("database unavailable")
+    }
+}
+
+/// Accepts events but loses them on flush
+struct FailingFlushSink;
+
+impl TransferSink for FailingFlushSink {
+    fn on_transfer(&mut self, _transfer: &Transfer) -> Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        bail!("disk full")
+    }
+}
+
+fn process_fixture_blocks(sink: &mut FanOut) {
+    for slot in [100, 101] {
+        process_block(load_block(slot), slot, &Config::default(), sink).unwrap();
+    }
+}
+
+#[test]
+fn fan_out_writes_text_and_jsonl() {
+    let text = SharedBuffer::default();
+    let jsonl = SharedBuffer::default();
+    let mut sink = FanOut::new()
+        .with_sink("text", TextSink::new(text.clone(), false))
+        .with_sink("jsonl", JsonlSink::new(jsonl.clone()));
+    process_fixture_blocks(&mut sink);
+
+    let expected = "\
+Latest block: 100
+TX detected: Alice sent 25 USDC to Bob
+Latest block: 101
+TX detected: Carol sent 1,989.48 USDC to Dave
+";
+    assert_eq!(expected, text.contents());
+
+    let rows = jsonl
+        .contents()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<Value>>();
+    assert_eq!(2, rows.len());
+    assert_eq!(
+        json!({
+            "type": "transfer",
+            "slot": 100,
+            "signature": "sig100",
+            "source": "Alice",
+            "destination": "Bob",
+            "sourceAccount": "AliceUsdc",
+            "destinationAccount": "BobUsdc",
+            "sourceLabel": null,
+            "destinationLabel": null,
+            "mint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+            "amount": 25_000_000,
+            "decimals": 6,
+            "instructionIndex": 0,
+            "innerInstructionIndex": 0,
+            "callStack": ["JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4"],
+        }),
+        rows[0]
+    );
+}
+
+#[test]
+fn filtered_sink_only_receives_matching_transfers() {
+    let whales = SharedBuffer::default();
+    let mut sink = FanOut::new().with_filtered_sink(
+        "whales",
+        TextSink::new(whales.clone(), false),
+        |transfer| transfer.amount >= 1_000_000_000,
+    );
+    process_fixture_blocks(&mut sink);
+
+    let expected = "\
+Latest block: 100
+Latest block: 101
+TX detected: Carol sent 1,989.48 USDC to Dave
+";
+    assert_eq!(expected, whales.contents());
+}
+
+#[test]
+fn failing_sink_does_not_stop_the_others() {
+    let text = SharedBuffer::default();
+    let mut sink = FanOut::new()
+        .with_sink("database", FailingSink)
+        .with_sink("text", TextSink::new(text.clone(), false));
+    process_fixture_blocks(&mut sink);
+
+    assert_eq!(2, sink.errors("database"));
+    assert_eq!(0, sink.errors("text"));
+    assert_eq!(4, text.contents().lines().count());
+}
+
+#[test]
+fn failed_flush_is_counted_without_stopping_the_monitor() {
+    let mut sink = FanOut::new()
+        .with_sink("file", FailingFlushSink)
+        .with_sink("text", TextSink::new(SharedBuffer::default(), false));
+    process_fixture_blocks(&mut sink);
+
+    sink.flush().unwrap();
+    assert_eq!(1, sink.errors("file"));
+    assert_eq!(0, sink.errors("text"));
+}
+
+#[test]
+fn failed_flush_of_a_required_sink_is_returned_after_flushing_the_others() {
+    let text = SharedBuffer::default();
+    let mut sink = FanOut::new()
+        .with_required_sink("file", FailingFlushSink)
+        .with_sink("text", TextSink::new(text.clone(), false));
+    process_fixture_blocks(&mut sink);
+
+    let error = sink.flush().unwrap_err();
+    assert_eq!("sink file failed: disk full", format!("{error:#}"));
+    assert_eq!(1, sink.errors("file"));
+    assert_eq!(0, sink.errors("text"));
+    assert!(text.contents().contains("Dave"));
+}
+
+#[test]
+fn jsonl_swap_rows_include_decimals() {
+    let jsonl = SharedBuffer::default();
+    let mut sink = JsonlSink::new(jsonl.clone());
+    sink.on_block_start(100, None).unwrap();
+    sink.on_swap(&Swap {
+        signature: "sig100".to_string(),
+        instruction_index: 0,
+        trader: "Trader".to_string(),
+        in_mint: "USDC".to_string(),
+        in_amount: 25_000_000,
+        in_decimals: 6,
+        out_mint: "SOL".to_string(),
+        out_amount: 100_000_000,
+        out_decimals: 9,
+        route: vec!["Raydium".to_string()],
+        legs: vec![0, 1],
+    })
+    .unwrap();
+
+    let row: Value = serde_json::from_str(&jsonl.contents()).unwrap();
+    assert_eq!(
+        json!({
+            "type": "swap",
+            "slot": 100,
+            "signature": "sig100",
+            "trader": "Trader",
+            "inMint": "USDC",
+            "inAmount": 25_000_000,
+            "inDecimals": 6,
+            "outMint": "SOL",
+            "outAmount": 100_000_000,
+            "outDecimals": 9,
+            "route": ["Raydium"],
+        }),
+        row
+    );
+}
+
+fn transfer_rows(sink: &SqliteSink) -> Vec<(i64, String, String, String, String, i64, u8)> {
+    let mut statement = sink
+        .connection()
+        .prepare(
+            "SELECT slot, signature, instruction_path, source, destination, amount, decimals \
+             FROM transfers ORDER BY slot",
+        )
+        .unwrap();
+    statement
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get(6)?,
+            ))
+        })
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn sqlite_sink_inserts_a_row_per_transfer() {
+    let mut sink = SqliteSink::new(Connection::open_in_memory().unwrap()).unwrap();
+    for slot in [100, 101] {
+        process_block(load_block(slot), slot, &Config::default(), &mut sink).unwrap();
+    }
+
+    let row = |slot: i64, signature: &str, source: &str, destination: &str, amount| {
+        (
+            slot,
+            signature.to_string(),
+            "0.0".to_string(),
+            source.to_string(),
+            destination.to_string(),
+            amount,
+            6,
+        )
+    };
+    assert_eq!(
+        vec![
+            row(100, "sig100", "Alice", "Bob", 25_000_000),
+            row(101, "sig101", "Carol", "Dave", 1_989_480_000),
+        ],
+        transfer_rows(&sink)
+    );
+}
+
+#[test]
+fn sqlite_sink_rolls_back_an_unfinished_block() {
+    let mut sink = SqliteSink::new(Connection::open_in_memory().unwrap()).unwrap();
+    sink.on_block_start(99, None).unwrap();
+    sink.on_transfer(&Transfer {
+        signature: "sig99".to_string(),
+        ..Default::default()
+    })
+    .unwrap();
+    process_block(load_block(100), 100, &Config::default(), &mut sink).unwrap();
+
+    let signatures = transfer_rows(&sink)
+        .into_iter()
+        .map(|row| row.1)
+        .collect::<Vec<_>>();
+    assert_eq!(vec!["sig100".to_string()], signatures);
+}
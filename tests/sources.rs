@@ -0,0 +1,105 @@
+use pretty_assertions::assert_eq;
+use solana_transfer_monitor::{
+    config::Config,
+    sources::{BlockSource, FileBlockSource, ReaderBlockSource},
+};
+use std::{env, fs, io::Cursor, str::from_utf8};
+
+mod common;
+
+use common::{fixture_path, load_block_json, write_source};
+
+const EXPECTED: &str = "\
+Latest block: 100
+TX detected: Alice sent 25 USDC to Bob
+Latest block: 101
+TX detected: Carol sent 1,989.48 USDC to Dave
+";
+
+fn source_output(source: impl BlockSource + Send + 'static) -> String {
+    let mut buffer: Vec<u8> = Vec::new();
+    write_source(source, &Config::default(), &mut buffer).unwrap();
+    from_utf8(&buffer).unwrap().to_string()
+}
+
+#[test]
+fn directory_of_json_files_uses_slots_from_file_names() {
+    let dir = env::temp_dir().join(format!("json-blocks-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for slot in [100, 101] {
+        fs::copy(fixture_path(slot), dir.join(format!("{slot}.json"))).unwrap();
+    }
+    fs::write(dir.join("notes.txt"), "not a block").unwrap();
+    let source = FileBlockSource::new(&dir).unwrap();
+    assert_eq!(EXPECTED, source_output(source));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn directory_is_read_in_slot_order_then_name_order() {
+    let dir = env::temp_dir().join(format!("ordered-blocks-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // By name 100.json would come before 99.json
+    fs::copy(fixture_path(100), dir.join("99.json")).unwrap();
+    fs::copy(fixture_path(101), dir.join("block_100.json")).unwrap();
+    let mut unnumbered = load_block_json(100);
+    unnumbered["slot"] = 7.into();
+    fs::write(dir.join("replay.json"), unnumbered.to_string()).unwrap();
+    let source = FileBlockSource::new(&dir).unwrap();
+    assert_eq!(
+        "\
+Latest block: 99
+TX detected: Alice sent 25 USDC to Bob
+Latest block: 100
+TX detected: Carol sent 1,989.48 USDC to Dave
+Latest block: 7
+TX detected: Alice sent 25 USDC to Bob
+",
+        source_output(source)
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn jsonl_stream_uses_slot_fields() {
+    let mut jsonl = String::new();
+    for slot in [100, 101] {
+        let mut block = load_block_json(slot);
+        block["slot"] = slot.into();
+        jsonl.push_str(&block.to_string());
+        jsonl.push('\n');
+    }
+    let source = ReaderBlockSource::new(Cursor::new(jsonl), "jsonl");
+    assert_eq!(EXPECTED, source_output(source));
+}
+
+#[test]
+fn pretty_printed_stream_uses_slot_fields() {
+    let pretty = [100, 101]
+        .map(|slot| {
+            let mut block = load_block_json(slot);
+            block["slot"] = slot.into();
+            serde_json::to_string_pretty(&block).unwrap()
+        })
+        .join("\n");
+    let source = ReaderBlockSource::new(Cursor::new(pretty), "pretty");
+    assert_eq!(EXPECTED, source_output(source));
+}
+
+#[test]
+fn block_without_a_slot_is_an_error() {
+    // The parent slot is not enough, the next slot may have been skipped
+    let block = load_block_json(100).to_string();
+    let mut source = ReaderBlockSource::new(block.as_bytes(), "no slot");
+    let error = source.next_block().unwrap_err();
+    assert_eq!(
+        "block has no slot field and its file is not named by its slot",
+        format!("{error:#}")
+    );
+}
+
+#[test]
+fn invalid_json_is_an_error() {
+    let mut source = ReaderBlockSource::new("{ not json".as_bytes(), "invalid");
+    assert!(source.next_block().is_err());
+}
@@ -0,0 +1,150 @@
+use pretty_assertions::assert_eq;
+use solana_transaction_status::UiConfirmedBlock;
+use solana_transfer_monitor::{
+    config::Config,
+    instructions::Transfer,
+    mints::format_token_amount,
+    swaps::{detect_swaps, Swap},
+};
+use std::str::from_utf8;
+
+mod common;
+
+use common::{mint_token_balance, program_instruction, transfer_instruction, write_block};
+
+const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+const SOL: &str = "So11111111111111111111111111111111111111112";
+const JUP: &str = "JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN";
+const JUPITER_V6: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+const RAYDIUM_AMM_V4: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+const METEORA_DLMM: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+
+// Trader swaps USDC for SOL on Raydium then SOL for JUP on Meteora, routed by Jupiter
+fn jupiter_route_block() -> UiConfirmedBlock {
+    common::jupiter_route_block(
+        &[
+            ("Trader", true),
+            ("TraderUsdc", false),
+            ("TraderJup", false),
+            ("RaydiumUsdc", false),
+            ("RaydiumSol", false),
+            ("JupiterSol", false),
+            ("MeteoraSol", false),
+            ("MeteoraJup", false),
+        ],
+        vec![
+            program_instruction(RAYDIUM_AMM_V4, Some(2)),
+            transfer_instruction("TraderUsdc", "RaydiumUsdc", "25000000"),
+            transfer_instruction("RaydiumSol", "JupiterSol", "500000000"),
+            program_instruction(METEORA_DLMM, Some(2)),
+            transfer_instruction("JupiterSol", "MeteoraSol", "500000000"),
+            transfer_instruction("MeteoraJup", "TraderJup", "100000000"),
+        ],
+        vec![
+            mint_token_balance(1, USDC, 6, "Trader", "0"),
+            mint_token_balance(2, JUP, 6, "Trader", "0"),
+            mint_token_balance(3, USDC, 6, "RaydiumPool", "0"),
+            mint_token_balance(4, SOL, 9, "RaydiumPool", "0"),
+            mint_token_balance(5, SOL, 9, "JupiterAuthority", "0"),
+            mint_token_balance(6, SOL, 9, "MeteoraPool", "0"),
+            mint_token_balance(7, JUP, 6, "MeteoraPool", "0"),
+        ],
+        Vec::new(),
+    )
+}
+
+fn write_route_block(config: &Config) -> String {
+    let mut buffer: Vec<u8> = Vec::new();
+    write_block(jupiter_route_block(), 1, config, &mut buffer).unwrap();
+    from_utf8(&buffer).unwrap().to_string()
+}
+
+#[test]
+fn only_usdc_legs_are_written_by_default() {
+    let expected = "\
+Latest block: 1
+TX detected: Trader sent 25 USDC to RaydiumPool
+";
+    assert_eq!(expected, write_route_block(&Config::default()));
+}
+
+#[test]
+fn routed_swap_is_written_after_its_legs() {
+    let config = Config {
+        detect_swaps: true,
+        ..Default::default()
+    };
+    let expected = "\
+Latest block: 1
+TX detected: Trader sent 25 USDC to RaydiumPool
+Swap detected: Trader swapped 25 USDC for 100 JUP via Raydium AMM v4 > Meteora DLMM
+";
+    assert_eq!(expected, write_route_block(&config));
+}
+
+#[test]
+fn swap_legs_can_be_hidden() {
+    let config = Config {
+        detect_swaps: true,
+        hide_swap_legs: true,
+        ..Default::default()
+    };
+    let expected = "\
+Latest block: 1
+Swap detected: Trader swapped 25 USDC for 100 JUP via Raydium AMM v4 > Meteora DLMM
+";
+    assert_eq!(expected, write_route_block(&config));
+}
+
+fn transfer(source: &str, destination: &str, mint: &str, amount: u64, program: &str) -> Transfer {
+    Transfer {
+        source_owner: source.to_string(),
+        destination_owner: destination.to_string(),
+        amount,
+        mint: mint.to_string(),
+        decimals: 6,
+        call_stack: vec![program.to_string()],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn direct_pool_swap_without_signers_uses_first_sender() {
+    let transfers = [
+        transfer("Trader", "Pool", USDC, 10_000_000, RAYDIUM_AMM_V4),
+        transfer("Pool", "Trader", JUP, 40_000_000, RAYDIUM_AMM_V4),
+    ];
+    let expected = vec![Swap {
+        signature: String::new(),
+        instruction_index: 0,
+        trader: "Trader".to_string(),
+        in_mint: USDC.to_string(),
+        in_amount: 10_000_000,
+        in_decimals: 6,
+        out_mint: JUP.to_string(),
+        out_amount: 40_000_000,
+        out_decimals: 6,
+        route: vec![RAYDIUM_AMM_V4.to_string()],
+        legs: vec![0, 1],
+    }];
+    assert_eq!(expected, detect_swaps(&transfers, &[]));
+}
+
+#[test]
+fn transfers_of_a_single_mint_are_not_swaps() {
+    let transfers = [
+        transfer("Alice", "Router", USDC, 10_000_000, JUPITER_V6),
+        transfer("Router", "Bob", USDC, 10_000_000, JUPITER_V6),
+    ];
+    assert_eq!(Vec::<Swap>::new(), detect_swaps(&transfers, &[]));
+}
+
+#[test]
+fn format_amounts_by_mint_decimals() {
+    assert_eq!("0.5", format_token_amount(500_000_000, 9).unwrap());
+    assert_eq!(
+        "1,234.000001",
+        format_token_amount(1_234_000_001_000, 9).unwrap()
+    );
+    assert_eq!("25", format_token_amount(25_000_000, 6).unwrap());
+}
@@ -1,11 +1,13 @@
 use pretty_assertions::assert_eq;
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::commitment_config::CommitmentConfig;
 use solana_transfer_monitor::utils::get_all_successful_usdc_transactions;
-use solana_transfer_monitor::{instructions::Transfer, make_block_config, write_block_transfers};
+use solana_transfer_monitor::{instructions::Transfer, write_block_transfers, USDC_MINT_ADDRESS};
 use std::io::Write;
 use std::str::from_utf8;
 
+mod common;
+
+use common::load_block;
+
 #[rustfmt::skip]
 const USDC_TRANSFER_FROM_250684537: [(&str, &str, &str); 33] = [
     // https://solscan.io/tx/3Tf9PsFsv3MDmr5UEviSGGkAwDXRbgYpN3vQrnSwgFHXayNk84fr8NwLZWS9qjhaEoXwXAu7SNa7vnY52KNSK7Cz
@@ -60,13 +62,11 @@ const USDC_TRANSFER_FROM_250684537: [(&str, &str, &str); 33] = [
     // https://solscan.io/tx/4VFnM6HKxWVuv88nGPEUFmYQoGdjg9CMYgzF6ZSHqsR6kpQTookEQvE7gv2Vwi272w2WZwrJxg5rLPnQgHS5wvpQ
     ("5ZuR4supLRJ8eQvpqur2pfhNnjuu1guzaLbzeWv4bM7E", "DCAK36VfExkPdAkYUQg6ewgxyinvcEyPLyHjRbmveKFw", "1.0007"),
     ("DCAK36VfExkPdAkYUQg6ewgxyinvcEyPLyHjRbmveKFw", "9nnLbotNTcUhvbrsA6Mdkx45Sm82G35zo28AqUvjExn8", "1.0007"),
-    // ("9nnLbotNTcUhvbrsA6Mdkx45Sm82G35zo28AqUvjExn8", "Raydium Authority V4", "1.0007"),
-    ("9nnLbotNTcUhvbrsA6Mdkx45Sm82G35zo28AqUvjExn8", "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1", "1.0007"),
+    ("9nnLbotNTcUhvbrsA6Mdkx45Sm82G35zo28AqUvjExn8", "Raydium Authority V4", "1.0007"),
 
 
     // https://solscan.io/tx/4LuZTqpLKLKQsGhnG1rG1C35Lj6iZQdopPoqCuhGr6Vm9LC9EEjKZzPeGXFmyYHCZ5cyuDPMQNjz1GSQwViVzpd2
-    // ("Meteora DLMM (SOL-USDC) Pool", "9nnLbotNTcUhvbrsA6Mdkx45Sm82G35zo28AqUvjExn8", "1.3418"),
-    ("FoSDw2L5DmTuQTFe55gWPDXf88euaxAEKFre74CnvQbX", "9nnLbotNTcUhvbrsA6Mdkx45Sm82G35zo28AqUvjExn8", "1.3418"),
+    ("Meteora DLMM (SOL-USDC) Pool", "9nnLbotNTcUhvbrsA6Mdkx45Sm82G35zo28AqUvjExn8", "1.3418"),
     ("9nnLbotNTcUhvbrsA6Mdkx45Sm82G35zo28AqUvjExn8", "3ESUFCnRNgZ7Mn2mPPUMmXYaKU8jpnV9VtA17M7t2mHQ", "1.3418"),
 
     // https://solscan.io/tx/3y9wmVAzokZntkHZbgD51CsLkkwy6BNshwaPKkPqdCnT5qCBqhKLjLVvZu62j1WHX3PNrsHCxVE6VvARv368gfmP
@@ -76,13 +76,11 @@ const USDC_TRANSFER_FROM_250684537: [(&str, &str, &str); 33] = [
     // https://solscan.io/tx/P8x7dYF67nKWAS9Y7saqZA4tkCqkTAJkZqEo3L5ipAuj1eHuCVA7ytMi6YArjS4Ku5g9J9SZTJGArBDXzu55iCc
     ("24EkAyBiM8Lwf7zVDiHshGGRtVGbx9PzF12FEWpWNy7t", "6U91aKa8pmMxkJwBCfPTmUEfZi6dHe7DcFq2ALvB2tbB", "100.39"),
     ("6U91aKa8pmMxkJwBCfPTmUEfZi6dHe7DcFq2ALvB2tbB", "J4uBbeoWpZE8fH58PM1Fp9n9K6f1aThyeVCyRdJbaXqt", "69.27"),
-    // ("6U91aKa8pmMxkJwBCfPTmUEfZi6dHe7DcFq2ALvB2tbB", "Meteora DLMM (USDC-USDT) Pool", "31.12"),
-    ("6U91aKa8pmMxkJwBCfPTmUEfZi6dHe7DcFq2ALvB2tbB", "ARwi1S4DaiTG5DX7S4M4ZsrXqpMD1MrTmbu9ue2tpmEq", "31.12"),
+    ("6U91aKa8pmMxkJwBCfPTmUEfZi6dHe7DcFq2ALvB2tbB", "Meteora DLMM (USDC-USDT) Pool", "31.12"),
 
     // https://solscan.io/tx/4fTwCP9K9hz96FKCGuET96xtgP7VdaJyz37dNGkpdb85p3Cpf4H2EGtGAD48ApoMg43TBUssNthFeFyxc9MDK3rH
     ("5nM1CTQwKXFZo5yJYC8J1pgj32JW6Fx8DpQAtPZ8aiLw", "EXHyQxMSttcvLPwjENnXCPZ8GmLjJYHtNBnAkcFeFKMn", "12"),
-    // ("Meteora DLMM (HNT-USDC) Pool", "5nM1CTQwKXFZo5yJYC8J1pgj32JW6Fx8DpQAtPZ8aiLw", "12"),
-    ("1koYvNEJ5gWXZ6V3re8xwXDHrEpHf4vNYrNGv4bhrqK", "5nM1CTQwKXFZo5yJYC8J1pgj32JW6Fx8DpQAtPZ8aiLw", "12"),
+    ("Meteora DLMM (HNT-USDC) Pool", "5nM1CTQwKXFZo5yJYC8J1pgj32JW6Fx8DpQAtPZ8aiLw", "12"),
 
     // https://solscan.io/tx/2UGj5V8Ry8ggY6scjRy6F1kVs6pnz1Lrtm1WGjXbWYJA8zVszmm3dU8ACu7ZwtKyHS34coqqLw7H3sQVob9Ctc9W
     ("2MNGqr5eStzyBSYMNTDPo47Z5sKqx5LyEbi9tGagDNzo", "6YJWm3nhHXGPvgAHErWcNmqPQtSSHZhvtmE4U9Adwb3g", "172.77"),
@@ -91,15 +89,10 @@ const USDC_TRANSFER_FROM_250684537: [(&str, &str, &str); 33] = [
 
 #[test]
 fn transfers_for_block_250684537() {
-    let rpc_url = "https://api.mainnet-beta.solana.com".to_string();
-    let client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::finalized());
-
     // block slot with a smaller number of USDC transfers: 250684537
     let slot = 250684537;
 
-    let block = client
-        .get_block_with_config(slot, make_block_config())
-        .unwrap();
+    let block = load_block(slot);
 
     let mut buffer: Vec<u8> = Vec::new();
     write_block_transfers(block, slot, &mut buffer).unwrap();
@@ -112,6 +105,9 @@ fn transfers_for_block_250684537() {
             source_owner: tx.0.to_string(),
             destination_owner: tx.1.to_string(),
             formatted_amount: tx.2.to_string(),
+            mint: USDC_MINT_ADDRESS.to_string(),
+            decimals: 6,
+            ..Default::default()
         };
         writeln!(&mut test_cases_buffer, "{transfer}").unwrap();
     }
@@ -145,16 +141,11 @@ const USDC_TRANSACTIONS_FROM_250684537: [&str; 18] = [
 
 #[test]
 fn get_text_search_of_data() {
-    let rpc_url = "https://api.mainnet-beta.solana.com".to_string();
-    let client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::finalized());
-
     let slot = 250684537;
 
-    let block = client
-        .get_block_with_config(slot, make_block_config())
-        .unwrap();
+    let block = load_block(slot);
 
-    let text_search_transactions = get_all_successful_usdc_transactions(block);
+    let text_search_transactions = get_all_successful_usdc_transactions(block).unwrap();
 
     assert_eq!(
         USDC_TRANSACTIONS_FROM_250684537,